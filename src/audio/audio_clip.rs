@@ -1,7 +1,9 @@
+use super::audio_error::{AudioError, AudioResult};
+use super::resample::{resample_frames, ResampleQuality};
 use dasp::frame::Frame;
 use dasp::frame::{Mono, Stereo};
-use dasp::signal;
-use dasp::{interpolate::linear::Linear, Signal};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
 
 pub trait AudioClipTrait {
     type S: dasp::Frame;
@@ -9,6 +11,7 @@ pub trait AudioClipTrait {
     // Constructors
     fn default() -> Self;
     fn with_capacity(capacity: usize) -> Self;
+    fn with_capacity_and_rate(capacity: usize, sample_rate: u32) -> Self;
 
     // Getters
     fn get_frames_ref(&self) -> &[Self::S];
@@ -18,13 +21,32 @@ pub trait AudioClipTrait {
     fn get_length(&self) -> usize;
 
     // Setters
-    fn set_frame(&mut self, idx: usize, val: Self::S);
+    fn set_frame(&mut self, idx: usize, val: Self::S) -> AudioResult<()>;
     fn resample(&self, sample_rate: u32) -> Self
+    where
+        Self: Sized,
+    {
+        self.resample_with_quality(sample_rate, ResampleQuality::default())
+    }
+    fn resample_with_quality(&self, sample_rate: u32, quality: ResampleQuality) -> Self
     where
         Self: Sized;
     fn resize_frames(&mut self, new_size: usize, value: Self::S);
     fn add_padding_left(&mut self, padding_frames: usize);
     fn reset_clip(&mut self);
+
+    // Copies frames from `start` on into `out` and returns how many there were,
+    // 0 past the end. Clips not held in memory decode them here and fail when
+    // that does
+    fn read_frames(&self, start: usize, out: &mut [Self::S]) -> AudioResult<usize> {
+        let frames = self.get_frames_ref();
+        if start >= frames.len() {
+            return Ok(0);
+        }
+        let count = (frames.len() - start).min(out.len());
+        out[..count].copy_from_slice(&frames[start..start + count]);
+        Ok(count)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -41,23 +63,20 @@ where
 
     // Initializer
     fn default() -> Self {
-        let sample_rate = 44100;
-        let length = sample_rate * 5;
-        let frames = vec![F::EQUILIBRIUM; length];
-
-        Self {
-            frames,
-            sample_rate: sample_rate as u32,
-        }
+        let length = DEFAULT_SAMPLE_RATE as usize * 5;
+        Self::with_capacity_and_rate(length, DEFAULT_SAMPLE_RATE)
     }
 
     fn with_capacity(capacity: usize) -> Self {
-        let sample_rate = 44100;
+        Self::with_capacity_and_rate(capacity, DEFAULT_SAMPLE_RATE)
+    }
+
+    fn with_capacity_and_rate(capacity: usize, sample_rate: u32) -> Self {
         let frames = vec![F::EQUILIBRIUM; capacity];
 
         Self {
             frames,
-            sample_rate: sample_rate as u32,
+            sample_rate,
         }
     }
 
@@ -87,9 +106,14 @@ where
     }
 
     // Setters
-    // Todo Should return Results
-    fn set_frame(&mut self, idx: usize, val: Self::S) {
-        self.frames[idx] = val;
+    fn set_frame(&mut self, idx: usize, val: Self::S) -> AudioResult<()> {
+        let len = self.get_length();
+        let frame = self
+            .frames
+            .get_mut(idx)
+            .ok_or(AudioError::FrameOutOfBounds { idx, len })?;
+        *frame = val;
+        Ok(())
     }
 
     fn resize_frames(&mut self, new_size: usize, value: Self::S) {
@@ -105,19 +129,12 @@ where
         self.frames = padding;
     }
 
-    fn resample(&self, sample_rate: u32) -> Self {
+    fn resample_with_quality(&self, sample_rate: u32, quality: ResampleQuality) -> Self {
         if self.sample_rate == sample_rate {
             return self.clone();
         }
 
-        let mut signal = signal::from_iter(self.frames.iter().copied());
-        let a = signal.next();
-        let b = signal.next();
-        let linear = Linear::new(a, b);
-        let frames: Vec<F> = signal
-            .from_hz_to_hz(linear, self.sample_rate as f64, sample_rate as f64)
-            .take(self.frames.len() * (sample_rate as usize) / self.sample_rate as usize)
-            .collect();
+        let frames = resample_frames(&self.frames, self.sample_rate, sample_rate, quality);
 
         Self {
             frames,
@@ -132,15 +149,148 @@ where
     }
 }
 
-impl AudioClip<[f32; 1]> {
-    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
-        let frames: Vec<[f32; 1]> = samples.into_iter().map(|sample| [sample]).collect();
-        Self {
+pub type Quad<S> = [S; 4];
+pub type Surround51<S> = [S; 6];
+pub type Surround71<S> = [S; 8];
+
+// Channel orders follow WAV/SMPTE:
+// Quad: FL FR BL BR
+// 5.1:  FL FR FC LFE SL SR
+// 7.1:  FL FR FC LFE BL BR SL SR
+const ATT: f32 = std::f32::consts::FRAC_1_SQRT_2; // -3 dB
+
+// ITU-R BS.775 downmixes, the LFE channel is dropped
+pub const QUAD_TO_STEREO: [[f32; 4]; 2] = [[1.0, 0.0, ATT, 0.0], [0.0, 1.0, 0.0, ATT]];
+pub const SURROUND51_TO_STEREO: [[f32; 6]; 2] = [
+    [1.0, 0.0, ATT, 0.0, ATT, 0.0],
+    [0.0, 1.0, ATT, 0.0, 0.0, ATT],
+];
+pub const SURROUND71_TO_STEREO: [[f32; 8]; 2] = [
+    [1.0, 0.0, ATT, 0.0, ATT, 0.0, ATT, 0.0],
+    [0.0, 1.0, ATT, 0.0, 0.0, ATT, 0.0, ATT],
+];
+pub const SURROUND51_TO_QUAD: [[f32; 6]; 4] = [
+    [1.0, 0.0, ATT, 0.0, 0.0, 0.0],
+    [0.0, 1.0, ATT, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+];
+pub const SURROUND71_TO_QUAD: [[f32; 8]; 4] = [
+    [1.0, 0.0, ATT, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, ATT, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, ATT, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, ATT],
+];
+pub const SURROUND71_TO_SURROUND51: [[f32; 8]; 6] = [
+    [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, ATT, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, ATT, 0.0, 1.0],
+];
+
+// Upmixes only route existing channels, nothing is synthesized for the surrounds.
+// Mono goes to the front pair at -3 dB per side, keeping its power, on every
+// layout alike since quad has no center
+pub const MONO_TO_QUAD: [[f32; 1]; 4] = [[ATT], [ATT], [0.0], [0.0]];
+pub const MONO_TO_SURROUND51: [[f32; 1]; 6] = [[ATT], [ATT], [0.0], [0.0], [0.0], [0.0]];
+pub const MONO_TO_SURROUND71: [[f32; 1]; 8] =
+    [[ATT], [ATT], [0.0], [0.0], [0.0], [0.0], [0.0], [0.0]];
+pub const STEREO_TO_QUAD: [[f32; 2]; 4] = [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0], [0.0, 0.0]];
+pub const STEREO_TO_SURROUND51: [[f32; 2]; 6] = [
+    [1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+];
+pub const STEREO_TO_SURROUND71: [[f32; 2]; 8] = [
+    [1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 0.0],
+];
+pub const QUAD_TO_SURROUND51: [[f32; 4]; 6] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+pub const QUAD_TO_SURROUND71: [[f32; 4]; 8] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+    [0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0],
+];
+pub const SURROUND51_TO_SURROUND71: [[f32; 6]; 8] = [
+    [1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+];
+
+impl<const N: usize> AudioClip<[f32; N]> {
+    // Interleaved samples, a partial frame at the end is an error
+    pub fn new(samples: Vec<f32>, sample_rate: u32) -> AudioResult<Self> {
+        if !samples.len().is_multiple_of(N) {
+            return Err(AudioError::PartialFrame {
+                samples: samples.len(),
+                channels: N as u32,
+            });
+        }
+        let frames: Vec<[f32; N]> = samples
+            .chunks_exact(N)
+            .map(|chunk| {
+                let mut frame = [0.0; N];
+                frame.copy_from_slice(chunk);
+                frame
+            })
+            .collect();
+
+        Ok(Self {
             frames,
             sample_rate,
+        })
+    }
+
+    // Each output channel is the weighted sum of the input channels in its matrix row
+    pub fn remix<const M: usize>(&self, matrix: &[[f32; N]; M]) -> AudioClip<[f32; M]> {
+        let frames: Vec<[f32; M]> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let mut out = [0.0; M];
+                for (sample, row) in out.iter_mut().zip(matrix.iter()) {
+                    *sample = frame.iter().zip(row.iter()).map(|(s, w)| s * w).sum();
+                }
+                out
+            })
+            .collect();
+
+        AudioClip {
+            frames,
+            sample_rate: self.sample_rate,
         }
     }
+}
 
+impl AudioClip<[f32; 1]> {
     pub fn to_stereo(&self) -> AudioClip<[f32; 2]> {
         let stereo_frames: Vec<[f32; 2]> =
             self.frames.iter().map(|mono| [mono[0], mono[0]]).collect();
@@ -152,18 +302,6 @@ impl AudioClip<[f32; 1]> {
 }
 
 impl AudioClip<[f32; 2]> {
-    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
-        let frames: Vec<[f32; 2]> = samples
-            .chunks_exact(2)
-            .map(|chunk| [chunk[0], chunk[1]])
-            .collect();
-
-        Self {
-            frames,
-            sample_rate,
-        }
-    }
-
     pub fn to_mono(&self) -> AudioClip<[f32; 1]> {
         let mono_frames: Vec<[f32; 1]> = self
             .frames
@@ -181,22 +319,82 @@ impl AudioClip<[f32; 2]> {
 pub enum AudioClipEnum {
     Mono(AudioClip<Mono<f32>>),
     Stereo(AudioClip<Stereo<f32>>),
+    Quad(AudioClip<Quad<f32>>),
+    Surround51(AudioClip<Surround51<f32>>),
+    Surround71(AudioClip<Surround71<f32>>),
 }
 
 impl AudioClipEnum {
-    pub fn from_samples(samples: Vec<f32>, sample_rate: u32, channels: u32) -> Self {
+    pub fn from_samples(samples: Vec<f32>, sample_rate: u32, channels: u32) -> AudioResult<Self> {
         match channels {
-            1 => Self::Mono(AudioClip::<Mono<f32>>::new(samples, sample_rate)),
-            2 => Self::Stereo(AudioClip::<Stereo<f32>>::new(samples, sample_rate)),
-            _ => panic!("Invalid number of channels"),
+            1 => Ok(Self::Mono(AudioClip::new(samples, sample_rate)?)),
+            2 => Ok(Self::Stereo(AudioClip::new(samples, sample_rate)?)),
+            4 => Ok(Self::Quad(AudioClip::new(samples, sample_rate)?)),
+            6 => Ok(Self::Surround51(AudioClip::new(samples, sample_rate)?)),
+            8 => Ok(Self::Surround71(AudioClip::new(samples, sample_rate)?)),
+            _ => Err(AudioError::UnsupportedChannelCount(channels)),
         }
     }
 
     pub fn default() -> Self {
-        let sample_rate = 44100;
-        let samples = Vec::<f32>::with_capacity(sample_rate * 5);
-        let clip = AudioClip::<Stereo<f32>>::new(samples, sample_rate as u32);
-        AudioClipEnum::Stereo(clip)
+        AudioClipEnum::Stereo(AudioClip::with_capacity_and_rate(0, DEFAULT_SAMPLE_RATE))
+    }
+
+    pub fn get_channel_count(&self) -> u32 {
+        match self {
+            AudioClipEnum::Mono(_) => 1,
+            AudioClipEnum::Stereo(_) => 2,
+            AudioClipEnum::Quad(_) => 4,
+            AudioClipEnum::Surround51(_) => 6,
+            AudioClipEnum::Surround71(_) => 8,
+        }
+    }
+
+    pub fn into_mono(self) -> AudioClip<Mono<f32>> {
+        match self {
+            AudioClipEnum::Mono(clip) => clip,
+            other => other.into_stereo().to_mono(),
+        }
+    }
+
+    pub fn into_stereo(self) -> AudioClip<Stereo<f32>> {
+        match self {
+            AudioClipEnum::Mono(clip) => clip.to_stereo(),
+            AudioClipEnum::Stereo(clip) => clip,
+            AudioClipEnum::Quad(clip) => clip.remix(&QUAD_TO_STEREO),
+            AudioClipEnum::Surround51(clip) => clip.remix(&SURROUND51_TO_STEREO),
+            AudioClipEnum::Surround71(clip) => clip.remix(&SURROUND71_TO_STEREO),
+        }
+    }
+
+    pub fn into_quad(self) -> AudioClip<Quad<f32>> {
+        match self {
+            AudioClipEnum::Mono(clip) => clip.remix(&MONO_TO_QUAD),
+            AudioClipEnum::Stereo(clip) => clip.remix(&STEREO_TO_QUAD),
+            AudioClipEnum::Quad(clip) => clip,
+            AudioClipEnum::Surround51(clip) => clip.remix(&SURROUND51_TO_QUAD),
+            AudioClipEnum::Surround71(clip) => clip.remix(&SURROUND71_TO_QUAD),
+        }
+    }
+
+    pub fn into_surround51(self) -> AudioClip<Surround51<f32>> {
+        match self {
+            AudioClipEnum::Mono(clip) => clip.remix(&MONO_TO_SURROUND51),
+            AudioClipEnum::Stereo(clip) => clip.remix(&STEREO_TO_SURROUND51),
+            AudioClipEnum::Quad(clip) => clip.remix(&QUAD_TO_SURROUND51),
+            AudioClipEnum::Surround51(clip) => clip,
+            AudioClipEnum::Surround71(clip) => clip.remix(&SURROUND71_TO_SURROUND51),
+        }
+    }
+
+    pub fn into_surround71(self) -> AudioClip<Surround71<f32>> {
+        match self {
+            AudioClipEnum::Mono(clip) => clip.remix(&MONO_TO_SURROUND71),
+            AudioClipEnum::Stereo(clip) => clip.remix(&STEREO_TO_SURROUND71),
+            AudioClipEnum::Quad(clip) => clip.remix(&QUAD_TO_SURROUND71),
+            AudioClipEnum::Surround51(clip) => clip.remix(&SURROUND51_TO_SURROUND71),
+            AudioClipEnum::Surround71(clip) => clip,
+        }
     }
 }
 
@@ -277,4 +475,104 @@ mod tests {
         let sample = input_clip.get_frame(2).unwrap();
         assert_eq!(sample, [2.0, 2.0]);
     }
+
+    #[test]
+    fn test_set_frame_out_of_bounds() {
+        let mut input_clip = AudioClip {
+            frames: vec![[0.0]; 2],
+            sample_rate: 44100,
+        };
+        assert!(input_clip.set_frame(1, [1.0]).is_ok());
+        assert!(matches!(
+            input_clip.set_frame(2, [1.0]),
+            Err(AudioError::FrameOutOfBounds { idx: 2, len: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_from_samples_multichannel() {
+        let clip =
+            AudioClipEnum::from_samples((0..12).map(|x| x as f32).collect(), 48000, 6).unwrap();
+        assert_eq!(clip.get_channel_count(), 6);
+        match clip {
+            AudioClipEnum::Surround51(clip) => {
+                assert_eq!(clip.get_length(), 2);
+                assert_eq!(clip.get_frame(1).unwrap(), [6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+            }
+            _ => panic!("expected a 5.1 clip"),
+        }
+    }
+
+    #[test]
+    fn test_surround51_downmix() {
+        // FL FR FC LFE SL SR
+        let clip =
+            AudioClip::<Surround51<f32>>::new(vec![1.0, 0.0, 1.0, 1.0, 0.0, 1.0], 44100).unwrap();
+
+        let stereo = AudioClipEnum::Surround51(clip.clone()).into_stereo();
+        let [left, right] = stereo.get_frame(0).unwrap();
+        assert!((left - (1.0 + ATT)).abs() < 1e-6);
+        assert!((right - 2.0 * ATT).abs() < 1e-6);
+
+        let mono = AudioClipEnum::Surround51(clip).into_mono();
+        assert!((mono.get_frame(0).unwrap()[0] - (left + right) / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_quad_and_surround71_downmix() {
+        // FL FR BL BR
+        let quad = AudioClip::<Quad<f32>>::new(vec![0.0, 1.0, 0.0, 1.0], 44100).unwrap();
+        let stereo = AudioClipEnum::Quad(quad).into_stereo();
+        assert_eq!(stereo.get_frame(0).unwrap(), [0.0, 1.0 + ATT]);
+
+        // FL FR FC LFE BL BR SL SR, LFE is dropped
+        let surround =
+            AudioClip::<Surround71<f32>>::new(vec![0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0], 44100)
+                .unwrap();
+        let stereo = AudioClipEnum::Surround71(surround).into_stereo();
+        assert_eq!(stereo.get_frame(0).unwrap(), [2.0 * ATT, 0.0]);
+    }
+
+    #[test]
+    fn test_upmix_from_mono_and_stereo() {
+        let mono = AudioClip::<Mono<f32>>::new(vec![0.5], 44100).unwrap();
+        let surround = AudioClipEnum::Mono(mono.clone()).into_surround51();
+        assert_eq!(
+            surround.get_frame(0).unwrap(),
+            [0.5 * ATT, 0.5 * ATT, 0.0, 0.0, 0.0, 0.0]
+        );
+        // Same level on every layout, and the power of the mono signal
+        let quad = AudioClipEnum::Mono(mono).into_quad();
+        assert_eq!(quad.get_frame(0).unwrap(), [0.5 * ATT, 0.5 * ATT, 0.0, 0.0]);
+        let [left, right, ..] = quad.get_frame(0).unwrap();
+        assert!((left * left + right * right - 0.25).abs() < 1e-6);
+
+        let stereo = AudioClip::<Stereo<f32>>::new(vec![0.25, 0.75], 44100).unwrap();
+        let surround = AudioClipEnum::Stereo(stereo).into_surround71();
+        assert_eq!(
+            surround.get_frame(0).unwrap(),
+            [0.25, 0.75, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_partial_trailing_frame_is_rejected() {
+        assert!(matches!(
+            AudioClip::<Stereo<f32>>::new(vec![0.0; 3], 44100),
+            Err(AudioError::PartialFrame {
+                samples: 3,
+                channels: 2
+            })
+        ));
+        assert!(AudioClipEnum::from_samples(vec![0.0; 7], 44100, 6).is_err());
+    }
+
+    #[test]
+    fn test_from_samples_unsupported_channels() {
+        let result = AudioClipEnum::from_samples(vec![0.0; 9], 44100, 3);
+        assert!(matches!(
+            result,
+            Err(AudioError::UnsupportedChannelCount(3))
+        ));
+    }
 }
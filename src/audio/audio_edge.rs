@@ -1,23 +1,53 @@
-use super::audio_clip::AudioClip;
 use super::audio_node::AudioNode;
 use super::audio_processor::AudioProcessor;
+use super::session::SessionOperation;
 use crate::audio::audio_clip::AudioClipTrait;
 use dasp::Frame;
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::sync::MutexGuard;
 
 // Define a trait for audio operations
 pub trait AudioOperation<F>: Send {
     fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>);
-}
+    // Undo what `apply` contributed to the child, used when the edge is removed
+    fn revert(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>);
+
+    // Copy for an edge duplicated onto another parent, e.g. when splitting a node
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>>;
+
+    // Non-linear operations cannot be applied as deltas or reverted, so the
+    // processor rebuilds their child from all of its inputs instead
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    // Frames of the parent on either side of a block that `apply` has to see
+    // to give the same result as on the whole overlap. Lazy rendering works
+    // block by block, so position-dependent operations need some context
+    fn block_context(&self) -> usize {
+        0
+    }
 
-// Separate Linear and Non-Linear operations
-pub trait LinearOperation<F>: AudioOperation<F> {}
-pub trait NonLinearOperation<F>: AudioOperation<F> {}
+    // Lets the processor reach the parameters of mixing edges behind the trait object
+    fn as_mix(&self) -> Option<&MixOperation> {
+        None
+    }
+
+    fn as_mix_mut(&mut self) -> Option<&mut MixOperation> {
+        None
+    }
+
+    // How the edge is written to a session file, `None` if it cannot be
+    fn to_session(&self) -> Option<SessionOperation> {
+        None
+    }
+}
 
 pub struct AudioGraphEdge<F> {
     pub operation: Box<dyn AudioOperation<F>>,
     description: &'static str,
+    // Frames the parent is shifted by on the child's timeline
+    offset: i64,
 }
 
 impl<F> fmt::Display for AudioGraphEdge<F> {
@@ -31,12 +61,47 @@ impl<F> AudioGraphEdge<F> {
         AudioGraphEdge {
             operation: Box::new(operation),
             description,
+            offset: 0,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: i64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn get_offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub(crate) fn set_offset(&mut self, offset: i64) {
+        self.offset = offset;
+    }
+
+    pub fn duplicate(&self) -> Self {
+        AudioGraphEdge {
+            operation: self.operation.clone_box(),
+            description: self.description,
+            offset: self.offset,
         }
     }
 
     pub fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
         self.operation.apply(parent_node, child_node)
     }
+
+    pub fn revert(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        self.operation.revert(parent_node, child_node)
+    }
+}
+
+impl<F: Frame<Sample = f32> + Copy> AudioGraphEdge<F> {
+    // The parent as this edge places it, sharing the parent's buffers. A
+    // placement before the start of the timeline is clamped to frame 0
+    pub fn place_parent(&self, parent_node: &AudioNode<F>) -> AudioNode<F> {
+        let start = (parent_node.get_clip_start() as i64 + self.offset).max(0);
+        parent_node.view_at(start as usize)
+    }
 }
 
 impl<F> fmt::Debug for AudioGraphEdge<F> {
@@ -47,25 +112,541 @@ impl<F> fmt::Debug for AudioGraphEdge<F> {
 
 #[derive(Clone)]
 pub struct AddOperation;
-impl<F: Frame<Sample = f32> + Default + Copy> LinearOperation<F> for AddOperation {}
 impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for AddOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Add)
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        AddOperation::mix(parent_node, child_node, 1.0);
+    }
+
+    fn revert(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        AddOperation::mix(parent_node, child_node, -1.0);
+    }
+}
+
+// Phase-cancel comparison: whatever the two takes share drops out of the child
+#[derive(Clone)]
+pub struct SubtractOperation;
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for SubtractOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Subtract)
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        AddOperation::mix(parent_node, child_node, -1.0);
+    }
+
+    fn revert(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        AddOperation::mix(parent_node, child_node, 1.0);
+    }
+}
+
+impl AddOperation {
+    fn mix<F: Frame<Sample = f32> + Copy>(
+        parent_node: &AudioNode<F>,
+        child_node: &AudioNode<F>,
+        sign: f32,
+    ) {
+        mix_scaled(parent_node, child_node, F::from_fn(|_| sign));
+    }
+}
+
+// Adds the parent's output to the child over the delta range, each channel
+// scaled by the matching channel of `gains`
+fn mix_scaled<F: Frame<Sample = f32> + Copy>(
+    parent_node: &AudioNode<F>,
+    child_node: &AudioNode<F>,
+    gains: F,
+) {
+    let child_start = child_node.get_clip_start();
+    let parent_start = parent_node.get_clip_start();
+    let (overlap_start, overlap_end) = child_node.get_absolute_delta_range().unwrap();
+
+    parent_node.with_output(|parent_clip| {
+        child_node.with_buffers_mut(|child_buffers| {
+            let parent_samples: &[F] = parent_clip.get_frames_ref();
+            let child_samples: &mut [F] = child_buffers.clip_mut().get_frames_mut();
+
+            for i in overlap_start..overlap_end {
+                let parent_index = i - parent_start;
+                let child_index = i - child_start;
+
+                child_samples[child_index] =
+                    child_samples[child_index].add_amp(parent_samples[parent_index].mul_amp(gains));
+            }
+        })
+    });
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MixParams {
+    pub gain_db: f32,
+    // -1.0 is hard left, 1.0 hard right. Only affects stereo frames
+    pub pan: f32,
+    pub mute: bool,
+    pub solo: bool,
+}
+
+impl Default for MixParams {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.0,
+            pan: 0.0,
+            mute: false,
+            solo: false,
+        }
+    }
+}
+
+// Mixing edge with per-connection gain, pan and mute/solo. Solo is scoped to
+// the child: while any mix edge into it is soloed, the others are silenced
+#[derive(Clone, Debug, Default)]
+pub struct MixOperation {
+    params: MixParams,
+    solo_silenced: bool,
+}
+
+impl MixOperation {
+    pub fn new(params: MixParams) -> Self {
+        Self {
+            params,
+            solo_silenced: false,
+        }
+    }
+
+    pub fn get_params(&self) -> MixParams {
+        self.params
+    }
+
+    pub(crate) fn set_params(&mut self, params: MixParams) {
+        self.params = params;
+    }
+
+    pub fn is_solo_silenced(&self) -> bool {
+        self.solo_silenced
+    }
+
+    pub(crate) fn set_solo_silenced(&mut self, silenced: bool) {
+        self.solo_silenced = silenced;
+    }
+
+    // Constant-power (sin/cos) pan law, -3 dB per side at the center
+    fn channel_gains<F: Frame<Sample = f32>>(&self) -> F {
+        if self.params.mute || self.solo_silenced {
+            return F::EQUILIBRIUM;
+        }
+        let gain = 10f32.powf(self.params.gain_db / 20.0);
+        if F::CHANNELS != 2 {
+            return F::from_fn(|_| gain);
+        }
+        let angle = (self.params.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        F::from_fn(|channel| match channel {
+            0 => gain * angle.cos(),
+            _ => gain * angle.sin(),
+        })
+    }
+}
+
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for MixOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Mix {
+            params: self.params,
+        })
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        mix_scaled(parent_node, child_node, self.channel_gains::<F>());
+    }
+
+    fn revert(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        let gains: F = self.channel_gains();
+        mix_scaled(parent_node, child_node, gains.scale_amp(-1.0));
+    }
+
+    fn as_mix(&self) -> Option<&MixOperation> {
+        Some(self)
+    }
+
+    fn as_mix_mut(&mut self) -> Option<&mut MixOperation> {
+        Some(self)
+    }
+}
+
+// Applies `combine(child, parent)` to every child frame overlapping the parent
+fn combine_overlap<F: Frame<Sample = f32> + Copy>(
+    parent_node: &AudioNode<F>,
+    child_node: &AudioNode<F>,
+    mut combine: impl FnMut(F, F) -> F,
+) {
+    let child_start = child_node.get_clip_start();
+    let parent_start = parent_node.get_clip_start();
+    let (overlap_start, overlap_end) = child_node.get_absolute_delta_range().unwrap();
+
+    parent_node.with_output(|parent_clip| {
+        child_node.with_buffers_mut(|child_buffers| {
+            let parent_samples: &[F] = parent_clip.get_frames_ref();
+            let child_samples: &mut [F] = child_buffers.clip_mut().get_frames_mut();
+
+            for i in overlap_start..overlap_end {
+                let child_index = i - child_start;
+                child_samples[child_index] =
+                    combine(child_samples[child_index], parent_samples[i - parent_start]);
+            }
+        })
+    });
+}
+
+// Ring modulation: the child is multiplied by the parent where they overlap
+// and left untouched elsewhere. A parent holding gain values works as an
+// amplitude envelope for the child
+#[derive(Clone)]
+pub struct MultiplyOperation;
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for MultiplyOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Multiply)
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        combine_overlap(parent_node, child_node, |child, parent| {
+            child.mul_amp(parent)
+        });
+    }
+
+    // Not invertible, the processor rebuilds the child without this edge
+    fn revert(&self, _parent_node: &AudioNode<F>, _child_node: &AudioNode<F>) {}
+
+    fn is_linear(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone)]
+pub struct MinOperation;
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for MinOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Min)
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        combine_overlap(parent_node, child_node, |child, parent| {
+            child.zip_map(parent, f32::min)
+        });
+    }
+
+    fn revert(&self, _parent_node: &AudioNode<F>, _child_node: &AudioNode<F>) {}
+
+    fn is_linear(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone)]
+pub struct MaxOperation;
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for MaxOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Max)
+    }
+
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        combine_overlap(parent_node, child_node, |child, parent| {
+            child.zip_map(parent, f32::max)
+        });
+    }
+
+    fn revert(&self, _parent_node: &AudioNode<F>, _child_node: &AudioNode<F>) {}
+
+    fn is_linear(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossfadeCurve {
+    #[default]
+    Linear,
+    // Constant power (sin/cos), no dip in loudness for uncorrelated material
+    EqualPower,
+    // Raised cosine, eases in and out of the fade
+    SCurve,
+}
+
+impl CrossfadeCurve {
+    // Gains for the outgoing and incoming clip at `position` from 0.0 to 1.0
+    pub fn gains(&self, position: f32) -> (f32, f32) {
+        match self {
+            CrossfadeCurve::Linear => (1.0 - position, position),
+            CrossfadeCurve::EqualPower => {
+                let angle = position * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            CrossfadeCurve::SCurve => {
+                let fade_in = 0.5 - 0.5 * (position * std::f32::consts::PI).cos();
+                (1.0 - fade_in, fade_in)
+            }
+        }
+    }
+}
+
+// The child fades out while the parent fades in over the first `length`
+// frames of their overlap, after which the parent replaces the child until
+// the overlap ends. `length` is clamped to the overlap
+#[derive(Clone, Debug)]
+pub struct CrossfadeOperation {
+    pub length: usize,
+    pub curve: CrossfadeCurve,
+}
+
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for CrossfadeOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Crossfade {
+            length: self.length,
+            curve: self.curve,
+        })
+    }
+
     fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
-        let child_start = child_node.get_clip_start();
-        let parent_start = parent_node.get_clip_start();
         let (overlap_start, overlap_end) = child_node.get_absolute_delta_range().unwrap();
+        let length = self.length.min(overlap_end - overlap_start);
+        let mut position = 0;
+
+        combine_overlap(parent_node, child_node, |child, parent| {
+            let (fade_out, fade_in) = if position < length {
+                self.curve.gains(position as f32 / length as f32)
+            } else {
+                (0.0, 1.0)
+            };
+            position += 1;
+            child.scale_amp(fade_out).add_amp(parent.scale_amp(fade_in))
+        });
+    }
+
+    fn revert(&self, _parent_node: &AudioNode<F>, _child_node: &AudioNode<F>) {}
+
+    fn is_linear(&self) -> bool {
+        false
+    }
+
+    // Enough to tell whether a block lies inside the fade
+    fn block_context(&self) -> usize {
+        self.length
+    }
+}
+
+// Sidechain ducking: the parent's peak envelope turns the child down by up to
+// `depth` (0.0 to 1.0). Attack and release are time constants in frames
+#[derive(Clone, Debug)]
+pub struct DuckOperation {
+    pub depth: f32,
+    pub attack: usize,
+    pub release: usize,
+}
+
+impl DuckOperation {
+    fn coefficient(frames: usize) -> f32 {
+        if frames == 0 {
+            1.0
+        } else {
+            1.0 - (-1.0 / frames as f32).exp()
+        }
+    }
+}
+
+impl<F: Frame<Sample = f32> + Default + Copy> AudioOperation<F> for DuckOperation {
+    fn clone_box(&self) -> Box<dyn AudioOperation<F>> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionOperation> {
+        Some(SessionOperation::Duck {
+            depth: self.depth,
+            attack: self.attack,
+            release: self.release,
+        })
+    }
 
-        let parent_clip: MutexGuard<'_, AudioClip<F>> = parent_node.get_clip();
-        let mut child_clip: MutexGuard<'_, AudioClip<F>> = child_node.get_clip();
+    fn apply(&self, parent_node: &AudioNode<F>, child_node: &AudioNode<F>) {
+        let attack = DuckOperation::coefficient(self.attack);
+        let release = DuckOperation::coefficient(self.release);
+        let depth = self.depth.clamp(0.0, 1.0);
+        let mut envelope = 0.0f32;
+
+        combine_overlap(parent_node, child_node, |child, parent| {
+            let peak = parent
+                .channels()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let coefficient = if peak > envelope { attack } else { release };
+            envelope += (peak - envelope) * coefficient;
+            child.scale_amp(1.0 - depth * envelope.min(1.0))
+        });
+    }
+
+    fn revert(&self, _parent_node: &AudioNode<F>, _child_node: &AudioNode<F>) {}
+
+    fn is_linear(&self) -> bool {
+        false
+    }
 
-        let parent_samples: &[F] = parent_clip.get_frames_ref();
-        let child_samples: &mut [F] = child_clip.get_frames_mut();
+    // The envelope forgets where it started within ten time constants, to
+    // under 1e-4 of full scale
+    fn block_context(&self) -> usize {
+        10 * self.attack.max(self.release)
+    }
+}
+
+// ! ---------  Tests ---------
 
-        for i in overlap_start..overlap_end {
-            let parent_index = i - parent_start;
-            let child_index = i - child_start;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_clip::AudioClip;
+    use dasp::frame::{Mono, Stereo};
 
-            child_samples[child_index] =
-                (child_samples[child_index].add_amp(parent_samples[parent_index])).into();
+    fn overlapping_nodes<F>(
+        parent: AudioClip<F>,
+        child: AudioClip<F>,
+    ) -> (AudioNode<F>, AudioNode<F>)
+    where
+        F: Frame<Sample = f32> + Copy,
+    {
+        let parent = AudioNode::new(parent, Some("parent"));
+        let mut child = AudioNode::new(child, Some("child"));
+        child.normalize_clip_bounds(&parent);
+        (parent, child)
+    }
+
+    #[test]
+    fn test_multiply_only_touches_overlap() {
+        let (mut parent, mut child) = overlapping_nodes(
+            AudioClip::<Mono<f32>>::new(vec![2.0, -1.0], 44100).unwrap(),
+            AudioClip::<Mono<f32>>::new(vec![1.0, 2.0, 3.0, 4.0], 44100).unwrap(),
+        );
+        parent.set_clip_start(1);
+        child.normalize_clip_bounds(&parent);
+        MultiplyOperation.apply(&parent, &child);
+        assert_eq!(
+            child.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[1.0], [4.0], [-3.0], [4.0]]
+        );
+    }
+
+    #[test]
+    fn test_min_max_per_channel() {
+        let parent = AudioClip::<Stereo<f32>>::new(vec![0.5, -0.5], 44100).unwrap();
+        let child = AudioClip::<Stereo<f32>>::new(vec![0.0, 0.0], 44100).unwrap();
+
+        let (parent_node, child_node) = overlapping_nodes(parent.clone(), child.clone());
+        MinOperation.apply(&parent_node, &child_node);
+        assert_eq!(
+            child_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[0.0, -0.5]]
+        );
+
+        let (parent_node, child_node) = overlapping_nodes(parent, child);
+        MaxOperation.apply(&parent_node, &child_node);
+        assert_eq!(
+            child_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[0.5, 0.0]]
+        );
+    }
+
+    #[test]
+    fn test_multiply_as_envelope() {
+        let (parent, child) = overlapping_nodes(
+            AudioClip::<Stereo<f32>>::new(vec![0.0, 0.0, 0.5, 0.5, 1.0, 1.0], 44100).unwrap(),
+            AudioClip::<Stereo<f32>>::new(vec![0.8, -0.8, 0.8, -0.8, 0.8, -0.8], 44100).unwrap(),
+        );
+        MultiplyOperation.apply(&parent, &child);
+        assert_eq!(
+            child.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[0.0, 0.0], [0.4, -0.4], [0.8, -0.8]]
+        );
+    }
+
+    #[test]
+    fn test_crossfade_curves_meet_at_ends() {
+        for curve in [
+            CrossfadeCurve::Linear,
+            CrossfadeCurve::EqualPower,
+            CrossfadeCurve::SCurve,
+        ] {
+            let (out_start, in_start) = curve.gains(0.0);
+            let (out_end, in_end) = curve.gains(1.0);
+            assert!((out_start - 1.0).abs() < 1e-6 && in_start.abs() < 1e-6);
+            assert!(out_end.abs() < 1e-6 && (in_end - 1.0).abs() < 1e-6);
         }
+        let (fade_out, fade_in) = CrossfadeCurve::EqualPower.gains(0.3);
+        assert!((fade_out * fade_out + fade_in * fade_in - 1.0).abs() < 1e-6);
+        assert_eq!(CrossfadeCurve::SCurve.gains(0.5), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_crossfade_over_overlap() {
+        let (mut parent, mut child) = overlapping_nodes(
+            AudioClip::<Mono<f32>>::new(vec![2.0; 6], 44100).unwrap(),
+            AudioClip::<Mono<f32>>::new(vec![1.0; 5], 44100).unwrap(),
+        );
+        parent.set_clip_start(1);
+        child.normalize_clip_bounds(&parent);
+        let crossfade = CrossfadeOperation {
+            length: 2,
+            curve: CrossfadeCurve::Linear,
+        };
+        crossfade.apply(&parent, &child);
+        assert_eq!(
+            child.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[1.0], [1.0], [1.5], [2.0], [2.0], [2.0], [2.0]]
+        );
+    }
+
+    #[test]
+    fn test_duck_follows_sidechain() {
+        let mut sidechain = vec![0.0; 4];
+        sidechain.extend(vec![1.0; 4]);
+        let (parent, child) = overlapping_nodes(
+            AudioClip::<Mono<f32>>::new(sidechain, 44100).unwrap(),
+            AudioClip::<Mono<f32>>::new(vec![1.0; 8], 44100).unwrap(),
+        );
+        let duck = DuckOperation {
+            depth: 0.5,
+            attack: 0,
+            release: 100,
+        };
+        duck.apply(&parent, &child);
+        assert_eq!(
+            child.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec()),
+            &[[1.0], [1.0], [1.0], [1.0], [0.5], [0.5], [0.5], [0.5]]
+        );
+        assert!(!AudioOperation::<Mono<f32>>::is_linear(&duck));
     }
 }
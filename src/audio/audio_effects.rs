@@ -1,83 +1,342 @@
-// // use dasp::Frame;
-// use std::cell::Cell;
-
-// trait AudioEffect<F: Frame> {
-//     fn apply_effect(&self, frame: &mut F);
-// }
-
-// struct Gain {
-//     factor: f32,
-// }
-
-// impl AudioEffect<dasp::frame::Mono<f32>> for Gain {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Mono<f32>) {
-//         frame.mul_amp(self.factor);
-//     }
-// }
-
-// impl AudioEffect<dasp::frame::Stereo<f32>> for Gain {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Stereo<f32>) {
-//         frame.mul_amp(self.factor);
-//     }
-// }
-
-// struct Invert;
-
-// impl AudioEffect<dasp::frame::Mono<f32>> for Invert {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Mono<f32>) {
-//         frame.scale_amp(-1.0);
-//     }
-// }
-
-// impl AudioEffect<dasp::frame::Stereo<f32>> for Invert {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Stereo<f32>) {
-//         frame.scale_amp(-1.0);
-//     }
-// }
-
-// struct FadeIn {
-//     duration: usize,
-//     counter: Cell<usize>,
-// }
-
-// impl AudioEffect<dasp::frame::Mono<f32>> for FadeIn {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Mono<f32>) {
-//         let fade_factor = (self.counter.get() as f32) / (self.duration as f32);
-//         frame.scale_amp(fade_factor.min(1.0));
-//         self.counter.set(self.counter.get() + 1);
-//     }
-// }
-
-// impl AudioEffect<dasp::frame::Stereo<f32>> for FadeIn {
-//     fn apply_effect(&self, frame: &mut dasp::frame::Stereo<f32>) {
-//         let fade_factor = (self.counter.get() as f32) / (self.duration as f32);
-//         frame.scale_amp(fade_factor.min(1.0));
-//         self.counter.set(self.counter.get() + 1);
-//     }
-// }
-
-// pub type EffectFn<F> = Box<dyn AudioEffect<F> + Send + Sync>;
-
-// pub fn gain<F: Frame<Sample = f32>>(factor: f32) -> EffectFn<F>
-// where
-//     F: AudioEffect<dasp::frame::Mono<f32>> + AudioEffect<dasp::frame::Stereo<f32>>,
-// {
-//     Box::new(Gain { factor })
-// }
-
-// pub fn invert<F: Frame<Sample = f32>>() -> EffectFn<F>
-// where
-//     F: AudioEffect<dasp::frame::Mono<f32>> + AudioEffect<dasp::frame::Stereo<f32>>,
-// {
-//     Box::new(Invert)
-// }
-
-// pub fn fade_in<F: Frame<Sample = f32>>(duration: usize) -> EffectFn<F>
-// where
-//     F: AudioEffect<dasp::frame::Mono<f32>> + AudioEffect<dasp::frame::Stereo<f32>>,
-// {
-//     Box::new(FadeIn {
-//         duration,
-//         counter: Cell::new(0),
-//     })
-// }
+use super::audio_clip::{AudioClip, AudioClipTrait};
+use super::session::SessionEffect;
+use dasp::Frame;
+
+// Define a trait for per-node effects, run over the node's whole clip
+pub trait AudioEffect<F>: Send {
+    fn apply(&mut self, clip: &mut AudioClip<F>);
+
+    // Copy kept by the edit history to put a changed chain back
+    fn clone_box(&self) -> EffectFn<F>;
+
+    // Factor frame `idx` of a clip `clip_len` frames long is scaled by, for
+    // effects that do nothing else. Only those can run a block at a time, as
+    // lazy rendering needs, filters and the like return `None`
+    fn gain_at(&self, _idx: usize, _clip_len: usize) -> Option<f32> {
+        None
+    }
+
+    // How the effect is written to a session file, `None` if it cannot be
+    fn to_session(&self) -> Option<SessionEffect> {
+        None
+    }
+}
+
+// `apply` for effects defined by `gain_at`
+fn apply_gain<F, E>(effect: &E, clip: &mut AudioClip<F>)
+where
+    F: Frame<Sample = f32> + Copy,
+    E: AudioEffect<F> + ?Sized,
+{
+    let clip_len = clip.get_length();
+    apply_gain_block(effect, clip.get_frames_mut(), 0, clip_len);
+}
+
+fn apply_gain_block<F, E>(effect: &E, block: &mut [F], offset: usize, clip_len: usize)
+where
+    F: Frame<Sample = f32> + Copy,
+    E: AudioEffect<F> + ?Sized,
+{
+    for (i, frame) in block.iter_mut().enumerate() {
+        if let Some(gain) = effect.gain_at(offset + i, clip_len) {
+            *frame = frame.scale_amp(gain);
+        }
+    }
+}
+
+pub type EffectFn<F> = Box<dyn AudioEffect<F>>;
+
+#[derive(Clone)]
+pub struct Gain {
+    pub factor: f32,
+}
+
+impl<F: Frame<Sample = f32> + Copy> AudioEffect<F> for Gain {
+    fn clone_box(&self) -> EffectFn<F> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionEffect> {
+        Some(SessionEffect::Gain {
+            factor: self.factor,
+        })
+    }
+
+    fn gain_at(&self, _idx: usize, _clip_len: usize) -> Option<f32> {
+        Some(self.factor)
+    }
+
+    fn apply(&mut self, clip: &mut AudioClip<F>) {
+        apply_gain(self, clip);
+    }
+}
+
+#[derive(Clone)]
+pub struct Invert;
+
+impl<F: Frame<Sample = f32> + Copy> AudioEffect<F> for Invert {
+    fn clone_box(&self) -> EffectFn<F> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionEffect> {
+        Some(SessionEffect::Invert)
+    }
+
+    fn gain_at(&self, _idx: usize, _clip_len: usize) -> Option<f32> {
+        Some(-1.0)
+    }
+
+    fn apply(&mut self, clip: &mut AudioClip<F>) {
+        apply_gain(self, clip);
+    }
+}
+
+#[derive(Clone)]
+pub struct FadeIn {
+    pub duration: usize,
+}
+
+impl<F: Frame<Sample = f32> + Copy> AudioEffect<F> for FadeIn {
+    fn clone_box(&self) -> EffectFn<F> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionEffect> {
+        Some(SessionEffect::FadeIn {
+            duration: self.duration,
+        })
+    }
+
+    fn gain_at(&self, idx: usize, _clip_len: usize) -> Option<f32> {
+        if idx < self.duration {
+            Some(idx as f32 / self.duration as f32)
+        } else {
+            Some(1.0)
+        }
+    }
+
+    fn apply(&mut self, clip: &mut AudioClip<F>) {
+        apply_gain(self, clip);
+    }
+}
+
+#[derive(Clone)]
+pub struct FadeOut {
+    pub duration: usize,
+}
+
+impl<F: Frame<Sample = f32> + Copy> AudioEffect<F> for FadeOut {
+    fn clone_box(&self) -> EffectFn<F> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionEffect> {
+        Some(SessionEffect::FadeOut {
+            duration: self.duration,
+        })
+    }
+
+    // Reaches zero on the last frame
+    fn gain_at(&self, idx: usize, clip_len: usize) -> Option<f32> {
+        if idx + self.duration >= clip_len {
+            Some((clip_len - 1 - idx) as f32 / self.duration as f32)
+        } else {
+            Some(1.0)
+        }
+    }
+
+    fn apply(&mut self, clip: &mut AudioClip<F>) {
+        apply_gain(self, clip);
+    }
+}
+
+pub struct AudioEffectChain<F> {
+    effects: Vec<EffectFn<F>>,
+}
+
+impl<F> AudioEffectChain<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn with_effect(mut self, effect: EffectFn<F>) -> Self {
+        self.add_effect(effect);
+        self
+    }
+
+    pub fn add_effect(&mut self, effect: EffectFn<F>) {
+        self.effects.push(effect);
+    }
+
+    pub fn set_effect(&mut self, idx: usize, effect: EffectFn<F>) -> Option<EffectFn<F>> {
+        let slot = self.effects.get_mut(idx)?;
+        Some(std::mem::replace(slot, effect))
+    }
+
+    pub fn remove_effect(&mut self, idx: usize) -> Option<EffectFn<F>> {
+        if idx < self.effects.len() {
+            Some(self.effects.remove(idx))
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn apply(&mut self, clip: &mut AudioClip<F>) {
+        for effect in self.effects.iter_mut() {
+            effect.apply(clip);
+        }
+    }
+
+    pub fn duplicate(&self) -> Self {
+        Self {
+            effects: self
+                .effects
+                .iter()
+                .map(|effect| effect.clone_box())
+                .collect(),
+        }
+    }
+
+    // Whether `apply_block` can stand in for `apply`, i.e. every effect is a gain
+    pub fn is_blockwise(&self) -> bool {
+        self.effects
+            .iter()
+            .all(|effect| effect.gain_at(0, 1).is_some())
+    }
+
+    // `apply` on frames `offset..offset + block.len()` of a clip `clip_len`
+    // frames long. Effects that are not gains are skipped, check `is_blockwise`
+    pub fn apply_block(&self, block: &mut [F], offset: usize, clip_len: usize) {
+        for effect in self.effects.iter() {
+            apply_gain_block(effect.as_ref(), block, offset, clip_len);
+        }
+    }
+
+    // `None` if any effect in the chain cannot be written to a session
+    pub fn to_session(&self) -> Option<Vec<SessionEffect>> {
+        self.effects
+            .iter()
+            .map(|effect| effect.to_session())
+            .collect()
+    }
+}
+
+impl<F> Default for AudioEffectChain<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn gain<F: Frame<Sample = f32> + Copy>(factor: f32) -> EffectFn<F> {
+    Box::new(Gain { factor })
+}
+
+pub fn invert<F: Frame<Sample = f32> + Copy>() -> EffectFn<F> {
+    Box::new(Invert)
+}
+
+pub fn fade_in<F: Frame<Sample = f32> + Copy>(duration: usize) -> EffectFn<F> {
+    Box::new(FadeIn { duration })
+}
+
+pub fn fade_out<F: Frame<Sample = f32> + Copy>(duration: usize) -> EffectFn<F> {
+    Box::new(FadeOut { duration })
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dasp::frame::{Mono, Stereo};
+
+    fn unit_clip_mono(size: usize) -> AudioClip<Mono<f32>> {
+        AudioClip::<Mono<f32>>::new(vec![1.0; size], 44100).unwrap()
+    }
+
+    #[test]
+    fn test_apply_block_matches_apply() {
+        let mut chain = AudioEffectChain::new()
+            .with_effect(fade_in(4))
+            .with_effect(invert())
+            .with_effect(gain(0.5))
+            .with_effect(fade_out(3));
+        assert!(chain.is_blockwise());
+        let mut clip = unit_clip_mono(9);
+        chain.apply(&mut clip);
+
+        let mut frames = vec![[1.0]; 9];
+        for (i, block) in frames.chunks_mut(2).enumerate() {
+            chain.apply_block(block, i * 2, 9);
+        }
+        assert_eq!(frames, clip.get_frames_ref());
+    }
+
+    #[test]
+    fn test_gain_stereo() {
+        let mut clip = AudioClip::<Stereo<f32>>::new(vec![1.0, -1.0, 0.5, 0.25], 44100).unwrap();
+        let mut effect = gain::<Stereo<f32>>(2.0);
+        effect.apply(&mut clip);
+        assert_eq!(clip.get_frames_ref(), &[[2.0, -2.0], [1.0, 0.5]]);
+    }
+
+    #[test]
+    fn test_invert_mono() {
+        let mut clip = unit_clip_mono(3);
+        let mut effect = invert::<Mono<f32>>();
+        effect.apply(&mut clip);
+        assert_eq!(clip.get_frames_ref(), &[[-1.0], [-1.0], [-1.0]]);
+    }
+
+    #[test]
+    fn test_fade_in_and_out() {
+        let mut clip = unit_clip_mono(6);
+        let mut chain = AudioEffectChain::new()
+            .with_effect(fade_in(2))
+            .with_effect(fade_out(2));
+        chain.apply(&mut clip);
+        assert_eq!(
+            clip.get_frames_ref(),
+            &[[0.0], [0.5], [1.0], [1.0], [0.5], [0.0]]
+        );
+    }
+
+    #[test]
+    fn test_fade_longer_than_clip() {
+        let mut clip = unit_clip_mono(2);
+        let mut effect = fade_in::<Mono<f32>>(4);
+        effect.apply(&mut clip);
+        assert_eq!(clip.get_frames_ref(), &[[0.0], [0.25]]);
+    }
+
+    #[test]
+    fn test_chain_set_and_remove_effect() {
+        let mut chain = AudioEffectChain::<Mono<f32>>::new().with_effect(gain(2.0));
+        assert!(chain.set_effect(0, gain(3.0)).is_some());
+        assert!(chain.set_effect(1, gain(3.0)).is_none());
+
+        let mut clip = unit_clip_mono(1);
+        chain.apply(&mut clip);
+        assert_eq!(clip.get_frames_ref(), &[[3.0]]);
+
+        assert!(chain.remove_effect(0).is_some());
+        assert!(chain.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use std::fmt;
+use std::sync::PoisonError;
+
+#[derive(Debug)]
+pub enum AudioError {
+    UnknownNode(NodeIndex),
+    InvalidEdge(EdgeIndex),
+    NotAMixEdge(EdgeIndex),
+    UnsavableEdge(EdgeIndex),
+    UnsavableEffect(NodeIndex),
+    UnrenderableEffect(NodeIndex),
+    SelfLoop(NodeIndex),
+    DuplicateEdge { parent: NodeIndex, child: NodeIndex },
+    CycleDetected { parent: NodeIndex, child: NodeIndex },
+    RootAsSource,
+    NegativePlacement { node: NodeIndex, offset: i64 },
+    RootRemoval,
+    RootMove,
+    UnsupportedChannelCount(u32),
+    SampleRateMismatch { expected: u32, found: u32 },
+    PartialFrame { samples: usize, channels: u32 },
+    FrameOutOfBounds { idx: usize, len: usize },
+    InvalidLoopRegion { start: usize, end: usize },
+    InvalidRegion { start: i64, end: i64 },
+    InvalidSplit(usize),
+    DeviceUnavailable(String),
+    Decode(String),
+    Encode(String),
+    Session(String),
+    Io(std::io::Error),
+    PoisonedLock,
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::UnknownNode(node) => write!(f, "unknown node {}", node.index()),
+            AudioError::InvalidEdge(edge) => write!(f, "invalid edge {}", edge.index()),
+            AudioError::NotAMixEdge(edge) => {
+                write!(f, "edge {} has no mix parameters", edge.index())
+            }
+            AudioError::UnsavableEdge(edge) => {
+                write!(f, "edge {} cannot be written to a session", edge.index())
+            }
+            AudioError::UnsavableEffect(node) => {
+                write!(
+                    f,
+                    "effect chain of node {} cannot be written to a session",
+                    node.index()
+                )
+            }
+            AudioError::UnrenderableEffect(node) => {
+                write!(
+                    f,
+                    "effect chain of node {} cannot be rendered block by block",
+                    node.index()
+                )
+            }
+            AudioError::SelfLoop(node) => {
+                write!(f, "node {} cannot be connected to itself", node.index())
+            }
+            AudioError::DuplicateEdge { parent, child } => write!(
+                f,
+                "node {} is already connected to node {}",
+                parent.index(),
+                child.index()
+            ),
+            AudioError::CycleDetected { parent, child } => write!(
+                f,
+                "connecting node {} to node {} would create a cycle",
+                parent.index(),
+                child.index()
+            ),
+            AudioError::RootAsSource => write!(f, "the root node cannot feed other nodes"),
+            AudioError::NegativePlacement { node, offset } => write!(
+                f,
+                "offset {} would place node {} before the start of the timeline",
+                offset,
+                node.index()
+            ),
+            AudioError::RootRemoval => write!(f, "the root node cannot be removed"),
+            AudioError::RootMove => write!(f, "the root node cannot be moved"),
+            AudioError::UnsupportedChannelCount(channels) => {
+                write!(f, "unsupported channel count: {}", channels)
+            }
+            AudioError::SampleRateMismatch { expected, found } => write!(
+                f,
+                "sample rate {} does not match the processor's {}",
+                found, expected
+            ),
+            AudioError::PartialFrame { samples, channels } => write!(
+                f,
+                "{} samples do not divide into frames of {} channels",
+                samples, channels
+            ),
+            AudioError::FrameOutOfBounds { idx, len } => {
+                write!(f, "frame {} out of bounds for clip of length {}", idx, len)
+            }
+            AudioError::InvalidLoopRegion { start, end } => {
+                write!(f, "invalid loop region {}..{}", start, end)
+            }
+            AudioError::InvalidRegion { start, end } => {
+                write!(f, "invalid source region {}..{}", start, end)
+            }
+            AudioError::InvalidSplit(at) => {
+                write!(f, "frame {} is not inside the region being split", at)
+            }
+            AudioError::DeviceUnavailable(reason) => write!(f, "device unavailable: {}", reason),
+            AudioError::Decode(reason) => write!(f, "failed to decode audio: {}", reason),
+            AudioError::Encode(reason) => write!(f, "failed to encode audio: {}", reason),
+            AudioError::Session(reason) => write!(f, "invalid session: {}", reason),
+            AudioError::Io(err) => write!(f, "io error: {}", err),
+            AudioError::PoisonedLock => write!(f, "lock poisoned by a panicked thread"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AudioError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AudioError {
+    fn from(err: std::io::Error) -> Self {
+        AudioError::Io(err)
+    }
+}
+
+impl From<audrey::read::ReadError> for AudioError {
+    fn from(err: audrey::read::ReadError) -> Self {
+        match err {
+            audrey::read::ReadError::Io(err) => AudioError::Io(err),
+            err => AudioError::Decode(err.to_string()),
+        }
+    }
+}
+
+impl From<audrey::read::FormatError> for AudioError {
+    fn from(err: audrey::read::FormatError) -> Self {
+        AudioError::Decode(err.to_string())
+    }
+}
+
+impl From<hound::Error> for AudioError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(err) => AudioError::Io(err),
+            err => AudioError::Encode(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AudioError {
+    fn from(err: serde_json::Error) -> Self {
+        if err.is_io() {
+            AudioError::Io(err.into())
+        } else {
+            AudioError::Session(err.to_string())
+        }
+    }
+}
+
+impl<T> From<PoisonError<T>> for AudioError {
+    fn from(_: PoisonError<T>) -> Self {
+        AudioError::PoisonedLock
+    }
+}
+
+pub type AudioResult<T> = Result<T, AudioError>;
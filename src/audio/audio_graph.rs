@@ -1,11 +1,13 @@
 // ----  Computation Tree ----
 
-use super::audio_clip::{AudioClip, AudioClipTrait};
-use super::audio_edge::{AddOperation, AudioGraphEdge};
+use super::audio_clip::{AudioClip, AudioClipTrait, DEFAULT_SAMPLE_RATE};
+use super::audio_edge::AudioGraphEdge;
+use super::audio_error::{AudioError, AudioResult};
 use super::audio_node::AudioNode;
+use petgraph::algo::has_path_connecting;
 use petgraph::dot::Dot;
 use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableDiGraph};
-use petgraph::visit::{Dfs, EdgeRef};
+use petgraph::visit::{DfsPostOrder, EdgeRef};
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
@@ -36,6 +38,7 @@ pub struct AudioGraph<F> {
     pub root: NodeIndex,
     node_lookup: HashMap<String, NodeIndex>,
     node_id: i32,
+    sample_rate: u32,
 }
 
 impl<F> AudioGraph<F>
@@ -43,9 +46,13 @@ where
     F: dasp::Frame<Sample = f32> + Default + Copy,
 {
     pub fn new() -> Self {
+        Self::with_sample_rate(DEFAULT_SAMPLE_RATE)
+    }
+
+    pub fn with_sample_rate(sample_rate: u32) -> Self {
         let mut graph = StableDiGraph::new();
         let node_lookup = HashMap::new();
-        let clip = AudioClip::<F>::default();
+        let clip = AudioClip::<F>::with_capacity_and_rate(sample_rate as usize * 5, sample_rate);
         let audio_node =
             AudioGraphNode::RootNode(Arc::new(Mutex::new(AudioNode::new(clip, Some("root")))));
         let root: petgraph::stable_graph::NodeIndex = graph.add_node(audio_node);
@@ -54,9 +61,14 @@ where
             node_lookup,
             root,
             node_id: 1,
+            sample_rate,
         }
     }
 
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     pub fn add_data_node(&mut self, mut audio_node: AudioNode<F>) -> NodeIndex {
         let name = match audio_node.get_name() {
             Some(n) => n.to_string(),
@@ -76,10 +88,24 @@ where
         node_id
     }
 
+    // Puts a node taken out by `remove_data_node` back under its own name
+    pub(crate) fn restore_data_node(
+        &mut self,
+        audio_node: Arc<Mutex<AudioNode<F>>>,
+    ) -> AudioResult<NodeIndex> {
+        let name = audio_node.lock()?.get_name().map(str::to_string);
+        let node_id = self.graph.add_node(AudioGraphNode::DataNode(audio_node));
+        if let Some(name) = name {
+            self.node_lookup.insert(name, node_id);
+        }
+        Ok(node_id)
+    }
+
     pub fn create_indexed_node(&mut self) -> AudioNode<F> {
         let name = format!("Node{}", self.node_id);
         self.node_id += 1;
-        let clip = AudioClip::<F>::default();
+        let clip =
+            AudioClip::<F>::with_capacity_and_rate(self.sample_rate as usize * 5, self.sample_rate);
         AudioNode::new(clip, Some(&name))
     }
 
@@ -88,32 +114,92 @@ where
         parent: NodeIndex,
         child: Option<NodeIndex>,
         edge: AudioGraphEdge<F>,
-    ) -> Result<EdgeIndex, String> {
-        let edge_id = match child {
-            Some(child) => self.graph.add_edge(parent, child, edge),
-            None => self.graph.add_edge(parent, self.root, edge),
-        };
-        Ok(edge_id)
+    ) -> AudioResult<EdgeIndex> {
+        let child = child.unwrap_or(self.root);
+        for node in [parent, child] {
+            if !self.graph.contains_node(node) {
+                return Err(AudioError::UnknownNode(node));
+            }
+        }
+
+        if parent == self.root {
+            return Err(AudioError::RootAsSource);
+        }
+        if parent == child {
+            return Err(AudioError::SelfLoop(parent));
+        }
+        if self.graph.find_edge(parent, child).is_some() {
+            return Err(AudioError::DuplicateEdge { parent, child });
+        }
+        // The new edge closes a cycle iff the parent is already reachable from the child
+        if has_path_connecting(&self.graph, child, parent, None) {
+            return Err(AudioError::CycleDetected { parent, child });
+        }
+
+        Ok(self.graph.add_edge(parent, child, edge))
     }
 
-    pub fn collect_dependents(
-        &self,
+    pub fn disconnect(&mut self, edge_idx: EdgeIndex) -> AudioResult<AudioGraphEdge<F>> {
+        self.graph
+            .remove_edge(edge_idx)
+            .ok_or(AudioError::InvalidEdge(edge_idx))
+    }
+
+    pub fn remove_data_node(
+        &mut self,
         node_idx: NodeIndex,
-    ) -> Vec<(NodeIndex, NodeIndex, EdgeIndex)> {
-        let mut dfs = Dfs::new(&self.graph, node_idx);
-        let mut to_compute = Vec::new();
+    ) -> AudioResult<Arc<Mutex<AudioNode<F>>>> {
+        if node_idx == self.root {
+            return Err(AudioError::RootRemoval);
+        }
+        match self.graph.remove_node(node_idx) {
+            Some(AudioGraphNode::DataNode(node)) => {
+                self.node_lookup.retain(|_, idx| *idx != node_idx);
+                Ok(node)
+            }
+            _ => Err(AudioError::UnknownNode(node_idx)),
+        }
+    }
 
-        while let Some(node) = dfs.next(&self.graph) {
-            let incoming_info: Vec<(NodeIndex, NodeIndex, EdgeIndex)> = self
-                .graph
-                .edges_directed(node, petgraph::Direction::Outgoing)
-                .map(|edge| (node, edge.target(), edge.id()))
-                .collect::<Vec<_>>();
+    pub fn get_edge_endpoints(&self, edge_idx: EdgeIndex) -> AudioResult<(NodeIndex, NodeIndex)> {
+        self.graph
+            .edge_endpoints(edge_idx)
+            .ok_or(AudioError::InvalidEdge(edge_idx))
+    }
 
-            to_compute.extend(incoming_info);
+    // The node and everything downstream of it, each node after all of its
+    // parents (reverse DFS post-order is a topological order of a DAG)
+    pub fn dependents_in_order(&self, node_idx: NodeIndex) -> Vec<NodeIndex> {
+        let mut dfs = DfsPostOrder::new(&self.graph, node_idx);
+        let mut order = Vec::new();
+        while let Some(node) = dfs.next(&self.graph) {
+            order.push(node);
         }
+        order.reverse();
+        order
+    }
 
-        to_compute
+    // Outgoing edges of every dependent, in the order of `dependents_in_order`
+    pub fn collect_dependents(
+        &self,
+        node_idx: NodeIndex,
+    ) -> Vec<(NodeIndex, NodeIndex, EdgeIndex)> {
+        self.dependents_in_order(node_idx)
+            .into_iter()
+            .flat_map(|node| {
+                self.graph
+                    .edges_directed(node, petgraph::Direction::Outgoing)
+                    .map(move |edge| (node, edge.target(), edge.id()))
+            })
+            .collect()
+    }
+
+    // Deltas only pass through linear edges, a node fed by any other kind has
+    // to be rebuilt from all of its inputs
+    pub fn has_nonlinear_inputs(&self, node_idx: NodeIndex) -> bool {
+        self.graph
+            .edges_directed(node_idx, petgraph::Direction::Incoming)
+            .any(|edge| !edge.weight().operation.is_linear())
     }
 
     pub fn get_node_id(&self, id: &str) -> Option<NodeIndex> {
@@ -121,17 +207,28 @@ where
     }
 
     pub fn get_node(&self, node_idx: NodeIndex) -> Option<&Arc<Mutex<AudioNode<F>>>> {
-        match &self.graph[node_idx] {
+        match self.graph.node_weight(node_idx)? {
             AudioGraphNode::RootNode(node) => Some(node),
             AudioGraphNode::DataNode(node) => Some(node),
         }
     }
 
+    pub fn try_get_node(&self, node_idx: NodeIndex) -> AudioResult<&Arc<Mutex<AudioNode<F>>>> {
+        self.get_node(node_idx)
+            .ok_or(AudioError::UnknownNode(node_idx))
+    }
+
     pub fn get_edge_ref(&self, edge_idx: EdgeIndex) -> Option<&AudioGraphEdge<F>> {
-        Some(&self.graph[edge_idx])
+        self.graph.edge_weight(edge_idx)
+    }
+
+    pub fn get_edge_mut(&mut self, edge_idx: EdgeIndex) -> Option<&mut AudioGraphEdge<F>> {
+        self.graph.edge_weight_mut(edge_idx)
     }
-    pub fn get_edge_mut(&mut self, edge_idx: EdgeIndex) -> Option<&AudioGraphEdge<F>> {
-        Some(&mut self.graph[edge_idx])
+
+    pub fn try_get_edge_ref(&self, edge_idx: EdgeIndex) -> AudioResult<&AudioGraphEdge<F>> {
+        self.get_edge_ref(edge_idx)
+            .ok_or(AudioError::InvalidEdge(edge_idx))
     }
 
     pub fn print_graph(&self) {
@@ -142,6 +239,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::audio_edge::AddOperation;
     use dasp::frame::{Mono, Stereo};
 
     fn setup_graph<F: dasp::Frame<Sample = f32> + Default + Copy>() -> AudioGraph<F> {
@@ -255,6 +353,140 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sample_rate_reaches_root_and_indexed_nodes() {
+        let mut graph = AudioGraph::<Stereo<f32>>::with_sample_rate(48000);
+        assert_eq!(graph.get_sample_rate(), 48000);
+
+        let root = graph.get_node(graph.root).unwrap().lock().unwrap();
+        assert_eq!(
+            root.with_buffers(|buffers| buffers.clip.get_sample_rate()),
+            48000
+        );
+        assert_eq!(root.get_clip_len(), 48000 * 5);
+        drop(root);
+
+        let node = graph.create_indexed_node();
+        assert_eq!(
+            node.with_buffers(|buffers| buffers.clip.get_sample_rate()),
+            48000
+        );
+        assert_eq!(
+            node.with_buffers(|buffers| buffers.delta_clip.get_sample_rate()),
+            48000
+        );
+    }
+
+    #[test]
+    fn test_connect_unknown_node() {
+        let mut graph = setup_graph::<Mono<f32>>();
+        let node1_id = graph.get_node_id("node1").unwrap();
+        let missing = NodeIndex::new(100);
+
+        let result = graph.connect(
+            node1_id,
+            Some(missing),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::UnknownNode(n)) if n == missing));
+        assert!(graph.get_node(missing).is_none());
+    }
+
+    #[test]
+    fn test_connect_rejects_invalid_edges() {
+        let mut graph = setup_graph::<Mono<f32>>();
+
+        let node1_id = graph.get_node_id("node1").unwrap();
+        let node2_id = graph.get_node_id("node2").unwrap();
+        let node3_id = graph.get_node_id("node3").unwrap();
+        let root = graph.root;
+
+        let result = graph.connect(
+            node1_id,
+            Some(node1_id),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::SelfLoop(n)) if n == node1_id));
+
+        let result = graph.connect(
+            root,
+            Some(node1_id),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::RootAsSource)));
+
+        graph
+            .connect(
+                node1_id,
+                Some(node2_id),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        graph
+            .connect(
+                node2_id,
+                Some(node3_id),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+
+        let result = graph.connect(
+            node1_id,
+            Some(node2_id),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::DuplicateEdge { .. })));
+
+        let result = graph.connect(
+            node3_id,
+            Some(node1_id),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(
+            result,
+            Err(AudioError::CycleDetected { parent, child }) if parent == node3_id && child == node1_id
+        ));
+
+        // Rejected edges leave the graph untouched
+        assert_eq!(graph.graph.edge_count(), 2);
+    }
+
+    #[test]
+    fn test_disconnect_and_remove_node() {
+        let mut graph = setup_graph::<Mono<f32>>();
+
+        let node1_id = graph.get_node_id("node1").unwrap();
+        let node2_id = graph.get_node_id("node2").unwrap();
+
+        let edge = graph
+            .connect(
+                node1_id,
+                Some(node2_id),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        assert_eq!(
+            graph.get_edge_endpoints(edge).unwrap(),
+            (node1_id, node2_id)
+        );
+
+        graph.disconnect(edge).unwrap();
+        assert!(matches!(
+            graph.disconnect(edge),
+            Err(AudioError::InvalidEdge(_))
+        ));
+
+        graph.remove_data_node(node1_id).unwrap();
+        assert!(graph.get_node(node1_id).is_none());
+        assert!(graph.get_node_id("node1").is_none());
+
+        let root = graph.root;
+        assert!(matches!(
+            graph.remove_data_node(root),
+            Err(AudioError::RootRemoval)
+        ));
+    }
+
     #[test]
     fn test_connect_and_collect_path_mono() {
         let mut graph = setup_graph::<Mono<f32>>();
@@ -498,4 +730,40 @@ mod tests {
         assert_eq!(path[1], (node1_id, node4_id, edge1));
         assert_eq!(path[2], (node4_id, graph.root, edge9));
     }
+
+    #[test]
+    fn test_dependents_in_order_diamond() {
+        let mut graph = setup_graph::<Mono<f32>>();
+
+        let node1_id = graph.get_node_id("node1").unwrap();
+        let node2_id = graph.get_node_id("node2").unwrap();
+        let node3_id = graph.get_node_id("node3").unwrap();
+        let node4_id = graph.get_node_id("node4").unwrap();
+
+        for (parent, child) in [
+            (node1_id, Some(node2_id)),
+            (node1_id, Some(node3_id)),
+            (node2_id, Some(node4_id)),
+            (node3_id, Some(node4_id)),
+            (node4_id, None),
+            (node1_id, None),
+        ] {
+            graph
+                .connect(parent, child, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+
+        let order = graph.dependents_in_order(node1_id);
+        assert_eq!(order.len(), 5);
+        assert_eq!(order[0], node1_id);
+        assert_eq!(order[3], node4_id);
+        assert_eq!(order[4], graph.root);
+
+        // Every edge shows up once, after all edges into its source
+        let path = graph.collect_dependents(node1_id);
+        assert_eq!(path.len(), 6);
+        let last_into_node4 = path.iter().rposition(|&(_, child, _)| child == node4_id);
+        let first_from_node4 = path.iter().position(|&(parent, _, _)| parent == node4_id);
+        assert!(last_into_node4 < first_from_node4);
+    }
 }
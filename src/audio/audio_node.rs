@@ -1,15 +1,211 @@
 use super::audio_clip::{AudioClip, AudioClipTrait};
-use std::sync::{Arc, Mutex, MutexGuard};
+use super::audio_effects::AudioEffectChain;
+use super::audio_error::{AudioError, AudioResult};
+use super::stream_clip::StreamClip;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Every buffer of a node sits behind one lock, so a caller holds all of them
+// or none. The lock is only taken for the length of an accessor's closure
+pub struct NodeBuffers<F> {
+    // Shared with the node's in-memory source until something is mixed in
+    pub clip: Arc<AudioClip<F>>,
+    pub delta_clip: AudioClip<F>,
+    pub prev_clip: AudioClip<F>,
+    // Output of the effect chain, read by outgoing edges in place of `clip`
+    pub wet_clip: Option<AudioClip<F>>,
+}
+
+thread_local! {
+    // Buffers this thread has locked, so that reaching the same buffers again
+    // from inside an accessor's closure fails a debug assertion instead of
+    // deadlocking
+    static HELD_BUFFERS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+struct HeldBuffers(usize);
+
+impl HeldBuffers {
+    fn enter(id: usize) -> Self {
+        HELD_BUFFERS.with(|held| {
+            let mut held = held.borrow_mut();
+            debug_assert!(
+                !held.contains(&id),
+                "node buffers locked again from inside one of their accessors"
+            );
+            held.push(id);
+        });
+        HeldBuffers(id)
+    }
+}
+
+impl Drop for HeldBuffers {
+    fn drop(&mut self) {
+        HELD_BUFFERS.with(|held| held.borrow_mut().retain(|id| *id != self.0));
+    }
+}
+
+impl<F> NodeBuffers<F> {
+    // A closure that panicked leaves the buffers poisoned. They are still whole
+    // frames, so later accesses go on with them rather than panicking as well
+    fn access<R>(buffers: &Arc<Mutex<NodeBuffers<F>>>, f: impl FnOnce(&mut Self) -> R) -> R {
+        let _held = HeldBuffers::enter(Arc::as_ptr(buffers) as usize);
+        let mut buffers = buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut buffers)
+    }
+}
+
+impl<F> NodeBuffers<F>
+where
+    F: dasp::Frame<Sample = f32> + Copy,
+{
+    pub fn output_clip(&self) -> &AudioClip<F> {
+        self.wet_clip.as_ref().unwrap_or(&self.clip)
+    }
+
+    // Copies the clip first if it is still shared with the source
+    pub fn clip_mut(&mut self) -> &mut AudioClip<F> {
+        Arc::make_mut(&mut self.clip)
+    }
+
+    fn resize(&mut self, new_size: usize, value: F) {
+        self.clip_mut().resize_frames(new_size, value);
+        self.delta_clip.resize_frames(new_size, value);
+        self.prev_clip.resize_frames(new_size, value);
+        if let Some(wet_clip) = &mut self.wet_clip {
+            wet_clip.resize_frames(new_size, value);
+        }
+    }
+
+    fn add_padding_left(&mut self, padding_amount: usize) {
+        self.clip_mut().add_padding_left(padding_amount);
+        self.delta_clip.add_padding_left(padding_amount);
+        self.prev_clip.add_padding_left(padding_amount);
+        if let Some(wet_clip) = &mut self.wet_clip {
+            wet_clip.add_padding_left(padding_amount);
+        }
+    }
+}
+
+// Frames read from a source at a time when mixing it into a buffer
+const SOURCE_CHUNK: usize = 4096;
+
+// Material a node plays, either held in memory or streamed from a file
+pub enum ClipSource<F> {
+    Memory(Arc<AudioClip<F>>),
+    Stream(Arc<StreamClip<F>>),
+}
+
+impl<F> Clone for ClipSource<F> {
+    fn clone(&self) -> Self {
+        match self {
+            ClipSource::Memory(clip) => ClipSource::Memory(Arc::clone(clip)),
+            ClipSource::Stream(clip) => ClipSource::Stream(Arc::clone(clip)),
+        }
+    }
+}
+
+impl<F> From<AudioClip<F>> for ClipSource<F> {
+    fn from(clip: AudioClip<F>) -> Self {
+        ClipSource::Memory(Arc::new(clip))
+    }
+}
+
+impl<F> From<Arc<AudioClip<F>>> for ClipSource<F> {
+    fn from(clip: Arc<AudioClip<F>>) -> Self {
+        ClipSource::Memory(clip)
+    }
+}
+
+impl<F> From<StreamClip<F>> for ClipSource<F> {
+    fn from(clip: StreamClip<F>) -> Self {
+        ClipSource::Stream(Arc::new(clip))
+    }
+}
+
+impl<F> ClipSource<F>
+where
+    F: dasp::Frame<Sample = f32> + Copy,
+{
+    pub fn get_sample_rate(&self) -> u32 {
+        match self {
+            ClipSource::Memory(clip) => clip.get_sample_rate(),
+            ClipSource::Stream(clip) => clip.get_sample_rate(),
+        }
+    }
+
+    pub fn get_length(&self) -> usize {
+        match self {
+            ClipSource::Memory(clip) => clip.get_length(),
+            ClipSource::Stream(clip) => clip.get_length(),
+        }
+    }
+
+    pub fn read_frames(&self, start: usize, out: &mut [F]) -> AudioResult<usize> {
+        match self {
+            ClipSource::Memory(clip) => clip.read_frames(start, out),
+            ClipSource::Stream(clip) => clip.read_frames(start, out),
+        }
+    }
+
+    // Every frame at once, which loads a stream whole
+    pub fn get_frames_ref(&self) -> AudioResult<&[F]> {
+        match self {
+            ClipSource::Memory(clip) => Ok(clip.get_frames_ref()),
+            ClipSource::Stream(clip) => clip.load(),
+        }
+    }
+
+    // Whether both are the same material rather than equal frames
+    pub fn ptr_eq(&self, other: &ClipSource<F>) -> bool {
+        match (self, other) {
+            (ClipSource::Memory(a), ClipSource::Memory(b)) => Arc::ptr_eq(a, b),
+            (ClipSource::Stream(a), ClipSource::Stream(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // Calls `f` with each chunk of source frames `start..end` and its offset
+    fn for_each_chunk(
+        &self,
+        start: usize,
+        end: usize,
+        mut f: impl FnMut(usize, &[F]),
+    ) -> AudioResult<()> {
+        let mut chunk = vec![F::EQUILIBRIUM; SOURCE_CHUNK.min(end.saturating_sub(start))];
+        let mut from = start;
+        while from < end {
+            let len = chunk.len().min(end - from);
+            let count = self.read_frames(from, &mut chunk[..len])?;
+            chunk[count..len].fill(F::EQUILIBRIUM);
+            f(from - start, &chunk[..len]);
+            from += len;
+        }
+        Ok(())
+    }
+}
 
 pub struct AudioNode<F> {
     pub name: Option<String>,
-    clip: Arc<Mutex<AudioClip<F>>>,
-    delta_clip: Arc<Mutex<AudioClip<F>>>,
-    prev_clip: Arc<Mutex<AudioClip<F>>>,
+    buffers: Arc<Mutex<NodeBuffers<F>>>,
     delta_range: Option<(usize, usize)>,
     clip_start: usize,
     clip_len: usize,
-    // effect_chain: Option<AudioEffectChain<F>>,
+    effect_chain: Option<AudioEffectChain<F>>,
+    // The node's own material before any input was mixed in. Only the frames in
+    // `source_region` are heard, starting `source_offset` frames into `clip`.
+    // Shared between nodes split from the same material
+    source_clip: ClipSource<F>,
+    source_region: (usize, usize),
+    source_offset: usize,
+    // File the source was loaded from, so sessions can refer to it
+    source_path: Option<PathBuf>,
+    // False while the processor renders lazily and the node only keeps its
+    // placement, leaving the buffers empty
+    buffered: bool,
+    // Set on views whose output is the pending delta rather than the clip
+    delta_output: bool,
 }
 
 impl<F> AudioNode<F>
@@ -17,32 +213,137 @@ where
     F: dasp::Frame<Sample = f32> + Copy,
 {
     pub fn new(clip: AudioClip<F>, name: Option<&str>) -> Self {
+        let source_region = (0, clip.get_length());
+        let clip = Arc::new(clip);
+        let source_clip = ClipSource::from(Arc::clone(&clip));
+        AudioNode::with_source(clip, source_clip, source_region, name)
+    }
+
+    // Node playing `region` of material it shares with other nodes
+    pub fn from_source(
+        source_clip: ClipSource<F>,
+        region: (usize, usize),
+        name: Option<&str>,
+    ) -> AudioResult<Self> {
+        let mut audio_node = AudioNode::without_buffers(source_clip, region, name);
+        audio_node.restore_buffers()?;
+        Ok(audio_node)
+    }
+
+    // Node placed on the timeline that holds no frames until its buffers are
+    // restored, so long streamed material is never copied in whole
+    pub fn without_buffers(
+        source_clip: ClipSource<F>,
+        region: (usize, usize),
+        name: Option<&str>,
+    ) -> Self {
+        let empty = AudioClip::with_capacity_and_rate(0, source_clip.get_sample_rate());
+        let mut audio_node = AudioNode::with_source(Arc::new(empty), source_clip, region, name);
+        audio_node.clip_len = region.1 - region.0;
+        audio_node.buffered = false;
+        audio_node
+    }
+
+    fn with_source(
+        clip: Arc<AudioClip<F>>,
+        source_clip: ClipSource<F>,
+        source_region: (usize, usize),
+        name: Option<&str>,
+    ) -> Self {
         let name = name.map(|s| s.to_string());
-        let prev_clip = clip.clone();
-        let delta_clip = AudioClip::with_capacity(clip.get_length());
+        let prev_clip = AudioClip::clone(&clip);
+        let delta_clip =
+            AudioClip::with_capacity_and_rate(clip.get_length(), clip.get_sample_rate());
         let clip_len = clip.get_length();
         AudioNode {
             name,
-            clip: Arc::new(Mutex::new(clip)),
-            prev_clip: Arc::new(Mutex::new(prev_clip)),
-            delta_clip: Arc::new(Mutex::new(delta_clip)),
+            buffers: Arc::new(Mutex::new(NodeBuffers {
+                clip,
+                delta_clip,
+                prev_clip,
+                wet_clip: None,
+            })),
             delta_range: None,
             clip_start: 0,
             clip_len,
-            // effect_chain: None,
+            effect_chain: None,
+            source_clip,
+            source_region,
+            source_offset: 0,
+            source_path: None,
+            buffered: true,
+            delta_output: false,
         }
     }
 
-    pub fn get_clip(&self) -> MutexGuard<'_, AudioClip<F>> {
-        self.clip.lock().unwrap()
+    pub fn with_effects(
+        clip: AudioClip<F>,
+        effect_chain: AudioEffectChain<F>,
+        name: Option<&str>,
+    ) -> Self {
+        let mut audio_node = AudioNode::new(clip, name);
+        audio_node.set_effect_chain(Some(effect_chain));
+        audio_node.commit_changes();
+        audio_node
+    }
+
+    // Runs `f` with every clip of the node locked. Node methods lock
+    // internally, so calling one on this node from `f` panics
+    pub fn with_buffers<R>(&self, f: impl FnOnce(&NodeBuffers<F>) -> R) -> R {
+        NodeBuffers::access(&self.buffers, |buffers| f(buffers))
+    }
+
+    pub fn with_buffers_mut<R>(&self, f: impl FnOnce(&mut NodeBuffers<F>) -> R) -> R {
+        NodeBuffers::access(&self.buffers, f)
+    }
+
+    // Runs `f` on what outgoing edges read from this node
+    pub fn with_output<R>(&self, f: impl FnOnce(&AudioClip<F>) -> R) -> R {
+        self.with_buffers(|buffers| {
+            if self.delta_output {
+                f(&buffers.delta_clip)
+            } else {
+                f(buffers.output_clip())
+            }
+        })
+    }
+
+    pub fn get_effect_chain(&self) -> Option<&AudioEffectChain<F>> {
+        self.effect_chain.as_ref()
     }
 
-    pub fn get_delta_clip(&self) -> MutexGuard<'_, AudioClip<F>> {
-        self.delta_clip.lock().unwrap()
+    pub fn get_effect_chain_mut(&mut self) -> Option<&mut AudioEffectChain<F>> {
+        self.effect_chain.as_mut()
     }
 
-    pub fn get_prev_clip(&self) -> MutexGuard<'_, AudioClip<F>> {
-        self.prev_clip.lock().unwrap()
+    pub fn set_effect_chain(&mut self, effect_chain: Option<AudioEffectChain<F>>) {
+        self.with_buffers_mut(|buffers| {
+            buffers.wet_clip = effect_chain
+                .as_ref()
+                .map(|_| AudioClip::clone(&buffers.clip));
+        });
+        self.effect_chain = effect_chain;
+        self.set_delta_range(Some((0, self.clip_len)));
+        self.process();
+    }
+
+    // Read-only stand-in for this node at another position on the timeline,
+    // sharing its buffers so edges can offset it without copying frames
+    pub fn view_at(&self, clip_start: usize) -> AudioNode<F> {
+        AudioNode {
+            name: self.name.clone(),
+            buffers: Arc::clone(&self.buffers),
+            delta_range: self.delta_range,
+            clip_start,
+            clip_len: self.clip_len,
+            effect_chain: None,
+            source_clip: self.source_clip.clone(),
+            source_region: self.source_region,
+            source_offset: self.source_offset,
+            source_path: None,
+            buffered: self.buffered,
+            delta_output: self.delta_output,
+        }
     }
 
     pub fn get_name(&self) -> Option<&str> {
@@ -85,15 +386,83 @@ where
     }
 
     pub fn resize_clips(&mut self, new_size: usize, value: F) {
-        self.get_clip().resize_frames(new_size, value);
-        self.get_delta_clip().resize_frames(new_size, value);
-        self.get_prev_clip().resize_frames(new_size, value);
+        if self.buffered {
+            self.with_buffers_mut(|buffers| buffers.resize(new_size, value));
+        }
     }
 
     pub fn add_padding_left(&mut self, padding_amount: usize) {
-        self.get_clip().add_padding_left(padding_amount);
-        self.get_delta_clip().add_padding_left(padding_amount);
-        self.get_prev_clip().add_padding_left(padding_amount);
+        self.source_offset += padding_amount;
+        if self.buffered {
+            self.with_buffers_mut(|buffers| buffers.add_padding_left(padding_amount));
+        }
+    }
+
+    pub fn is_buffered(&self) -> bool {
+        self.buffered
+    }
+
+    // Frees every buffer, keeping only what places the node's material on the
+    // timeline. Edits still update that placement
+    pub fn drop_buffers(&mut self) {
+        let sample_rate = self.source_clip.get_sample_rate();
+        let empty = AudioClip::with_capacity_and_rate(0, sample_rate);
+        self.with_buffers_mut(|buffers| {
+            buffers.wet_clip = buffers.wet_clip.as_ref().map(|_| empty.clone());
+            buffers.clip = Arc::new(empty.clone());
+            buffers.delta_clip = empty.clone();
+            buffers.prev_clip = empty;
+        });
+        self.buffered = false;
+        self.delta_range = None;
+    }
+
+    // Reallocates the buffers with just the node's own material. Inputs have
+    // to be mixed back in by the caller. On a failed read the buffers stay
+    // dropped
+    pub fn restore_buffers(&mut self) -> AudioResult<()> {
+        if self.buffered {
+            return Ok(());
+        }
+        let sample_rate = self.source_clip.get_sample_rate();
+        let clip_len = self.clip_len;
+        self.with_buffers_mut(|buffers| {
+            buffers.clip = Arc::new(AudioClip::with_capacity_and_rate(clip_len, sample_rate));
+            buffers.delta_clip = AudioClip::with_capacity_and_rate(clip_len, sample_rate);
+        });
+        self.buffered = true;
+        if let Err(err) = self.mix_source(1.0) {
+            self.drop_buffers();
+            return Err(err);
+        }
+        self.with_buffers_mut(|buffers| {
+            buffers.wet_clip = buffers
+                .wet_clip
+                .as_ref()
+                .map(|_| AudioClip::clone(&buffers.clip));
+        });
+        self.process();
+        self.commit_changes();
+        Ok(())
+    }
+
+    // Adds the node's own material falling in timeline frames
+    // `start..start + out.len()`, read straight from the source
+    pub fn read_source(&self, start: usize, out: &mut [F]) -> AudioResult<()> {
+        let (region_start, region_end) = self.get_region_bounds();
+        let from = region_start.max(start);
+        let to = region_end.min(start + out.len());
+        if from >= to {
+            return Ok(());
+        }
+        let source_start = self.source_region.0 + from - region_start;
+        let out = &mut out[from - start..to - start];
+        self.source_clip
+            .for_each_chunk(source_start, source_start + to - from, |offset, chunk| {
+                for (frame, source_frame) in out[offset..].iter_mut().zip(chunk) {
+                    *frame = frame.add_amp(*source_frame);
+                }
+            })
     }
 
     pub fn normalize_clip_bounds(&mut self, parent_node: &AudioNode<F>) -> (usize, usize) {
@@ -130,75 +499,187 @@ where
         (overlap_start, overlap_end)
     }
 
-    pub fn apply_delta(&mut self, parent_node: &AudioNode<F>) {
-        if let Some((overlap_start, overlap_end)) = self.get_absolute_delta_range() {
-            let child_clip_start = self.get_clip_start();
-            let parent_delta_start = parent_node.get_clip_start();
+    // Stand-in whose output is this node's pending delta. Running a linear
+    // edge on it gives exactly the change that edge makes to the child
+    pub fn delta_view(&self) -> AudioNode<F> {
+        let mut delta_node = self.view_at(self.clip_start);
+        delta_node.delta_output = true;
+        delta_node
+    }
+
+    // Drops every input mixed into the clip, leaving only the node's own material
+    pub fn reset_to_source(&mut self) -> AudioResult<()> {
+        self.with_buffers_mut(|buffers| buffers.clip_mut().get_frames_mut().fill(F::EQUILIBRIUM));
+        self.mix_source(1.0)
+    }
+
+    fn mix_source(&self, gain: f32) -> AudioResult<()> {
+        if !self.buffered {
+            return Ok(());
+        }
+        let (start, end) = self.source_region;
+        self.with_buffers_mut(|buffers| {
+            let frames = &mut buffers.clip_mut().get_frames_mut()[self.source_offset..];
+            self.source_clip
+                .for_each_chunk(start, end, |offset, chunk| {
+                    for (frame, source_frame) in frames[offset..].iter_mut().zip(chunk) {
+                        *frame = frame.add_amp(source_frame.scale_amp(gain));
+                    }
+                })
+        })
+    }
+
+    pub fn get_source_clip(&self) -> &ClipSource<F> {
+        &self.source_clip
+    }
 
-            let parent_delta = parent_node.get_delta_clip();
-            let mut child_clip = self.get_clip();
+    pub fn get_source_path(&self) -> Option<&Path> {
+        self.source_path.as_deref()
+    }
 
-            let child_samples: &mut [F] = child_clip.get_frames_mut();
-            let delta_samples: &[F] = parent_delta.get_frames_ref();
+    pub fn set_source_path(&mut self, path: Option<PathBuf>) {
+        self.source_path = path;
+    }
 
-            for i in overlap_start..overlap_end {
-                let delta_index = i - parent_delta_start;
-                let child_index = i - child_clip_start;
+    pub fn get_source_region(&self) -> (usize, usize) {
+        self.source_region
+    }
 
-                child_samples[child_index] =
-                    (child_samples[child_index].add_amp(delta_samples[delta_index])).into();
-            }
+    // Timeline frames the node's own material covers
+    pub fn get_region_bounds(&self) -> (usize, usize) {
+        let (start, end) = self.source_region;
+        let region_start = self.clip_start + self.source_offset;
+        (region_start, region_start + end - start)
+    }
+
+    // Trims the material to source frames `start..end`. Every source frame stays
+    // where it sits on the timeline, so trimming back out restores the original
+    pub fn set_source_region(&mut self, start: usize, end: usize) -> AudioResult<()> {
+        let (region_start, _) = self.get_region_bounds();
+        let timeline_start = (region_start + start) as i64 - self.source_region.0 as i64;
+        if start >= end || end > self.source_clip.get_length() || timeline_start < 0 {
+            return Err(AudioError::InvalidRegion {
+                start: start as i64,
+                end: end as i64,
+            });
+        }
+
+        self.mix_source(-1.0)?;
+        let timeline_start = timeline_start as usize;
+        if timeline_start < self.clip_start {
+            let padding_amount = self.clip_start - timeline_start;
+            self.add_padding_left(padding_amount);
+            self.set_clip_len(self.clip_len + padding_amount);
+            self.set_clip_start(timeline_start);
+        }
+        self.source_offset = timeline_start - self.clip_start;
+        self.source_region = (start, end);
+        let region_end = self.source_offset + end - start;
+        if region_end > self.clip_len {
+            self.resize_clips(region_end, F::EQUILIBRIUM);
+            self.set_clip_len(region_end);
         }
+        self.mix_source(1.0)?;
+
+        self.set_delta_range(Some((0, self.clip_len)));
+        Ok(())
     }
 
-    pub fn compute_delta(&self) {
-        if let Some((start, end)) = self.get_delta_range() {
-            let prev_clip = self.get_prev_clip();
-            let original_frames = prev_clip.get_frames_ref();
+    // Shifts the material under fixed region bounds: positive `frames` bring
+    // later source material into view
+    pub fn slip(&mut self, frames: i64) -> AudioResult<()> {
+        let (start, end) = self.source_region;
+        let (new_start, new_end) = (start as i64 + frames, end as i64 + frames);
+        if new_start < 0 || new_end > self.source_clip.get_length() as i64 {
+            return Err(AudioError::InvalidRegion {
+                start: new_start,
+                end: new_end,
+            });
+        }
 
-            let current_clip = self.get_clip();
-            let current_frames = current_clip.get_frames_ref();
+        self.mix_source(-1.0)?;
+        self.source_region = (new_start as usize, new_end as usize);
+        self.mix_source(1.0)?;
 
-            let mut delta_clip = self.get_delta_clip();
-            let delta_frames = delta_clip.get_frames_mut();
+        self.set_delta_range(Some((self.source_offset, self.source_offset + end - start)));
+        Ok(())
+    }
 
-            for i in start..end {
-                delta_frames[i] =
-                    current_frames[i].add_amp(original_frames[i].scale_amp(-1.0 as f32));
-            }
+    // Cuts the material at timeline frame `at`. This node keeps the part before
+    // it and the returned node, sharing the same source, plays the rest
+    pub fn split_off(&mut self, at: usize) -> AudioResult<AudioNode<F>> {
+        let (region_start, region_end) = self.get_region_bounds();
+        if at <= region_start || at >= region_end {
+            return Err(AudioError::InvalidSplit(at));
+        }
+
+        let (start, end) = self.source_region;
+        let cut = start + at - region_start;
+        let mut tail = AudioNode::from_source(self.source_clip.clone(), (cut, end), None)?;
+        tail.set_clip_start(at);
+        tail.set_source_path(self.source_path.clone());
+
+        self.mix_source(-1.0)?;
+        self.source_region = (start, cut);
+        self.mix_source(1.0)?;
+
+        self.set_delta_range(Some((self.source_offset, self.source_offset + end - start)));
+        Ok(tail)
+    }
+
+    pub fn compute_delta(&self) {
+        if !self.buffered {
+            return;
+        }
+        if let Some((start, end)) = self.get_delta_range() {
+            self.with_buffers_mut(|buffers| {
+                let NodeBuffers {
+                    clip,
+                    delta_clip,
+                    prev_clip,
+                    wet_clip,
+                } = buffers;
+
+                let original_frames = prev_clip.get_frames_ref();
+                let current_frames = wet_clip.as_ref().unwrap_or(&**clip).get_frames_ref();
+                let delta_frames = delta_clip.get_frames_mut();
+
+                for i in start..end {
+                    delta_frames[i] =
+                        current_frames[i].add_amp(original_frames[i].scale_amp(-1.0 as f32));
+                }
+            });
         }
     }
 
     pub fn commit_changes(&mut self) {
         self.set_delta_range(None);
-        self.get_delta_clip().reset_clip();
-
-        let mut prev_clip = self.get_prev_clip();
-        let current_clip = self.get_clip();
-
-        *prev_clip = current_clip.clone();
-    }
-
-    // pub fn with_effects(
-    //     clip: AudioClip<F>,
-    //     // effect_chain: AudioEffectChain<F>,
-    //     name: Option<&str>,
-    // ) -> Self {
-    //     let name = name.unwrap_or("default").to_string();
-    //     let mut audio_node = AudioNode {
-    //         name,
-    //         clip,
-    //         // effect_chain: Some(effect_chain),
-    //     };
-    //     audio_node.process();
-    //     audio_node
-    // }
-
-    // pub fn process(&mut self) {
-    //     if let Some(effect_chain) = &self.effect_chain {
-    //         effect_chain.apply(&mut self.clip);
-    //     }
-    // }
+
+        self.with_buffers_mut(|buffers| {
+            buffers.delta_clip.reset_clip();
+            buffers.prev_clip = buffers.output_clip().clone();
+        });
+    }
+
+    pub fn process(&mut self) {
+        let Some(effect_chain) = &mut self.effect_chain else {
+            return;
+        };
+        let processed = NodeBuffers::access(&self.buffers, |buffers| {
+            let NodeBuffers { clip, wet_clip, .. } = buffers;
+            let Some(wet_clip) = wet_clip else {
+                return false;
+            };
+            *wet_clip = AudioClip::clone(clip);
+            effect_chain.apply(wet_clip);
+            true
+        });
+        if processed {
+            // Effects like fades depend on position, so a change anywhere can
+            // alter the output anywhere: diff the whole clip
+            self.delta_range = Some((0, self.clip_len));
+        }
+    }
 }
 
 // ! --------------  Tests --------------
@@ -211,12 +692,12 @@ mod tests {
 
     // Helper functions
     fn create_mono_audio_node_with_samples(samples: Vec<f32>) -> AudioNode<Mono<f32>> {
-        let clip = AudioClip::<Mono<f32>>::new(samples, 44100);
+        let clip = AudioClip::<Mono<f32>>::new(samples, 44100).unwrap();
         AudioNode::new(clip, Some("mono_test_clip"))
     }
 
     fn create_stereo_audio_node_with_samples(samples: Vec<f32>) -> AudioNode<Stereo<f32>> {
-        let clip = AudioClip::<Stereo<f32>>::new(samples, 44100);
+        let clip = AudioClip::<Stereo<f32>>::new(samples, 44100).unwrap();
         AudioNode::new(clip, Some("stereo_test_clip"))
     }
 
@@ -225,7 +706,8 @@ mod tests {
     fn test_resize_clips_mono() {
         let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0, 3.0]);
         audio_node.resize_clips(5, [0.0]);
-        let clip_frames: Vec<_> = audio_node.get_clip().get_frames_ref().to_vec();
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
         assert_eq!(clip_frames, vec![[1.0], [2.0], [3.0], [0.0], [0.0]]);
     }
 
@@ -234,7 +716,8 @@ mod tests {
         let mut audio_node =
             create_stereo_audio_node_with_samples(vec![1.0, 1.0, 2.0, 2.0, 3.0, 3.0]);
         audio_node.resize_clips(5, [0.0, 0.0]);
-        let clip_frames: Vec<_> = audio_node.get_clip().get_frames_ref().to_vec();
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
         assert_eq!(
             clip_frames,
             vec![[1.0, 1.0], [2.0, 2.0], [3.0, 3.0], [0.0, 0.0], [0.0, 0.0]]
@@ -245,7 +728,8 @@ mod tests {
     fn test_add_padding_left_mono() {
         let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0, 3.0]);
         audio_node.add_padding_left(2);
-        let clip_frames: Vec<_> = audio_node.get_clip().get_frames_ref().to_vec();
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
         assert_eq!(clip_frames, vec![[0.0], [0.0], [1.0], [2.0], [3.0]]);
     }
 
@@ -254,13 +738,107 @@ mod tests {
         let mut audio_node =
             create_stereo_audio_node_with_samples(vec![1.0, 1.0, 2.0, 2.0, 3.0, 3.0]);
         audio_node.add_padding_left(2);
-        let clip_frames: Vec<_> = audio_node.get_clip().get_frames_ref().to_vec();
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
         assert_eq!(
             clip_frames,
             vec![[0.0, 0.0], [0.0, 0.0], [1.0, 1.0], [2.0, 2.0], [3.0, 3.0]]
         );
     }
 
+    #[test]
+    fn test_reset_to_source_after_padding() {
+        let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0]);
+        audio_node.add_padding_left(2);
+        audio_node.resize_clips(5, [0.0]);
+        audio_node.with_buffers_mut(|buffers| buffers.clip_mut().get_frames_mut().fill([7.0]));
+
+        audio_node.reset_to_source().unwrap();
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
+        assert_eq!(clip_frames, vec![[0.0], [0.0], [1.0], [2.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_source_region_keeps_timeline_position() {
+        let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0, 3.0, 4.0]);
+        audio_node.set_clip_start(3);
+        audio_node.set_source_region(2, 4).unwrap();
+        assert_eq!(audio_node.get_region_bounds(), (5, 7));
+
+        // Trimming back out past the clip start grows the clip to the left
+        audio_node.set_clip_start(0);
+        audio_node.set_source_region(2, 3).unwrap();
+        audio_node.set_source_region(0, 3).unwrap();
+        assert_eq!(audio_node.get_clip_start(), 0);
+        let clip_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
+        assert_eq!(clip_frames, vec![[1.0], [2.0], [3.0], [0.0]]);
+        assert!(audio_node.set_source_region(3, 3).is_err());
+    }
+
+    #[test]
+    fn test_split_off_shares_source() {
+        let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0, 3.0, 4.0]);
+        audio_node.set_clip_start(10);
+        let tail = audio_node.split_off(11).unwrap();
+
+        let head_frames: Vec<_> =
+            audio_node.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
+        let tail_frames: Vec<_> =
+            tail.with_buffers(|buffers| buffers.clip.get_frames_ref().to_vec());
+        assert_eq!(head_frames, vec![[1.0], [0.0], [0.0], [0.0]]);
+        assert_eq!(tail_frames, vec![[2.0], [3.0], [4.0]]);
+        assert_eq!(tail.get_clip_start(), 11);
+        assert_eq!(tail.get_source_region(), (1, 4));
+        assert!(audio_node.get_source_clip().ptr_eq(tail.get_source_clip()));
+        assert!(audio_node.split_off(10).is_err());
+    }
+
+    #[test]
+    fn test_clip_shares_source_until_written() {
+        let audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0]);
+        let ClipSource::Memory(source) = audio_node.get_source_clip().clone() else {
+            panic!("expected an in-memory source");
+        };
+        assert!(audio_node.with_buffers(|buffers| Arc::ptr_eq(&buffers.clip, &source)));
+
+        audio_node.with_buffers_mut(|buffers| buffers.clip_mut().get_frames_mut()[0] = [5.0]);
+        assert!(!audio_node.with_buffers(|buffers| Arc::ptr_eq(&buffers.clip, &source)));
+        assert_eq!(source.get_frames_ref(), &[[1.0], [2.0]]);
+    }
+
+    #[test]
+    fn test_delta_view_reads_delta_without_copying() {
+        let mut audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0]);
+        audio_node.with_buffers_mut(|buffers| buffers.clip_mut().get_frames_mut()[1] = [5.0]);
+        audio_node.set_delta_range(Some((0, 2)));
+        audio_node.compute_delta();
+
+        let delta_node = audio_node.delta_view();
+        assert!(Arc::ptr_eq(&audio_node.buffers, &delta_node.buffers));
+        let delta = delta_node.with_output(|clip| clip.get_frames_ref().to_vec());
+        assert_eq!(delta, vec![[0.0], [3.0]]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "locked again")]
+    fn test_reentrant_buffer_access_panics() {
+        let audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0]);
+        audio_node.with_buffers(|_| audio_node.with_output(|clip| clip.get_length()));
+    }
+
+    #[test]
+    fn test_buffers_outlive_a_panicking_accessor() {
+        let audio_node = create_mono_audio_node_with_samples(vec![1.0, 2.0]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            audio_node.with_buffers(|_| panic!("accessor failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(audio_node.with_output(|clip| clip.get_length()), 2);
+    }
+
     #[test]
     fn test_normalize_clip_bounds() {
         let mut child = create_mono_audio_node_with_samples(vec![1.0, 1.0, 1.0]);
@@ -1,18 +1,50 @@
-use super::audio_clip::{AudioClip, AudioClipEnum};
-use super::audio_edge::{AddOperation, AudioGraphEdge};
+use super::audio_clip::{
+    AudioClip, AudioClipEnum, Quad, Surround51, Surround71, DEFAULT_SAMPLE_RATE,
+};
+use super::audio_edge::{AddOperation, AudioGraphEdge, MixParams};
+use super::audio_effects::AudioEffectChain;
+use super::audio_error::{AudioError, AudioResult};
 use super::audio_graph::AudioGraph;
-use super::audio_node::AudioNode;
+use super::audio_node::{AudioNode, ClipSource};
+use super::export::{write_wav, BitDepth};
+use super::history::{EditCommand, History, Remap};
+use super::lazy_render::{LazyRender, RenderMode, BLOCK_LEN};
+use super::renderer::{render_channel, AudioRenderer, SnapshotPublisher};
+use super::resample::ResampleQuality;
+use super::session::{
+    Session, SessionEdge, SessionEffect, SessionNode, SessionSource, SESSION_VERSION,
+};
+use super::stream_clip::StreamClip;
+use super::transport::Transport;
+use super::util::load_clip;
 use crate::audio::audio_clip::AudioClipTrait;
 use dasp::frame::{Mono, Stereo};
 
 use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{EdgeRef, Topo};
+use petgraph::Direction;
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 pub struct AudioProcessor<F> {
     audio_graph: Arc<Mutex<AudioGraph<F>>>,
-    root_frame_idx: usize,
+    transport: Arc<Transport>,
+    publisher: Option<SnapshotPublisher<F>>,
     pub root_node_index: NodeIndex,
     sample_rate: u32,
+    resample_quality: ResampleQuality,
+    history: History<F>,
+    // How many edits are running inside a larger one. Only the outermost edit
+    // publishes the root, once it is done
+    edit_depth: usize,
+    // Frames of node outputs edited since the last publish. Rendering lazily,
+    // only what they reach of the root is rendered again
+    changed: Vec<(NodeIndex, (usize, usize))>,
+    render_mode: RenderMode,
+    // Scratch for blocks rendered lazily, kept so that reading the root stops
+    // allocating once it has seen the device's block size
+    lazy_block: Vec<F>,
 }
 
 impl<F> AudioProcessor<F>
@@ -20,288 +52,1910 @@ where
     F: dasp::Frame<Sample = f32> + Default + Copy,
 {
     pub fn new() -> Self {
-        let audio_graph = AudioGraph::new();
+        Self::with_sample_rate(DEFAULT_SAMPLE_RATE)
+    }
+
+    // Clips added to the processor are resampled to `sample_rate`, which should
+    // match the output device to avoid pitch-shifted playback
+    pub fn with_sample_rate(sample_rate: u32) -> Self {
+        let audio_graph = AudioGraph::with_sample_rate(sample_rate);
         let root_node_index = audio_graph.root;
         Self {
             audio_graph: Arc::new(Mutex::new(audio_graph)),
-            root_frame_idx: 0,
+            transport: Arc::new(Transport::new(sample_rate)),
+            publisher: None,
             root_node_index,
-            sample_rate: 44100,
+            sample_rate,
+            resample_quality: ResampleQuality::default(),
+            history: History::default(),
+            edit_depth: 0,
+            changed: Vec::new(),
+            render_mode: RenderMode::default(),
+            lazy_block: Vec::new(),
         }
     }
 
-    pub fn lock_audio_graph(&self) -> MutexGuard<AudioGraph<F>> {
-        self.audio_graph.lock().unwrap()
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
     }
 
-    pub fn get_node_or_root_sample(&mut self, node: Option<NodeIndex>) -> Option<F> {
-        self.root_frame_idx += 1;
-        let node_idx = node.unwrap_or(self.root_node_index);
-        let audio_graph = self.lock_audio_graph();
+    pub fn get_resample_quality(&self) -> ResampleQuality {
+        self.resample_quality
+    }
+
+    // Quality used when clips added from now on are converted to the processor's rate
+    pub fn set_resample_quality(&mut self, quality: ResampleQuality) {
+        self.resample_quality = quality;
+    }
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    // Switching to `Lazy` frees the buffers of every node. It fails, leaving
+    // the mode as it was, if a node has effects that cannot run a block at a
+    // time. Switching back rebuilds the buffers from the sources up, as costly
+    // as reconnecting every edge
+    pub fn set_render_mode(&mut self, mode: RenderMode) -> AudioResult<()> {
+        if mode == self.render_mode {
+            return Ok(());
+        }
+        if mode == RenderMode::Lazy {
+            let graph = self.lock_audio_graph()?;
+            for node_idx in graph.graph.node_indices() {
+                let node = graph.try_get_node(node_idx)?.lock()?;
+                Self::check_blockwise(node_idx, node.get_effect_chain())?;
+            }
+        }
+        self.render_mode = mode;
+
+        let graph = self.lock_audio_graph()?;
+        let mut order = Vec::new();
+        let mut topo = Topo::new(&graph.graph);
+        while let Some(node_idx) = topo.next(&graph.graph) {
+            order.push(node_idx);
+        }
+        for &node_idx in &order {
+            self.adopt_node(&mut *graph.try_get_node(node_idx)?.lock()?)?;
+            let has_inputs = graph
+                .graph
+                .edges_directed(node_idx, Direction::Incoming)
+                .next()
+                .is_some();
+            if has_inputs {
+                self.rebuild_node(&graph, node_idx)?;
+            }
+        }
+        for node_idx in order {
+            graph.try_get_node(node_idx)?.lock()?.commit_changes();
+        }
+        self.publish_root(&graph)
+    }
 
-        let root_node = audio_graph.get_node(node_idx).unwrap().lock().unwrap();
-        let root_clip = root_node.get_clip();
+    fn check_blockwise(
+        node_idx: NodeIndex,
+        effect_chain: Option<&AudioEffectChain<F>>,
+    ) -> AudioResult<()> {
+        match effect_chain {
+            Some(effect_chain) if !effect_chain.is_blockwise() => {
+                Err(AudioError::UnrenderableEffect(node_idx))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Brings a node entering the graph in line with the render mode
+    fn adopt_node(&self, node: &mut AudioNode<F>) -> AudioResult<()> {
+        match self.render_mode {
+            RenderMode::Cached => node.restore_buffers()?,
+            RenderMode::Lazy => node.drop_buffers(),
+        }
+        Ok(())
+    }
+
+    // Whether nodes hold their mixes, otherwise there is nothing to update on
+    // an edit beyond the graph itself
+    fn caches_mixes(&self) -> bool {
+        self.render_mode == RenderMode::Cached
+    }
+
+    pub fn lock_audio_graph(&self) -> AudioResult<MutexGuard<'_, AudioGraph<F>>> {
+        Ok(self.audio_graph.lock()?)
+    }
+
+    // Handle for control threads, e.g. a UI driving play/pause/seek while the
+    // output callback pulls audio
+    pub fn transport(&self) -> Arc<Transport> {
+        Arc::clone(&self.transport)
+    }
+
+    // Reads the frame under the play head and advances it regardless of the play state
+    pub fn get_node_or_root_sample(&mut self, node: Option<NodeIndex>) -> AudioResult<Option<F>> {
+        let node_idx = node.unwrap_or(self.root_node_index);
+        let audio_graph = self.lock_audio_graph()?;
+        if !self.caches_mixes() {
+            let mut render = LazyRender::new(&audio_graph);
+            let (extent_start, extent_end) = render.extent(node_idx)?;
+            let len = extent_end - extent_start;
+            let (start, count) = self.transport.next_segment(1, len);
+            if count == 0 {
+                return Ok(None);
+            }
+            let mut frame = [F::EQUILIBRIUM];
+            render.render(node_idx, extent_start + start, &mut frame)?;
+            self.transport.finish_segment(start, count, len);
+            return Ok(Some(frame[0]));
+        }
 
-        root_clip.get_frame(self.root_frame_idx - 1)
+        let root_node = audio_graph.try_get_node(node_idx)?.lock()?;
+        Ok(root_node.with_output(|root_clip| {
+            let (start, count) = self.transport.next_segment(1, root_clip.get_length());
+            if count == 0 {
+                return None;
+            }
+            self.transport
+                .finish_segment(start, count, root_clip.get_length());
+            root_clip.get_frame(start)
+        }))
     }
 
     pub fn set_root_frame_idx(&mut self, idx: usize) {
-        self.root_frame_idx = idx;
+        self.transport.seek(idx);
+    }
+
+    // Copies the next block of the root mix under a single lock and pads the
+    // rest with silence once the clip ends or while the transport is not
+    // playing. Returns the number of real frames. On an error the whole
+    // buffer is silent and the error is passed on, so a failing graph is not
+    // mistaken for the end of the clip
+    pub fn fill_buffer(&mut self, out: &mut [F]) -> AudioResult<usize> {
+        let result = self.read_root_block(out.len(), |i, frame| out[i] = *frame);
+        let written = *result.as_ref().unwrap_or(&0);
+        out[written..].fill(F::EQUILIBRIUM);
+        result
+    }
+
+    // Same as `fill_buffer` for interleaved device buffers, e.g. cpal's `&mut [f32]`
+    pub fn fill_interleaved(&mut self, out: &mut [f32]) -> AudioResult<usize> {
+        let channels = F::CHANNELS;
+        let result = self.read_root_block(out.len() / channels, |i, frame| {
+            let samples = &mut out[i * channels..(i + 1) * channels];
+            for (sample, value) in samples.iter_mut().zip(frame.channels()) {
+                *sample = value;
+            }
+        });
+        let written = *result.as_ref().unwrap_or(&0);
+        out[written * channels..].fill(0.0);
+        result
+    }
+
+    fn read_root_block(&mut self, len: usize, write: impl FnMut(usize, &F)) -> AudioResult<usize> {
+        if !self.transport.is_playing() {
+            return Ok(0);
+        }
+        let audio_graph = self.audio_graph.lock()?;
+        if !self.caches_mixes() {
+            return Self::read_lazy_block(
+                &audio_graph,
+                &self.transport,
+                &mut self.lazy_block,
+                len,
+                write,
+            );
+        }
+        let root_node = audio_graph.try_get_node(audio_graph.root)?.lock()?;
+        Ok(root_node.with_output(|root_clip| {
+            self.transport
+                .read_block(root_clip.get_frames_ref(), len, write)
+        }))
+    }
+
+    // `Transport::read_block` for a root that only exists as the frames asked for
+    fn read_lazy_block(
+        audio_graph: &AudioGraph<F>,
+        transport: &Transport,
+        block: &mut Vec<F>,
+        len: usize,
+        mut write: impl FnMut(usize, &F),
+    ) -> AudioResult<usize> {
+        let mut render = LazyRender::new(audio_graph);
+        let (_, root_len) = render.extent(audio_graph.root)?;
+        if block.len() < len {
+            block.resize(len, F::EQUILIBRIUM);
+        }
+        let mut written = 0;
+        while written < len {
+            let (start, count) = transport.next_segment(len - written, root_len);
+            if count == 0 {
+                break;
+            }
+            render.render(audio_graph.root, start, &mut block[..count])?;
+            for (i, frame) in block[..count].iter().enumerate() {
+                write(written + i, frame);
+            }
+            transport.finish_segment(start, count, root_len);
+            written += count;
+        }
+        Ok(written)
+    }
+
+    // Output of a node (the root mix by default) over timeline frames
+    // `start..start + out.len()`, silent where the node has nothing
+    pub fn render_block(
+        &self,
+        node: Option<NodeIndex>,
+        start: usize,
+        out: &mut [F],
+    ) -> AudioResult<()> {
+        let audio_graph = self.lock_audio_graph()?;
+        let node_idx = node.unwrap_or(audio_graph.root);
+        if !self.caches_mixes() {
+            return LazyRender::new(&audio_graph).render(node_idx, start, out);
+        }
+
+        let audio_node = audio_graph.try_get_node(node_idx)?.lock()?;
+        let clip_start = audio_node.get_clip_start();
+        audio_node.with_output(|output_clip| {
+            let frames = output_clip.get_frames_ref();
+            for (i, frame) in out.iter_mut().enumerate() {
+                *frame = (start + i)
+                    .checked_sub(clip_start)
+                    .and_then(|idx| frames.get(idx))
+                    .copied()
+                    .unwrap_or(F::EQUILIBRIUM);
+            }
+        });
+        Ok(())
+    }
+
+    // Real-time side of the processor. It plays the latest published mix of the
+    // root and never touches the graph locks, so edits here cannot stall it.
+    // Creating a new renderer detaches the previous one from further updates
+    pub fn create_renderer(&mut self) -> AudioResult<AudioRenderer<F>> {
+        let frames = self.root_output_frames(&*self.lock_audio_graph()?)?;
+        let (publisher, renderer) = render_channel(frames, self.transport());
+        self.publisher = Some(publisher);
+        Ok(renderer)
+    }
+
+    fn root_output_frames(&self, audio_graph: &AudioGraph<F>) -> AudioResult<Vec<F>> {
+        self.output_frames(audio_graph, audio_graph.root)
+    }
+
+    // Whole output of a node. Rendering lazily, this is the one full-length
+    // buffer, built a block at a time
+    fn output_frames(
+        &self,
+        audio_graph: &AudioGraph<F>,
+        node_idx: NodeIndex,
+    ) -> AudioResult<Vec<F>> {
+        if self.caches_mixes() {
+            let audio_node = audio_graph.try_get_node(node_idx)?.lock()?;
+            return Ok(audio_node.with_output(|output_clip| output_clip.get_frames_ref().to_vec()));
+        }
+
+        let mut render = LazyRender::new(audio_graph);
+        let (start, end) = render.extent(node_idx)?;
+        let mut frames = vec![F::EQUILIBRIUM; end - start];
+        for (i, block) in frames.chunks_mut(BLOCK_LEN).enumerate() {
+            render.render(node_idx, start + i * BLOCK_LEN, block)?;
+        }
+        Ok(frames)
+    }
+
+    fn publish_root(&self, audio_graph: &AudioGraph<F>) -> AudioResult<()> {
+        if let Some(publisher) = &self.publisher {
+            publisher.publish(self.root_output_frames(audio_graph)?);
+        }
+        Ok(())
+    }
+
+    // Records a finished edit. Unless it is part of a larger one, the renderer
+    // gets the new mix too. A failed publish leaves the edit applied and
+    // recorded, so it can still be undone
+    fn record(&mut self, command: EditCommand<F>) -> AudioResult<()> {
+        self.history.record(command);
+        self.publish_edit()
+    }
+
+    // What did not change is copied from the mix the renderer already has. If
+    // publishing fails, the changes are kept for the next try
+    fn publish_edit(&mut self) -> AudioResult<()> {
+        if self.edit_depth > 0 {
+            return Ok(());
+        }
+        let changed = std::mem::take(&mut self.changed);
+        let published = self.publish_changes(&changed);
+        if published.is_err() {
+            self.changed = changed;
+        }
+        published
+    }
+
+    fn publish_changes(&self, changed: &[(NodeIndex, (usize, usize))]) -> AudioResult<()> {
+        let Some(publisher) = &self.publisher else {
+            return Ok(());
+        };
+        let audio_graph = self.lock_audio_graph()?;
+        if self.caches_mixes() {
+            return self.publish_root(&audio_graph);
+        }
+
+        let root = audio_graph.root;
+        let mut render = LazyRender::new(&audio_graph);
+        let (start, end) = render.extent(root)?;
+        let mut frames = publisher.latest().to_vec();
+        frames.resize(end - start, F::EQUILIBRIUM);
+        for (from, to) in render.reach(root, changed)? {
+            let (from, to) = (from.max(start), to.min(end));
+            if from >= to {
+                continue;
+            }
+            for (i, block) in frames[from - start..to - start]
+                .chunks_mut(BLOCK_LEN)
+                .enumerate()
+            {
+                render.render(root, from + i * BLOCK_LEN, block)?;
+            }
+        }
+        publisher.publish(frames);
+        Ok(())
+    }
+
+    // Frames of the child the edge's contribution covers, or all of the child
+    // if the edge is soloed and so silences the others. Nothing is noted when
+    // the root is cached in full
+    fn edge_change(
+        &self,
+        audio_graph: &AudioGraph<F>,
+        edge_idx: EdgeIndex,
+    ) -> AudioResult<Option<(NodeIndex, (usize, usize))>> {
+        if self.caches_mixes() {
+            return Ok(None);
+        }
+        let (parent_idx, child_idx) = audio_graph.get_edge_endpoints(edge_idx)?;
+        let edge = audio_graph.try_get_edge_ref(edge_idx)?;
+        let solo = edge
+            .operation
+            .as_mix()
+            .is_some_and(|mix| mix.get_params().solo);
+        let mut render = LazyRender::new(audio_graph);
+        let range = if solo {
+            render.extent(child_idx)?
+        } else {
+            render.placement(parent_idx, edge.get_offset())?
+        };
+        Ok(Some((child_idx, range)))
+    }
+
+    // Runs `edit` as part of one larger edit, publishing once at the end even
+    // if it failed partway
+    fn nested_edit<T>(&mut self, edit: impl FnOnce(&mut Self) -> AudioResult<T>) -> AudioResult<T> {
+        self.edit_depth += 1;
+        let result = edit(self);
+        self.edit_depth -= 1;
+        let published = self.publish_edit();
+        let value = result?;
+        published?;
+        Ok(value)
     }
 
-    //Todo Return result if connection is valid or invalid
-    // Example, connection already exists or creates a cycle
+    // Offline bounce of a node's output (the root mix by default), no device needed
+    pub fn render_to_wav<P: AsRef<Path>>(
+        &self,
+        node: Option<NodeIndex>,
+        path: P,
+        bit_depth: BitDepth,
+    ) -> AudioResult<()> {
+        let frames = {
+            let audio_graph = self.lock_audio_graph()?;
+            let node_idx = node.unwrap_or(audio_graph.root);
+            self.output_frames(&audio_graph, node_idx)?
+        };
+        write_wav(path, &frames, self.sample_rate, bit_depth)
+    }
+
+    // Fails without touching any clip if the edge is a self-loop, duplicate,
+    // starts at the root or would close a cycle. If mixing it in fails, the
+    // edge is taken out again
     pub fn connect(
         &mut self,
         parent: NodeIndex,
         child: Option<NodeIndex>,
         edge: AudioGraphEdge<F>,
-    ) -> EdgeIndex {
-        let mut graph = self.lock_audio_graph();
-        let edge_id = graph.connect(parent, child, edge).unwrap();
+    ) -> AudioResult<EdgeIndex> {
+        let template = edge.duplicate();
+        let (edge_id, child_node_index, change) = {
+            let mut graph = self.lock_audio_graph()?;
+            Self::check_placement(&graph, parent, edge.get_offset())?;
+            let edge_id = graph.connect(parent, child, edge)?;
 
-        let child_node_index = child.unwrap_or(graph.root);
-        self.apply_effect(&mut graph, parent, child_node_index, edge_id);
-        self.propagate_change(&mut graph, parent);
-        edge_id
+            let child_node_index = child.unwrap_or(graph.root);
+            if let Err(err) = self.mix_in_edge(&mut graph, parent, child_node_index, edge_id) {
+                self.restore_child(&mut graph, child_node_index, |graph| {
+                    let _ = graph.disconnect(edge_id);
+                });
+                return Err(err);
+            }
+            let change = self.edge_change(&graph, edge_id)?;
+            (edge_id, child_node_index, change)
+        };
+        self.changed.extend(change);
+        self.record(EditCommand::Connect {
+            edge_idx: edge_id,
+            parent,
+            child: child_node_index,
+            edge: template,
+        })?;
+        Ok(edge_id)
     }
 
-    pub fn apply_effect(
+    fn mix_in_edge(
         &self,
         audio_graph: &mut AudioGraph<F>,
         parent_idx: NodeIndex,
         child_idx: NodeIndex,
         edge_idx: EdgeIndex,
+    ) -> AudioResult<()> {
+        let rebuild = audio_graph.has_nonlinear_inputs(child_idx);
+        let remixed = self.resolve_solo(audio_graph, child_idx, Some(edge_idx), rebuild)?;
+        if rebuild {
+            self.rebuild_node(audio_graph, child_idx)?;
+        } else {
+            self.apply_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+        }
+        self.propagate_child_edit(audio_graph, child_idx, remixed || rebuild)
+    }
+
+    // Puts the graph back with `restore` after an edit failed partway through
+    // re-mixing the child, then rebuilds the child and everything below it to
+    // match. The caller reports the edit's own error
+    fn restore_child(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        child_idx: NodeIndex,
+        restore: impl FnOnce(&mut AudioGraph<F>),
     ) {
-        let parent_node = audio_graph
-            .get_node(parent_idx)
-            .expect("Parent node not found")
-            .lock()
-            .unwrap();
+        restore(audio_graph);
+        let rebuilt = self
+            .resolve_solo(audio_graph, child_idx, None, true)
+            .and_then(|_| self.rebuild_downstream(audio_graph, child_idx));
+        if let Err(err) = rebuilt {
+            log::warn!("failed to restore node {:?}: {}", child_idx, err);
+        }
+    }
 
-        let effect = audio_graph.get_edge_ref(edge_idx).unwrap();
+    pub fn set_edge_offset(&mut self, edge_idx: EdgeIndex, offset: i64) -> AudioResult<()> {
+        let (old, changes) = {
+            let mut graph = self.lock_audio_graph()?;
+            let (parent_idx, child_idx) = graph.get_edge_endpoints(edge_idx)?;
+            Self::check_placement(&graph, parent_idx, offset)?;
+            let old = graph.try_get_edge_ref(edge_idx)?.get_offset();
+            let before = self.edge_change(&graph, edge_idx)?;
 
-        let mut child_node = audio_graph
-            .get_node(child_idx)
-            .expect("Target node not found")
-            .lock()
-            .unwrap();
+            let placed = self.place_edge(&mut graph, parent_idx, child_idx, edge_idx, offset);
+            if let Err(err) = placed {
+                self.restore_child(&mut graph, child_idx, |graph| {
+                    if let Some(edge) = graph.get_edge_mut(edge_idx) {
+                        edge.set_offset(old);
+                    }
+                });
+                return Err(err);
+            }
+            (old, [before, self.edge_change(&graph, edge_idx)?])
+        };
+        self.changed.extend(changes.into_iter().flatten());
+        self.record(EditCommand::SetEdgeOffset {
+            edge_idx,
+            old,
+            new: offset,
+        })?;
+        Ok(())
+    }
 
-        child_node.normalize_clip_bounds(&*parent_node);
+    fn place_edge(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        parent_idx: NodeIndex,
+        child_idx: NodeIndex,
+        edge_idx: EdgeIndex,
+        offset: i64,
+    ) -> AudioResult<()> {
+        let rebuild = audio_graph.has_nonlinear_inputs(child_idx);
+        if !rebuild {
+            self.revert_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+        }
+        if let Some(edge) = audio_graph.get_edge_mut(edge_idx) {
+            edge.set_offset(offset);
+        }
+        if rebuild {
+            self.rebuild_node(audio_graph, child_idx)?;
+        } else {
+            self.apply_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+        }
+        // The old and new placement can be far apart, diff the whole child
+        self.propagate_child_edit(audio_graph, child_idx, true)
+    }
 
-        effect.operation.apply(&*parent_node, &*child_node);
+    pub fn set_edge_offset_seconds(
+        &mut self,
+        edge_idx: EdgeIndex,
+        seconds: f64,
+    ) -> AudioResult<()> {
+        let offset = (seconds * self.sample_rate as f64).round() as i64;
+        self.set_edge_offset(edge_idx, offset)
     }
 
-    pub fn propagate_change(&self, audio_graph: &mut AudioGraph<F>, node_idx: NodeIndex) {
-        let to_compute = audio_graph.collect_dependents(node_idx);
-        let mut last_child_node = None;
+    fn check_placement(
+        audio_graph: &AudioGraph<F>,
+        parent_idx: NodeIndex,
+        offset: i64,
+    ) -> AudioResult<()> {
+        let parent_start = audio_graph
+            .try_get_node(parent_idx)?
+            .lock()?
+            .get_clip_start();
+        if parent_start as i64 + offset < 0 {
+            return Err(AudioError::NegativePlacement {
+                node: parent_idx,
+                offset,
+            });
+        }
+        Ok(())
+    }
 
-        for (parent, child, _edge) in to_compute {
-            let mut parent_node = audio_graph
-                .get_node(parent)
-                .expect("Parent node not found")
-                .lock()
-                .unwrap();
+    pub fn set_mix_params(&mut self, edge_idx: EdgeIndex, params: MixParams) -> AudioResult<()> {
+        self.update_mix_params(edge_idx, |current| *current = params)
+    }
 
-            let mut child_node = audio_graph
-                .get_node(child)
-                .expect("Child node not found")
-                .lock()
-                .unwrap();
+    // Swaps the edge's old contribution for one with the new parameters. Only
+    // the child is re-mixed, the rest follows through delta propagation
+    pub fn update_mix_params(
+        &mut self,
+        edge_idx: EdgeIndex,
+        update: impl FnOnce(&mut MixParams),
+    ) -> AudioResult<()> {
+        let (old, params, changes) = {
+            let mut graph = self.lock_audio_graph()?;
+            let (parent_idx, child_idx) = graph.get_edge_endpoints(edge_idx)?;
+            let old = graph
+                .try_get_edge_ref(edge_idx)?
+                .operation
+                .as_mix()
+                .ok_or(AudioError::NotAMixEdge(edge_idx))?
+                .get_params();
+            let mut params = old;
+            update(&mut params);
+            let before = self.edge_change(&graph, edge_idx)?;
 
-            child_node.normalize_clip_bounds(&*parent_node);
-            child_node.apply_delta(&*parent_node);
-            child_node.compute_delta();
-            parent_node.commit_changes();
+            let remixed = self.remix_edge(&mut graph, parent_idx, child_idx, edge_idx, params);
+            if let Err(err) = remixed {
+                self.restore_child(&mut graph, child_idx, |graph| {
+                    Self::set_params(graph, edge_idx, old);
+                });
+                return Err(err);
+            }
+            (old, params, [before, self.edge_change(&graph, edge_idx)?])
+        };
+        self.changed.extend(changes.into_iter().flatten());
+        self.record(EditCommand::SetMixParams {
+            edge_idx,
+            old,
+            new: params,
+        })?;
+        Ok(())
+    }
 
-            last_child_node = Some(child);
+    fn remix_edge(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        parent_idx: NodeIndex,
+        child_idx: NodeIndex,
+        edge_idx: EdgeIndex,
+        params: MixParams,
+    ) -> AudioResult<()> {
+        let rebuild = audio_graph.has_nonlinear_inputs(child_idx);
+        if !rebuild {
+            self.revert_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+        }
+        Self::set_params(audio_graph, edge_idx, params);
+        let remixed = self.resolve_solo(audio_graph, child_idx, Some(edge_idx), rebuild)?;
+        if rebuild {
+            self.rebuild_node(audio_graph, child_idx)?;
+        } else {
+            self.apply_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
         }
+        self.propagate_child_edit(audio_graph, child_idx, remixed || rebuild)
+    }
 
-        if let Some(child_idx) = last_child_node {
-            audio_graph
-                .get_node(child_idx)
-                .expect("Child node not found")
-                .lock()
-                .unwrap()
-                .commit_changes();
+    fn set_params(audio_graph: &mut AudioGraph<F>, edge_idx: EdgeIndex, params: MixParams) {
+        if let Some(mix) = audio_graph
+            .get_edge_mut(edge_idx)
+            .and_then(|edge| edge.operation.as_mix_mut())
+        {
+            mix.set_params(params);
         }
     }
 
-    pub fn add_node(&mut self, node: AudioNode<F>) -> NodeIndex {
-        self.lock_audio_graph().add_data_node(node)
+    // Rebuilds a node from its own material and every input, for nodes that
+    // cannot take deltas. Linear inputs are summed first, then non-linear ones
+    // are applied in the order they were connected
+    fn rebuild_node(&self, audio_graph: &AudioGraph<F>, node_idx: NodeIndex) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
+        }
+        let mut incoming: Vec<(bool, EdgeIndex, NodeIndex)> = audio_graph
+            .graph
+            .edges_directed(node_idx, Direction::Incoming)
+            .map(|edge| {
+                (
+                    !edge.weight().operation.is_linear(),
+                    edge.id(),
+                    edge.source(),
+                )
+            })
+            .collect();
+        incoming.sort();
+
+        let mut node = audio_graph.try_get_node(node_idx)?.lock()?;
+        node.reset_to_source()?;
+        for (_, edge_idx, parent_idx) in incoming {
+            let effect = audio_graph.try_get_edge_ref(edge_idx)?;
+            let unplaced_parent = audio_graph.try_get_node(parent_idx)?.lock()?;
+            let parent = effect.place_parent(&unplaced_parent);
+            node.normalize_clip_bounds(&parent);
+            effect.apply(&parent, &node);
+        }
+        node.process();
+
+        let clip_len = node.get_clip_len();
+        node.set_delta_range(Some((0, clip_len)));
+        Ok(())
     }
 
-    fn get_node_frames_copy(&self, node_index: NodeIndex) -> Vec<F> {
-        let graph = self.lock_audio_graph();
-        let node = graph.get_node(node_index).unwrap().lock().unwrap();
-        let x = node.get_clip().get_frames_ref().clone().to_vec();
-        x
+    // Silences the mix edges into `child` that lost to a solo and restores the
+    // ones that no longer do. `pending` is an edge whose contribution is not in
+    // the child right now, so only its flag is updated. A child that gets
+    // rebuilt picks the flags up from there. Returns whether any other edge was
+    // re-mixed
+    fn resolve_solo(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        child_idx: NodeIndex,
+        pending: Option<EdgeIndex>,
+        rebuild: bool,
+    ) -> AudioResult<bool> {
+        let incoming: Vec<(EdgeIndex, NodeIndex)> = audio_graph
+            .graph
+            .edges_directed(child_idx, Direction::Incoming)
+            .map(|edge| (edge.id(), edge.source()))
+            .collect();
+        let any_solo = incoming.iter().any(|(edge_idx, _)| {
+            audio_graph.graph[*edge_idx]
+                .operation
+                .as_mix()
+                .is_some_and(|mix| mix.get_params().solo)
+        });
+
+        let mut remixed = false;
+        for (edge_idx, parent_idx) in incoming {
+            let Some(mix) = audio_graph.graph[edge_idx].operation.as_mix() else {
+                continue;
+            };
+            let silenced = any_solo && !mix.get_params().solo;
+            if Some(edge_idx) == pending || rebuild {
+                Self::set_solo_silenced(audio_graph, edge_idx, silenced);
+                continue;
+            }
+            if mix.is_solo_silenced() == silenced {
+                continue;
+            }
+            self.revert_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+            Self::set_solo_silenced(audio_graph, edge_idx, silenced);
+            self.apply_effect(audio_graph, parent_idx, child_idx, edge_idx)?;
+            remixed = true;
+        }
+        Ok(remixed)
     }
 
-    fn print_graph(&self) {
-        self.lock_audio_graph().print_graph();
+    fn set_solo_silenced(audio_graph: &mut AudioGraph<F>, edge_idx: EdgeIndex, silenced: bool) {
+        if let Some(mix) = audio_graph
+            .get_edge_mut(edge_idx)
+            .and_then(|edge| edge.operation.as_mix_mut())
+        {
+            mix.set_solo_silenced(silenced);
+        }
     }
-}
 
-impl AudioProcessor<Mono<f32>> {
-    pub fn add_node_from_clip(&mut self, clip: AudioClipEnum, name: Option<&str>) -> NodeIndex {
-        let mut clip = match clip {
-            AudioClipEnum::Mono(clip) => clip,
-            AudioClipEnum::Stereo(clip) => clip.to_mono(),
-        };
+    // Diffs the child against its committed state and pushes that down to the
+    // root. Re-mixing several inputs can reach past the last edge's overlap,
+    // hence `whole_clip`
+    fn propagate_child_edit(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        child_idx: NodeIndex,
+        whole_clip: bool,
+    ) -> AudioResult<()> {
+        {
+            let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
+            if whole_clip {
+                let clip_len = child_node.get_clip_len();
+                child_node.set_delta_range(Some((0, clip_len)));
+            }
+            child_node.compute_delta();
+        }
+        self.propagate_change(audio_graph, child_idx)
+    }
 
-        if self.sample_rate != clip.get_sample_rate() {
-            clip = clip.resample(self.sample_rate);
+    pub fn apply_effect(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        parent_idx: NodeIndex,
+        child_idx: NodeIndex,
+        edge_idx: EdgeIndex,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
         }
+        let effect = audio_graph.try_get_edge_ref(edge_idx)?;
+        let unplaced_parent = audio_graph.try_get_node(parent_idx)?.lock()?;
+        let parent_node = effect.place_parent(&unplaced_parent);
+
+        let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
+
+        child_node.normalize_clip_bounds(&parent_node);
 
-        let audio_node = AudioNode::new(clip, name);
-        self.lock_audio_graph().add_data_node(audio_node)
+        effect.operation.apply(&parent_node, &child_node);
+        child_node.process();
+        Ok(())
     }
-}
 
-impl AudioProcessor<Stereo<f32>> {
-    pub fn add_node_from_clip(&mut self, clip: AudioClipEnum, name: Option<&str>) -> NodeIndex {
-        let mut clip = match clip {
-            AudioClipEnum::Mono(clip) => clip.to_stereo(),
-            AudioClipEnum::Stereo(clip) => clip,
+    // Removes the edge and rebuilds the child and everything below it without
+    // the parent's contribution
+    pub fn disconnect(&mut self, edge_idx: EdgeIndex) -> AudioResult<AudioGraphEdge<F>> {
+        let (edge, parent, child, change) = {
+            let mut graph = self.lock_audio_graph()?;
+            let (parent, child) = graph.get_edge_endpoints(edge_idx)?;
+            let change = self.edge_change(&graph, edge_idx)?;
+            (
+                self.disconnect_edge(&mut graph, edge_idx)?,
+                parent,
+                child,
+                change,
+            )
         };
+        self.changed.extend(change);
+        self.record(EditCommand::Disconnect {
+            edge_idx,
+            parent,
+            child,
+            edge: edge.duplicate(),
+        })?;
+        Ok(edge)
+    }
 
-        if self.sample_rate != clip.get_sample_rate() {
-            clip = clip.resample(self.sample_rate);
-        }
+    // Outgoing edges go first so their removal reaches the root. Incoming ones
+    // only fed the node, but are disconnected too so undo can restore them
+    pub fn remove_node(&mut self, node_idx: NodeIndex) -> AudioResult<()> {
+        self.record_group(|processor| {
+            let edges: Vec<EdgeIndex> = {
+                let graph = processor.lock_audio_graph()?;
+                if node_idx == graph.root {
+                    return Err(AudioError::RootRemoval);
+                }
+                graph.try_get_node(node_idx)?;
+                [Direction::Outgoing, Direction::Incoming]
+                    .into_iter()
+                    .flat_map(|direction| graph.graph.edges_directed(node_idx, direction))
+                    .map(|edge| edge.id())
+                    .collect()
+            };
+            for edge_idx in edges {
+                processor.disconnect(edge_idx)?;
+            }
 
-        let audio_node = AudioNode::new(clip, name);
-        self.lock_audio_graph().add_data_node(audio_node)
+            let node = processor.lock_audio_graph()?.remove_data_node(node_idx)?;
+            processor.record(EditCommand::RemoveNode { node_idx, node })?;
+            Ok(())
+        })
     }
-}
-
-#[cfg(test)]
-mod tests {
 
-    use super::*;
-    use dasp::frame::Mono;
+    // Subtracting the parent back out would leave rounding behind in the child
+    // and in every delta below it. Rebuilding them instead leaves exactly the
+    // mix the edge was never part of
+    fn disconnect_edge(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        edge_idx: EdgeIndex,
+    ) -> AudioResult<AudioGraphEdge<F>> {
+        let (parent_idx, child_idx) = audio_graph.get_edge_endpoints(edge_idx)?;
+        let edge = audio_graph.disconnect(edge_idx)?;
+        let rebuilt = self
+            .resolve_solo(audio_graph, child_idx, None, true)
+            .and_then(|_| self.rebuild_downstream(audio_graph, child_idx));
+        if let Err(err) = rebuilt {
+            // The graph hands the freed index straight back, so the edge
+            // returns under the index it had
+            self.restore_child(audio_graph, child_idx, |graph| {
+                let _ = graph.connect(parent_idx, Some(child_idx), edge);
+            });
+            return Err(err);
+        }
+        Ok(edge)
+    }
 
-    fn create_simple_clip() -> AudioClip<Mono<f32>> {
-        let samples: Vec<f32> = (1..=3).map(|x| x as f32).collect();
-        AudioClip::<Mono<f32>>::new(samples, 44100)
+    // Rebuilds the node and its dependents in order, so each one is mixed from
+    // parents that are already up to date
+    fn rebuild_downstream(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        node_idx: NodeIndex,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
+        }
+        let order = audio_graph.dependents_in_order(node_idx);
+        for &node in &order {
+            self.rebuild_node(audio_graph, node)?;
+        }
+        for node in order {
+            audio_graph.try_get_node(node)?.lock()?.commit_changes();
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_two_nodes_to_root() {
-        let mut processor = AudioProcessor::<Mono<f32>>::new();
+    pub fn revert_effect(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        parent_idx: NodeIndex,
+        child_idx: NodeIndex,
+        edge_idx: EdgeIndex,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
+        }
+        let effect = audio_graph.try_get_edge_ref(edge_idx)?;
+        let unplaced_parent = audio_graph.try_get_node(parent_idx)?.lock()?;
+        let parent_node = effect.place_parent(&unplaced_parent);
 
-        let node1 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"));
-        let node2 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"));
+        let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node1, None, add_edge);
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node2, None, add_edge);
+        child_node.normalize_clip_bounds(&parent_node);
 
-        let expected_samples = [[2.0], [4.0], [6.0]];
+        effect.operation.revert(&parent_node, &child_node);
+        child_node.process();
+        // Unlike `apply_effect`, propagation starts at the child, so its delta
+        // has to be ready before `propagate_change` runs
+        child_node.compute_delta();
+        Ok(())
+    }
 
-        for expected in &expected_samples {
-            let sample = processor
-                .get_node_or_root_sample(None)
-                .expect("Expected a sample");
-            assert_eq!(sample, *expected);
+    // Pushes the node's delta through everything downstream of it. Nodes are
+    // visited after all of their parents, so a node fed along several paths
+    // takes every parent's delta before its own is computed, exactly once
+    pub fn propagate_change(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        node_idx: NodeIndex,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
+        }
+        let order = audio_graph.dependents_in_order(node_idx);
+
+        for (position, &child) in order.iter().enumerate().skip(1) {
+            if audio_graph.has_nonlinear_inputs(child) {
+                self.rebuild_node(audio_graph, child)?;
+            } else {
+                let updated = &order[..position];
+                self.apply_parent_deltas(audio_graph, child, updated)?;
+            }
+            audio_graph.try_get_node(child)?.lock()?.compute_delta();
+        }
+
+        // Deltas are only cleared once every child has read them
+        for node in order {
+            audio_graph.try_get_node(node)?.lock()?.commit_changes();
         }
+        Ok(())
     }
 
-    #[test]
-    fn test_audio_processor_complex_graph() {
-        let mut processor = AudioProcessor::<Mono<f32>>::new();
+    // Runs the edge from each of the child's `updated` parents on that parent's
+    // delta and re-runs the child's effects. The child's delta range ends up
+    // covering every parent's overlap
+    fn apply_parent_deltas(
+        &self,
+        audio_graph: &AudioGraph<F>,
+        child_idx: NodeIndex,
+        updated: &[NodeIndex],
+    ) -> AudioResult<()> {
+        let incoming: Vec<(NodeIndex, EdgeIndex)> = audio_graph
+            .graph
+            .edges_directed(child_idx, Direction::Incoming)
+            .filter(|edge| updated.contains(&edge.source()))
+            .map(|edge| (edge.source(), edge.id()))
+            .collect();
 
-        let node1 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"));
-        let node2 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"));
-        let node3 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"));
-        let node4 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node4"));
+        let mut changed: Option<(usize, usize)> = None;
+        for (parent_idx, edge_idx) in incoming {
+            let effect = audio_graph.try_get_edge_ref(edge_idx)?;
+            let unplaced_parent = audio_graph.try_get_node(parent_idx)?.lock()?;
+            let parent_node = effect.place_parent(&unplaced_parent);
+            let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node1, Some(node3), add_edge);
-        let frames = processor.get_node_frames_copy(node3);
-        let expected_frames = vec![[2.0], [4.0], [6.0]];
-        assert_eq!(frames, expected_frames);
+            let (start, end) = child_node.normalize_clip_bounds(&parent_node);
+            effect
+                .operation
+                .apply(&parent_node.delta_view(), &child_node);
+            changed = Some(match changed {
+                Some((changed_start, changed_end)) => {
+                    (changed_start.min(start), changed_end.max(end))
+                }
+                None => (start, end),
+            });
+        }
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node2, Some(node3), add_edge);
-        let frames = processor.get_node_frames_copy(node3);
-        let expected_frames = vec![[3.0], [6.0], [9.0]];
-        assert_eq!(frames, expected_frames);
+        let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
+        let clip_start = child_node.get_clip_start();
+        child_node
+            .set_delta_range(changed.map(|(start, end)| (start - clip_start, end - clip_start)));
+        child_node.process();
+        Ok(())
+    }
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node3, Some(node4), add_edge);
-        let frames = processor.get_node_frames_copy(node4);
-        let expected_frames = vec![[4.0], [8.0], [12.0]];
-        assert_eq!(frames, expected_frames);
+    pub fn set_effect_chain(
+        &mut self,
+        node_idx: NodeIndex,
+        effect_chain: Option<AudioEffectChain<F>>,
+    ) -> AudioResult<()> {
+        let mut chain = effect_chain;
+        self.swap_effect_chain(node_idx, &mut chain)?;
+        self.record(EditCommand::SetEffectChain { node_idx, chain })
+    }
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node4, None, add_edge);
+    // Re-runs the node's chain after `update` changed its parameters and pushes
+    // the difference down to the root. The update is made on a copy, so the
+    // chain as it was is kept for undo
+    pub fn update_effect_chain(
+        &mut self,
+        node_idx: NodeIndex,
+        update: impl FnOnce(&mut AudioEffectChain<F>),
+    ) -> AudioResult<()> {
+        let chain = self
+            .lock_audio_graph()?
+            .try_get_node(node_idx)?
+            .lock()?
+            .get_effect_chain()
+            .map(AudioEffectChain::duplicate);
+        let Some(mut chain) = chain else {
+            return Ok(());
+        };
+        update(&mut chain);
+        self.set_effect_chain(node_idx, Some(chain))
+    }
 
-        let expected_samples = [[4.0], [8.0], [12.0]];
+    // Gives the node `chain` and leaves its old one there instead. If pushing
+    // the difference down fails, both are put back as they were
+    fn swap_effect_chain(
+        &mut self,
+        node_idx: NodeIndex,
+        chain: &mut Option<AudioEffectChain<F>>,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            Self::check_blockwise(node_idx, chain.as_ref())?;
+        }
+        let change = {
+            let mut graph = self.lock_audio_graph()?;
+            {
+                let mut node = graph.try_get_node(node_idx)?.lock()?;
+                Self::swap_chain(&mut node, chain);
+                node.compute_delta();
+            }
+            if let Err(err) = self.propagate_change(&mut graph, node_idx) {
+                self.restore_child(&mut graph, node_idx, |graph| {
+                    if let Ok(mut node) = graph
+                        .try_get_node(node_idx)
+                        .and_then(|node| Ok(node.lock()?))
+                    {
+                        Self::swap_chain(&mut node, chain);
+                    }
+                });
+                return Err(err);
+            }
+            self.node_change(&graph, node_idx)?
+        };
+        self.changed.extend(change);
+        Ok(())
+    }
 
-        for expected in &expected_samples {
-            let sample = processor
-                .get_node_or_root_sample(None)
-                .expect("Expected a sample");
-            assert_eq!(sample, *expected);
+    // All of the node's output, for edits that change it throughout
+    fn node_change(
+        &self,
+        audio_graph: &AudioGraph<F>,
+        node_idx: NodeIndex,
+    ) -> AudioResult<Option<(NodeIndex, (usize, usize))>> {
+        if self.caches_mixes() {
+            return Ok(None);
         }
+        Ok(Some((
+            node_idx,
+            LazyRender::new(audio_graph).extent(node_idx)?,
+        )))
     }
 
-    #[test]
-    fn test_audio_processor_complex_graph_2() {
-        let mut processor = AudioProcessor::<Mono<f32>>::new();
+    // Timeline frames of the node's own material, leaving its inputs out
+    fn own_frames(node: &AudioNode<F>) -> (usize, usize) {
+        (
+            node.get_clip_start(),
+            node.get_clip_start() + node.get_clip_len(),
+        )
+    }
 
-        // Create nodes
-        let node1 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"));
-        let node2 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"));
-        let node3 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"));
-        let node4 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node4"));
-        let node5 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node5"));
-        let node6 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node6"));
-        let node7 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node7"));
-        let node8 =
-            processor.add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node8"));
+    fn swap_chain(node: &mut AudioNode<F>, chain: &mut Option<AudioEffectChain<F>>) {
+        let old = node.get_effect_chain_mut().map(std::mem::take);
+        node.set_effect_chain(chain.take());
+        *chain = old;
+    }
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node1, Some(node3), add_edge);
-        let frames = processor.get_node_frames_copy(node3);
-        let expected_frames = vec![[2.0], [4.0], [6.0]];
-        assert_eq!(frames, expected_frames);
+    // Trims the node's own material to source frames `start..end`
+    pub fn trim_node(&mut self, node_idx: NodeIndex, start: usize, end: usize) -> AudioResult<()> {
+        let old = self.edit_region(node_idx, |node| {
+            let old = node.get_source_region();
+            node.set_source_region(start, end).map(|_| old)
+        })?;
+        self.record(EditCommand::SetRegion {
+            node_idx,
+            old,
+            new: (start, end),
+        })?;
+        Ok(())
+    }
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node2, Some(node3), add_edge);
-        let frames = processor.get_node_frames_copy(node3);
+    pub fn slip_node(&mut self, node_idx: NodeIndex, frames: i64) -> AudioResult<()> {
+        self.edit_region(node_idx, |node| node.slip(frames))?;
+        self.record(EditCommand::Slip { node_idx, frames })?;
+        Ok(())
+    }
+
+    // The material after `at` moves to a new node that feeds the same children
+    // through copies of the original's outgoing edges. Inputs and effects stay
+    // with the original
+    pub fn split_node(&mut self, node_idx: NodeIndex, at: usize) -> AudioResult<NodeIndex> {
+        self.record_group(|processor| {
+            let (tail, old, new) = processor.edit_region(node_idx, |node| {
+                let old = node.get_source_region();
+                let tail = node.split_off(at)?;
+                Ok((tail, old, node.get_source_region()))
+            })?;
+            processor.record(EditCommand::SetRegion { node_idx, old, new })?;
+
+            let outgoing: Vec<(NodeIndex, AudioGraphEdge<F>)> = processor
+                .lock_audio_graph()?
+                .graph
+                .edges_directed(node_idx, Direction::Outgoing)
+                .map(|edge| (edge.target(), edge.weight().duplicate()))
+                .collect();
+            let tail_idx = processor.add_node(tail)?;
+            for (child_idx, edge) in outgoing {
+                processor.connect(tail_idx, Some(child_idx), edge)?;
+            }
+            Ok(tail_idx)
+        })
+    }
+
+    // Places the node at timeline frame `new_start` and re-mixes it into every
+    // child there. Its inputs keep their own placement, so a node fed by others
+    // is rebuilt around them
+    pub fn move_node(&mut self, node_idx: NodeIndex, new_start: usize) -> AudioResult<()> {
+        let (old_start, len) = {
+            let mut graph = self.lock_audio_graph()?;
+            if node_idx == graph.root {
+                return Err(AudioError::RootMove);
+            }
+            // A child fed along two paths must be moved into after the other one
+            let order = graph.dependents_in_order(node_idx);
+            let mut outgoing: Vec<(NodeIndex, EdgeIndex)> = graph
+                .graph
+                .edges_directed(node_idx, Direction::Outgoing)
+                .map(|edge| (edge.target(), edge.id()))
+                .collect();
+            outgoing.sort_by_key(|(child_idx, _)| order.iter().position(|node| node == child_idx));
+            for &(_, edge_idx) in &outgoing {
+                let offset = graph.try_get_edge_ref(edge_idx)?.get_offset();
+                if new_start as i64 + offset < 0 {
+                    return Err(AudioError::NegativePlacement {
+                        node: node_idx,
+                        offset,
+                    });
+                }
+            }
+
+            let (old_start, len, before) = {
+                let mut node = graph.try_get_node(node_idx)?.lock()?;
+                let old_start = node.get_clip_start();
+                if old_start == new_start {
+                    return Ok(());
+                }
+                let mut before = AudioNode::new(node.with_output(AudioClip::clone), None);
+                before.set_clip_start(old_start);
+                node.set_clip_start(new_start);
+                (old_start, node.get_clip_len(), before)
+            };
+            if graph
+                .graph
+                .edges_directed(node_idx, Direction::Incoming)
+                .next()
+                .is_some()
+            {
+                self.rebuild_node(&graph, node_idx)?;
+            }
+            graph.try_get_node(node_idx)?.lock()?.commit_changes();
+
+            for (child_idx, edge_idx) in outgoing {
+                self.move_contribution(&mut graph, &before, node_idx, child_idx, edge_idx)?;
+            }
+            (old_start, len)
+        };
+        if !self.caches_mixes() {
+            self.changed.extend([
+                (node_idx, (old_start, old_start + len)),
+                (node_idx, (new_start, new_start + len)),
+            ]);
+        }
+        self.record(EditCommand::MoveNode {
+            node_idx,
+            old: old_start,
+            new: new_start,
+        })?;
+        Ok(())
+    }
+
+    // Swaps what `before` contributed to the child for what the node at
+    // `node_idx` contributes now, then pushes the child's change to the root
+    fn move_contribution(
+        &self,
+        audio_graph: &mut AudioGraph<F>,
+        before: &AudioNode<F>,
+        node_idx: NodeIndex,
+        child_idx: NodeIndex,
+        edge_idx: EdgeIndex,
+    ) -> AudioResult<()> {
+        if !self.caches_mixes() {
+            return Ok(());
+        }
+        if audio_graph.has_nonlinear_inputs(child_idx) {
+            self.rebuild_node(audio_graph, child_idx)?;
+        } else {
+            let effect = audio_graph.try_get_edge_ref(edge_idx)?;
+            let unplaced_parent = audio_graph.try_get_node(node_idx)?.lock()?;
+            let old_parent = effect.place_parent(before);
+            let new_parent = effect.place_parent(&unplaced_parent);
+            let mut child_node = audio_graph.try_get_node(child_idx)?.lock()?;
+
+            child_node.normalize_clip_bounds(&old_parent);
+            effect.operation.revert(&old_parent, &child_node);
+            child_node.normalize_clip_bounds(&new_parent);
+            effect.operation.apply(&new_parent, &child_node);
+            child_node.process();
+        }
+        self.propagate_child_edit(audio_graph, child_idx, true)
+    }
+
+    fn edit_region<T>(
+        &mut self,
+        node_idx: NodeIndex,
+        edit: impl FnOnce(&mut AudioNode<F>) -> AudioResult<T>,
+    ) -> AudioResult<T> {
+        let (result, before, after) = {
+            let mut graph = self.lock_audio_graph()?;
+            let (result, before, after) = {
+                let mut node = graph.try_get_node(node_idx)?.lock()?;
+                let before = Self::own_frames(&node);
+                let result = edit(&mut node)?;
+                node.process();
+                (result, before, Self::own_frames(&node))
+            };
+            // The material was swapped in place, which is only right for a plain sum
+            if graph.has_nonlinear_inputs(node_idx) {
+                self.rebuild_node(&graph, node_idx)?;
+            }
+            self.propagate_child_edit(&mut graph, node_idx, false)?;
+            (result, before, after)
+        };
+        if !self.caches_mixes() {
+            self.changed.extend([(node_idx, before), (node_idx, after)]);
+        }
+        Ok(result)
+    }
+
+    pub fn add_node(&mut self, mut node: AudioNode<F>) -> AudioResult<NodeIndex> {
+        self.adopt_node(&mut node)?;
+        let (node_idx, node) = {
+            let mut graph = self.lock_audio_graph()?;
+            let node_idx = graph.add_data_node(node);
+            (node_idx, Arc::clone(graph.try_get_node(node_idx)?))
+        };
+        self.record(EditCommand::AddNode { node_idx, node })?;
+        Ok(node_idx)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // Reverts the latest edit by performing its inverse. Returns whether there
+    // was anything to undo. An edit that fails to revert stays on the undo
+    // stack, with whatever part of it got through put back
+    pub fn undo(&mut self) -> AudioResult<bool> {
+        let Some(command) = self.history.pop_undo() else {
+            return Ok(false);
+        };
+        let mut inverse = command.inverse();
+        let result = self.replay(&mut inverse);
+        let command = inverse.inverse();
+        match result {
+            Ok(()) => self.history.push_redo(command),
+            Err(err) => {
+                self.history.push_undo(command);
+                return Err(err);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn redo(&mut self) -> AudioResult<bool> {
+        let Some(mut command) = self.history.pop_redo() else {
+            return Ok(false);
+        };
+        match self.replay(&mut command) {
+            Ok(()) => self.history.push_undo(command),
+            Err(err) => {
+                self.history.push_redo(command);
+                return Err(err);
+            }
+        }
+        Ok(true)
+    }
+
+    // Runs a recorded edit without recording it again. Nodes re-created on the
+    // way are followed by the rest of the history even if the edit then failed
+    fn replay(&mut self, command: &mut EditCommand<F>) -> AudioResult<()> {
+        let history = std::mem::take(&mut self.history);
+        let mut remaps = Vec::new();
+        let result = self.nested_edit(|processor| processor.execute(command, &mut remaps));
+        self.history = history;
+        for remap in remaps {
+            self.history.remap(remap);
+        }
+        result
+    }
+
+    // Performs `command`, updating it in place if a node or edge it creates
+    // gets a new index. Those changes are added to `remaps` so other edits can
+    // follow them
+    fn execute(
+        &mut self,
+        command: &mut EditCommand<F>,
+        remaps: &mut Vec<Remap>,
+    ) -> AudioResult<()> {
+        match command {
+            EditCommand::AddNode { node_idx, node } => {
+                self.adopt_node(&mut *node.lock()?)?;
+                let new_idx = self
+                    .lock_audio_graph()?
+                    .restore_data_node(Arc::clone(node))?;
+                if new_idx != *node_idx {
+                    remaps.push(Remap::Node(*node_idx, new_idx));
+                    *node_idx = new_idx;
+                }
+            }
+            EditCommand::RemoveNode { node_idx, .. } => {
+                self.lock_audio_graph()?.remove_data_node(*node_idx)?;
+            }
+            EditCommand::Connect {
+                edge_idx,
+                parent,
+                child,
+                edge,
+            } => {
+                let new_idx = self.connect(*parent, Some(*child), edge.duplicate())?;
+                if new_idx != *edge_idx {
+                    remaps.push(Remap::Edge(*edge_idx, new_idx));
+                    *edge_idx = new_idx;
+                }
+            }
+            EditCommand::Disconnect { edge_idx, .. } => {
+                self.disconnect(*edge_idx)?;
+            }
+            EditCommand::SetEdgeOffset { edge_idx, new, .. } => {
+                self.set_edge_offset(*edge_idx, *new)?;
+            }
+            EditCommand::SetMixParams { edge_idx, new, .. } => {
+                self.set_mix_params(*edge_idx, *new)?;
+            }
+            EditCommand::SetRegion { node_idx, new, .. } => {
+                self.trim_node(*node_idx, new.0, new.1)?;
+            }
+            EditCommand::Slip { node_idx, frames } => {
+                self.slip_node(*node_idx, *frames)?;
+            }
+            EditCommand::MoveNode { node_idx, new, .. } => {
+                self.move_node(*node_idx, *new)?;
+            }
+            EditCommand::SetEffectChain { node_idx, chain } => {
+                self.swap_effect_chain(*node_idx, chain)?;
+            }
+            EditCommand::Group(commands) => {
+                for i in 0..commands.len() {
+                    let first = remaps.len();
+                    let result = self.execute(&mut commands[i], remaps);
+                    Self::follow_remaps(commands, &remaps[first..]);
+                    if let Err(err) = result {
+                        self.roll_back(&mut commands[..i], remaps);
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Takes back the part of a group that ran before one of its edits failed,
+    // last edit first, so none of the group stays applied
+    fn roll_back(&mut self, done: &mut [EditCommand<F>], remaps: &mut Vec<Remap>) {
+        for i in (0..done.len()).rev() {
+            let first = remaps.len();
+            let mut inverse =
+                std::mem::replace(&mut done[i], EditCommand::Group(Vec::new())).inverse();
+            let result = self.execute(&mut inverse, remaps);
+            done[i] = inverse.inverse();
+            Self::follow_remaps(done, &remaps[first..]);
+            if let Err(err) = result {
+                log::warn!("failed to roll back a partly applied edit: {}", err);
+                return;
+            }
+        }
+    }
+
+    fn follow_remaps(commands: &mut [EditCommand<F>], remaps: &[Remap]) {
+        for remap in remaps {
+            for command in commands.iter_mut() {
+                command.remap(*remap);
+            }
+        }
+    }
+
+    // Records everything `edit` does as a single undo step. If it fails
+    // partway, what it did so far is taken back and nothing is recorded
+    fn record_group<T>(
+        &mut self,
+        edit: impl FnOnce(&mut Self) -> AudioResult<T>,
+    ) -> AudioResult<T> {
+        self.nested_edit(|processor| {
+            let outer = std::mem::take(&mut processor.history);
+            let result = edit(processor);
+            let inner = std::mem::replace(&mut processor.history, outer);
+            if result.is_err() {
+                if let Err(err) = processor.replay(&mut inner.into_group().inverse()) {
+                    log::warn!("failed to roll back a partly applied edit: {}", err);
+                }
+            } else {
+                processor.history.record_group(inner);
+            }
+            result
+        })
+    }
+
+    fn add_resampled_node(
+        &mut self,
+        clip: AudioClip<F>,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        let audio_node = AudioNode::new(self.resample_to_processor(clip), name);
+        self.add_node(audio_node)
+    }
+
+    fn resample_to_processor(&self, clip: AudioClip<F>) -> AudioClip<F> {
+        if self.sample_rate != clip.get_sample_rate() {
+            clip.resample_with_quality(self.sample_rate, self.resample_quality)
+        } else {
+            clip
+        }
+    }
+
+    fn add_node_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+        convert: fn(AudioClipEnum) -> AudioClip<F>,
+    ) -> AudioResult<NodeIndex> {
+        let node_idx = self.add_resampled_node(convert(load_clip(&path)?), name)?;
+        let graph = self.lock_audio_graph()?;
+        let mut node = graph.try_get_node(node_idx)?.lock()?;
+        node.set_source_path(Some(path.as_ref().to_path_buf()));
+        Ok(node_idx)
+    }
+
+    // Node reading the file from disk as it plays instead of loading it, for
+    // recordings too long to hold in memory. The file is not resampled, so it
+    // has to be at the processor's rate. Combine with `RenderMode::Lazy` to
+    // keep the node's own buffers from holding the whole region too
+    pub fn add_node_from_stream<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        let source_clip = self.open_stream(path.as_ref())?;
+        let region = (0, source_clip.get_length());
+        let mut audio_node = AudioNode::without_buffers(source_clip, region, name);
+        audio_node.set_source_path(Some(path.as_ref().to_path_buf()));
+        self.add_node(audio_node)
+    }
+
+    fn open_stream(&self, path: &Path) -> AudioResult<ClipSource<F>> {
+        let stream_clip = StreamClip::open(path)?;
+        if stream_clip.get_sample_rate() != self.sample_rate {
+            return Err(AudioError::SampleRateMismatch {
+                expected: self.sample_rate,
+                found: stream_clip.get_sample_rate(),
+            });
+        }
+        Ok(stream_clip.into())
+    }
+
+    // Writes the graph to a session file, see `session` for the format
+    pub fn save_session<P: AsRef<Path>>(&self, path: P) -> AudioResult<()> {
+        self.to_session()?.write(path)
+    }
+
+    pub fn to_session(&self) -> AudioResult<Session> {
+        let graph = self.lock_audio_graph()?;
+        let mut source_clips: Vec<ClipSource<F>> = Vec::new();
+        let mut sources = Vec::new();
+        let mut nodes = Vec::new();
+
+        for node_idx in graph.graph.node_indices() {
+            if node_idx == graph.root {
+                continue;
+            }
+            let node = graph.try_get_node(node_idx)?.lock()?;
+            let source_clip = node.get_source_clip();
+            let source = match source_clips
+                .iter()
+                .position(|clip| clip.ptr_eq(source_clip))
+            {
+                Some(source) => source,
+                None => {
+                    let streamed = matches!(source_clip, ClipSource::Stream(_));
+                    sources.push(match node.get_source_path() {
+                        Some(path) => SessionSource::File {
+                            path: path.to_path_buf(),
+                            streamed,
+                        },
+                        None => SessionSource::Embedded {
+                            channels: F::CHANNELS as u32,
+                            sample_rate: source_clip.get_sample_rate(),
+                            samples: source_clip
+                                .get_frames_ref()?
+                                .iter()
+                                .flat_map(|frame| frame.channels())
+                                .collect(),
+                        },
+                    });
+                    source_clips.push(source_clip.clone());
+                    sources.len() - 1
+                }
+            };
+            let effects = match node.get_effect_chain() {
+                Some(effect_chain) => effect_chain
+                    .to_session()
+                    .ok_or(AudioError::UnsavableEffect(node_idx))?,
+                None => Vec::new(),
+            };
+            nodes.push(SessionNode {
+                id: node_idx.index(),
+                name: node.get_name().map(str::to_string),
+                source,
+                region: node.get_source_region(),
+                position: node.get_region_bounds().0,
+                effects,
+            });
+        }
+
+        let mut edges = Vec::new();
+        for edge_idx in graph.graph.edge_indices() {
+            let (parent_idx, child_idx) = graph.get_edge_endpoints(edge_idx)?;
+            let edge = graph.try_get_edge_ref(edge_idx)?;
+            edges.push(SessionEdge {
+                parent: parent_idx.index(),
+                child: (child_idx != graph.root).then(|| child_idx.index()),
+                offset: edge.get_offset(),
+                operation: edge
+                    .operation
+                    .to_session()
+                    .ok_or(AudioError::UnsavableEdge(edge_idx))?,
+            });
+        }
+
+        Ok(Session {
+            version: SESSION_VERSION,
+            sample_rate: self.sample_rate,
+            sources,
+            nodes,
+            edges,
+        })
+    }
+
+    // Rebuilds the graph node by node and reconnects every edge, which re-mixes
+    // the root along the way
+    fn from_session(
+        session: Session,
+        convert: fn(AudioClipEnum) -> AudioClip<F>,
+    ) -> AudioResult<Self> {
+        let mut processor = Self::with_sample_rate(session.sample_rate);
+
+        let mut sources = Vec::new();
+        for source in session.sources {
+            let (clip, path) = match source {
+                SessionSource::File {
+                    path,
+                    streamed: true,
+                } => {
+                    sources.push((processor.open_stream(&path)?, Some(path)));
+                    continue;
+                }
+                SessionSource::File { path, .. } => (convert(load_clip(&path)?), Some(path)),
+                SessionSource::Embedded {
+                    channels,
+                    sample_rate,
+                    samples,
+                } => (
+                    convert(AudioClipEnum::from_samples(samples, sample_rate, channels)?),
+                    None,
+                ),
+            };
+            sources.push((processor.resample_to_processor(clip).into(), path));
+        }
+
+        let mut node_ids = HashMap::new();
+        for node in session.nodes {
+            let (source_clip, path) = sources.get(node.source).ok_or_else(|| {
+                AudioError::Session(format!("node {} has no source {}", node.id, node.source))
+            })?;
+            let (start, end) = node.region;
+            if start >= end || end > source_clip.get_length() {
+                return Err(AudioError::InvalidRegion {
+                    start: start as i64,
+                    end: end as i64,
+                });
+            }
+            let mut audio_node =
+                AudioNode::without_buffers(source_clip.clone(), node.region, node.name.as_deref());
+            audio_node.set_clip_start(node.position);
+            audio_node.set_source_path(path.clone());
+            let node_idx = processor.add_node(audio_node)?;
+            if !node.effects.is_empty() {
+                let effect_chain = SessionEffect::into_chain(node.effects);
+                processor.set_effect_chain(node_idx, Some(effect_chain))?;
+            }
+            node_ids.insert(node.id, node_idx);
+        }
+
+        let lookup = |id: usize| {
+            node_ids
+                .get(&id)
+                .copied()
+                .ok_or_else(|| AudioError::Session(format!("unknown node id {}", id)))
+        };
+        for edge in session.edges {
+            let parent = lookup(edge.parent)?;
+            let child = edge.child.map(lookup).transpose()?;
+            let graph_edge = edge.operation.into_edge().with_offset(edge.offset);
+            processor.connect(parent, child, graph_edge)?;
+        }
+        // A freshly loaded session starts with nothing to undo
+        processor.clear_history();
+        Ok(processor)
+    }
+
+    fn get_node_frames_copy(&self, node_index: NodeIndex) -> Vec<F> {
+        let graph = self.lock_audio_graph().unwrap();
+        let node = graph.get_node(node_index).unwrap().lock().unwrap();
+        let x = node.with_buffers(|buffers| buffers.clip.get_frames_ref().clone().to_vec());
+        x
+    }
+
+    fn print_graph(&self) {
+        if let Ok(graph) = self.lock_audio_graph() {
+            graph.print_graph();
+        }
+    }
+}
+
+impl AudioProcessor<Mono<f32>> {
+    pub fn add_node_from_clip(
+        &mut self,
+        clip: AudioClipEnum,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_resampled_node(clip.into_mono(), name)
+    }
+
+    pub fn add_node_from_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_node_from_file(path, name, AudioClipEnum::into_mono)
+    }
+
+    pub fn load_session<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        Self::from_session(Session::read(path)?, AudioClipEnum::into_mono)
+    }
+}
+
+impl AudioProcessor<Stereo<f32>> {
+    pub fn add_node_from_clip(
+        &mut self,
+        clip: AudioClipEnum,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_resampled_node(clip.into_stereo(), name)
+    }
+
+    pub fn add_node_from_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_node_from_file(path, name, AudioClipEnum::into_stereo)
+    }
+
+    pub fn load_session<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        Self::from_session(Session::read(path)?, AudioClipEnum::into_stereo)
+    }
+}
+
+impl AudioProcessor<Quad<f32>> {
+    pub fn add_node_from_clip(
+        &mut self,
+        clip: AudioClipEnum,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_resampled_node(clip.into_quad(), name)
+    }
+
+    pub fn add_node_from_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_node_from_file(path, name, AudioClipEnum::into_quad)
+    }
+
+    pub fn load_session<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        Self::from_session(Session::read(path)?, AudioClipEnum::into_quad)
+    }
+}
+
+impl AudioProcessor<Surround51<f32>> {
+    pub fn add_node_from_clip(
+        &mut self,
+        clip: AudioClipEnum,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_resampled_node(clip.into_surround51(), name)
+    }
+
+    pub fn add_node_from_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_node_from_file(path, name, AudioClipEnum::into_surround51)
+    }
+
+    pub fn load_session<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        Self::from_session(Session::read(path)?, AudioClipEnum::into_surround51)
+    }
+}
+
+impl AudioProcessor<Surround71<f32>> {
+    pub fn add_node_from_clip(
+        &mut self,
+        clip: AudioClipEnum,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_resampled_node(clip.into_surround71(), name)
+    }
+
+    pub fn add_node_from_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Option<&str>,
+    ) -> AudioResult<NodeIndex> {
+        self.add_node_from_file(path, name, AudioClipEnum::into_surround71)
+    }
+
+    pub fn load_session<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        Self::from_session(Session::read(path)?, AudioClipEnum::into_surround71)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::audio::audio_edge::{
+        CrossfadeCurve, CrossfadeOperation, DuckOperation, MaxOperation, MixOperation,
+        MultiplyOperation, SubtractOperation,
+    };
+    use crate::audio::audio_effects::{fade_in, fade_out, gain, invert, AudioEffect, EffectFn};
+    use crate::audio::biquad::{high_pass, low_pass, BUTTERWORTH_Q};
+    use crate::audio::test_util::{temp_path, truncate_file, write_float_wav};
+    use dasp::frame::Mono;
+
+    fn assert_frames_close<const N: usize>(actual: &[[f32; N]], expected: &[[f32; N]]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            for (a, e) in a.iter().zip(e) {
+                assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    fn mix_edge<F>(params: MixParams) -> AudioGraphEdge<F>
+    where
+        F: dasp::Frame<Sample = f32> + Default + Copy,
+    {
+        AudioGraphEdge::new(MixOperation::new(params), "MixOp")
+    }
+
+    fn create_simple_clip() -> AudioClip<Mono<f32>> {
+        let samples: Vec<f32> = (1..=3).map(|x| x as f32).collect();
+        AudioClip::<Mono<f32>>::new(samples, 44100).unwrap()
+    }
+
+    #[test]
+    fn test_two_nodes_to_root() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node1, None, add_edge).unwrap();
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node2, None, add_edge).unwrap();
+
+        let expected_samples = [[2.0], [4.0], [6.0]];
+
+        for expected in &expected_samples {
+            let sample = processor
+                .get_node_or_root_sample(None)
+                .unwrap()
+                .expect("Expected a sample");
+            assert_eq!(sample, *expected);
+        }
+    }
+
+    #[test]
+    fn test_audio_processor_complex_graph() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+        let node3 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"))
+            .unwrap();
+        let node4 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node4"))
+            .unwrap();
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node1, Some(node3), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node3);
+        let expected_frames = vec![[2.0], [4.0], [6.0]];
+        assert_eq!(frames, expected_frames);
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node2, Some(node3), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node3);
+        let expected_frames = vec![[3.0], [6.0], [9.0]];
+        assert_eq!(frames, expected_frames);
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node3, Some(node4), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node4);
+        let expected_frames = vec![[4.0], [8.0], [12.0]];
+        assert_eq!(frames, expected_frames);
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node4, None, add_edge).unwrap();
+
+        let expected_samples = [[4.0], [8.0], [12.0]];
+
+        for expected in &expected_samples {
+            let sample = processor
+                .get_node_or_root_sample(None)
+                .unwrap()
+                .expect("Expected a sample");
+            assert_eq!(sample, *expected);
+        }
+    }
+
+    #[test]
+    fn test_audio_processor_complex_graph_2() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+
+        // Create nodes
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+        let node3 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"))
+            .unwrap();
+        let node4 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node4"))
+            .unwrap();
+        let node5 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node5"))
+            .unwrap();
+        let node6 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node6"))
+            .unwrap();
+        let node7 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node7"))
+            .unwrap();
+        let node8 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node8"))
+            .unwrap();
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node1, Some(node3), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node3);
+        let expected_frames = vec![[2.0], [4.0], [6.0]];
+        assert_eq!(frames, expected_frames);
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node2, Some(node3), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node3);
         let expected_frames = vec![[3.0], [6.0], [9.0]];
         assert_eq!(frames, expected_frames);
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node3, Some(node4), add_edge);
+        processor.connect(node3, Some(node4), add_edge).unwrap();
         let frames = processor.get_node_frames_copy(node4);
         let expected_frames = vec![[4.0], [8.0], [12.0]]; // node3 + node4
         assert_eq!(frames, expected_frames);
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node5, Some(node4), add_edge);
+        processor.connect(node5, Some(node4), add_edge).unwrap();
         let frames = processor.get_node_frames_copy(node4);
         let expected_frames = vec![[5.0], [10.0], [15.0]]; // previous node4 + node5
         assert_eq!(frames, expected_frames);
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node6, Some(node5), add_edge);
+        processor.connect(node6, Some(node5), add_edge).unwrap();
         let frames = processor.get_node_frames_copy(node5);
         let expected_frames = vec![[2.0], [4.0], [6.0]]; // original node5 + node6
         assert_eq!(frames, expected_frames);
@@ -310,7 +1964,7 @@ mod tests {
         assert_eq!(frames, expected_frames);
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node7, Some(node5), add_edge);
+        processor.connect(node7, Some(node5), add_edge).unwrap();
         let frames = processor.get_node_frames_copy(node5);
         let expected_frames = vec![[3.0], [6.0], [9.0]]; // previous node5 + node7
         assert_eq!(frames, expected_frames);
@@ -318,37 +1972,1372 @@ mod tests {
         let expected_frames = vec![[7.0], [14.0], [21.0]];
         assert_eq!(frames, expected_frames);
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node4, None, add_edge);
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node4, None, add_edge).unwrap();
+
+        processor.print_graph();
+
+        let expected_samples = [[7.0], [14.0], [21.0]];
+
+        for expected in &expected_samples {
+            let sample = processor
+                .get_node_or_root_sample(None)
+                .unwrap()
+                .expect("Expected a sample");
+            assert_eq!(sample, *expected);
+        }
+
+        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(node8, Some(node5), add_edge).unwrap();
+        let frames = processor.get_node_frames_copy(node5);
+        let expected_frames = vec![[4.0], [8.0], [12.0]]; // previous node5 + node8
+        assert_eq!(frames, expected_frames);
+        let frames = processor.get_node_frames_copy(node4);
+        let expected_frames = vec![[8.0], [16.0], [24.0]];
+        assert_eq!(frames, expected_frames);
+
+        let expected_samples = [[8.0], [16.0], [24.0]];
+        processor.set_root_frame_idx(0);
+        for expected in &expected_samples {
+            let sample = processor
+                .get_node_or_root_sample(None)
+                .unwrap()
+                .expect("Expected a sample");
+            assert_eq!(sample, *expected);
+        }
+    }
+
+    #[test]
+    fn test_connect_unknown_node_returns_error() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let missing = NodeIndex::new(42);
+
+        let result = processor.connect(
+            node1,
+            Some(missing),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::UnknownNode(n)) if n == missing));
+        assert!(matches!(
+            processor.get_node_or_root_sample(Some(missing)),
+            Err(AudioError::UnknownNode(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejected_cycle_leaves_clips_untouched() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+
+        processor
+            .connect(
+                node1,
+                Some(node2),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        let result = processor.connect(
+            node2,
+            Some(node1),
+            AudioGraphEdge::new(AddOperation, "AddOp"),
+        );
+        assert!(matches!(result, Err(AudioError::CycleDetected { .. })));
+
+        assert_eq!(
+            processor.get_node_frames_copy(node1),
+            vec![[1.0], [2.0], [3.0]]
+        );
+        assert_eq!(
+            processor.get_node_frames_copy(node2),
+            vec![[2.0], [4.0], [6.0]]
+        );
+    }
+
+    #[test]
+    fn test_disconnect_restores_mix() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let mut reference = AudioProcessor::<Mono<f32>>::new();
+
+        let mut node_ids = Vec::new();
+        for p in [&mut processor, &mut reference] {
+            let node1 = p.add_node(create_unit_node(3)).unwrap();
+            let node2 = p
+                .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+                .unwrap();
+            let node3 = p
+                .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"))
+                .unwrap();
+            node_ids.push((node1, node2, node3));
+        }
+        let (node1, node2, node3) = node_ids[0];
+
+        let edge = processor
+            .connect(
+                node1,
+                Some(node3),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(
+                node2,
+                Some(node3),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(node3, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        assert_eq!(
+            processor.get_node_frames_copy(node3),
+            vec![[3.0], [5.0], [7.0]]
+        );
+
+        processor.disconnect(edge).unwrap();
+
+        let (_, ref_node2, ref_node3) = node_ids[1];
+        reference
+            .connect(
+                ref_node2,
+                Some(ref_node3),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        reference
+            .connect(ref_node3, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        assert_eq!(
+            processor.get_node_frames_copy(node3),
+            reference.get_node_frames_copy(ref_node3)
+        );
+        assert_eq!(
+            processor.get_node_frames_copy(processor.root_node_index),
+            reference.get_node_frames_copy(reference.root_node_index)
+        );
+        assert!(matches!(
+            processor.disconnect(edge),
+            Err(AudioError::InvalidEdge(_))
+        ));
+    }
+
+    #[test]
+    fn test_disconnect_leaves_the_exact_mix() {
+        let sine = |freq: f32| -> Vec<f32> {
+            (0..64)
+                .map(|i| (i as f32 * freq * 0.1).sin() * 0.3)
+                .collect()
+        };
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let a = add_mono_node(&mut processor, sine(1.0));
+        let b = add_mono_node(&mut processor, sine(1.7));
+        let c = add_mono_node(&mut processor, sine(2.3));
+        for node in [a, c] {
+            processor
+                .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        let root_before = root_prefix(&processor, 64);
+        let a_before = processor.get_node_frames_copy(a);
+
+        let edge = processor
+            .connect(b, Some(a), AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        assert_ne!(root_prefix(&processor, 64), root_before);
+        processor.disconnect(edge).unwrap();
+        assert_eq!(root_prefix(&processor, 64), root_before);
+        assert_eq!(processor.get_node_frames_copy(a), a_before);
+
+        // Undoing a connect goes through the same path
+        processor
+            .connect(b, Some(a), mix_edge(MixParams::default()))
+            .unwrap();
+        processor.undo().unwrap();
+        assert_eq!(root_prefix(&processor, 64), root_before);
+        assert_eq!(processor.get_node_frames_copy(a), a_before);
+    }
+
+    #[test]
+    fn test_remove_node_restores_mix() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+        let node3 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"))
+            .unwrap();
+
+        processor
+            .connect(
+                node1,
+                Some(node2),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(node2, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(node3, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let root_frames = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(root_frames[0..3], [[3.0], [6.0], [9.0]]);
+
+        processor.remove_node(node2).unwrap();
+        let root_frames = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(root_frames[0..3], [[1.0], [2.0], [3.0]]);
+        assert!(root_frames[3..].iter().all(|frame| *frame == [0.0]));
+
+        // The removed node's input is left dangling but untouched
+        assert_eq!(
+            processor.get_node_frames_copy(node1),
+            vec![[1.0], [2.0], [3.0]]
+        );
+        assert!(matches!(
+            processor.remove_node(processor.root_node_index),
+            Err(AudioError::RootRemoval)
+        ));
+    }
+
+    #[test]
+    fn test_add_multichannel_clips() {
+        // FL FR FC LFE SL SR
+        let surround =
+            AudioClipEnum::from_samples(vec![1.0, 1.0, 0.0, 1.0, 0.0, 0.0], 44100, 6).unwrap();
+        let mut stereo_processor = AudioProcessor::<Stereo<f32>>::new();
+        let node = stereo_processor
+            .add_node_from_clip(surround, Some("surround"))
+            .unwrap();
+        assert_eq!(
+            stereo_processor.get_node_frames_copy(node),
+            vec![[1.0, 1.0]]
+        );
+
+        let stereo = AudioClipEnum::from_samples(vec![0.5, 0.25], 44100, 2).unwrap();
+        let mut surround_processor = AudioProcessor::<Surround51<f32>>::new();
+        let node = surround_processor
+            .add_node_from_clip(stereo, Some("stereo"))
+            .unwrap();
+        surround_processor
+            .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let root_frames =
+            surround_processor.get_node_frames_copy(surround_processor.root_node_index);
+        assert_eq!(root_frames[0], [0.5, 0.25, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_with_sample_rate_resamples_to_device_rate() {
+        let mut processor = AudioProcessor::<Stereo<f32>>::with_sample_rate(48000);
+        assert_eq!(processor.get_sample_rate(), 48000);
+
+        let clip = AudioClip::<Stereo<f32>>::new(vec![0.5; 44100 * 2], 44100).unwrap();
+        let node = processor
+            .add_node_from_clip(AudioClipEnum::Stereo(clip), Some("cd"))
+            .unwrap();
+        processor
+            .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        let graph = processor.lock_audio_graph().unwrap();
+        let node = graph.get_node(node).unwrap().lock().unwrap();
+        assert_eq!(
+            node.with_buffers(|buffers| buffers.clip.get_sample_rate()),
+            48000
+        );
+        assert_eq!(node.get_clip_len(), 48000);
+
+        let root = graph.get_node(graph.root).unwrap().lock().unwrap();
+        assert_eq!(
+            root.with_buffers(|buffers| buffers.clip.get_sample_rate()),
+            48000
+        );
+        assert_eq!(root.get_clip_len(), 48000 * 5);
+    }
+
+    #[test]
+    fn test_add_node_from_path() {
+        let path = temp_path("processor_mono.wav");
+        write_float_wav(&path, 1, 22050, &[0.5; 22050]);
+
+        let mut processor = AudioProcessor::<Stereo<f32>>::new();
+        let node = processor.add_node_from_path(&path, Some("file")).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let frames = processor.get_node_frames_copy(node);
+        assert_eq!(frames.len(), 44100);
+        assert_eq!(frames[1000], [0.5, 0.5]);
+
+        let missing = processor.add_node_from_path("does/not/exist.wav", None);
+        assert!(matches!(missing, Err(AudioError::Io(_))));
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let wav_path = temp_path("session.wav");
+        let session_path = temp_path("session.json");
+        let frames: Vec<Mono<f32>> = (0..8).map(|i| [i as f32 / 8.0]).collect();
+        write_wav(&wav_path, &frames, 44100, BitDepth::Float32).unwrap();
+
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let file = processor
+            .add_node_from_path(&wav_path, Some("file"))
+            .unwrap();
+        let envelope = add_mono_node(&mut processor, vec![0.5; 4]);
+        let bus = add_mono_node(&mut processor, vec![0.0; 12]);
+        processor
+            .connect(
+                file,
+                Some(bus),
+                mix_edge(MixParams {
+                    gain_db: -6.0,
+                    ..MixParams::default()
+                })
+                .with_offset(2),
+            )
+            .unwrap();
+        processor
+            .connect(
+                envelope,
+                Some(bus),
+                AudioGraphEdge::new(MultiplyOperation, "MulOp"),
+            )
+            .unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .set_effect_chain(
+                bus,
+                Some(
+                    AudioEffectChain::new()
+                        .with_effect(gain(2.0))
+                        .with_effect(fade_out(3)),
+                ),
+            )
+            .unwrap();
+        let tail = processor.split_node(file, 5).unwrap();
+        processor.trim_node(tail, 5, 7).unwrap();
+        processor.save_session(&session_path).unwrap();
+
+        let session = Session::read(&session_path).unwrap();
+        // Both halves of the split file node share one entry
+        assert_eq!(session.sources.len(), 3);
+        assert_eq!(
+            session.sources[0],
+            SessionSource::File {
+                path: wav_path.clone(),
+                streamed: false,
+            }
+        );
+        assert_eq!(session.nodes.len(), 4);
+        assert_eq!(session.edges.len(), 4);
+        assert_eq!(
+            session
+                .nodes
+                .iter()
+                .map(|node| node.effects.len())
+                .sum::<usize>(),
+            2
+        );
+
+        let loaded = AudioProcessor::<Mono<f32>>::load_session(&session_path).unwrap();
+        std::fs::remove_file(&wav_path).unwrap();
+        std::fs::remove_file(&session_path).unwrap();
+
+        let expected = processor.get_node_frames_copy(processor.root_node_index);
+        let actual = loaded.get_node_frames_copy(loaded.root_node_index);
+        assert_frames_close(&actual[..12], &expected[..12]);
+        assert!(loaded
+            .lock_audio_graph()
+            .unwrap()
+            .get_node_id("file")
+            .is_some());
+    }
+
+    #[test]
+    fn test_undo_redo_effect_chain() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let node = add_mono_node(&mut processor, vec![1.0; 4]);
+        processor
+            .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .set_effect_chain(node, Some(AudioEffectChain::new().with_effect(gain(0.5))))
+            .unwrap();
+        processor
+            .update_effect_chain(node, |chain| {
+                chain.set_effect(0, gain(0.25));
+            })
+            .unwrap();
+        assert_eq!(root_prefix(&processor, 4), vec![[0.25]; 4]);
+
+        processor.undo().unwrap();
+        assert_eq!(root_prefix(&processor, 4), vec![[0.5]; 4]);
+        processor.undo().unwrap();
+        assert_eq!(root_prefix(&processor, 4), vec![[1.0]; 4]);
+        processor.redo().unwrap();
+        processor.redo().unwrap();
+        assert_eq!(root_prefix(&processor, 4), vec![[0.25]; 4]);
+    }
+
+    #[test]
+    fn test_session_rejects_unsavable_effects() {
+        struct Custom;
+        impl AudioEffect<Mono<f32>> for Custom {
+            fn apply(&mut self, _clip: &mut AudioClip<Mono<f32>>) {}
+
+            fn clone_box(&self) -> EffectFn<Mono<f32>> {
+                Box::new(Custom)
+            }
+        }
+
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let node = add_mono_node(&mut processor, vec![1.0; 4]);
+        processor
+            .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .set_effect_chain(
+                node,
+                Some(
+                    AudioEffectChain::new()
+                        .with_effect(gain(0.5))
+                        .with_effect(Box::new(Custom)),
+                ),
+            )
+            .unwrap();
+        assert!(matches!(
+            processor.to_session(),
+            Err(AudioError::UnsavableEffect(idx)) if idx == node
+        ));
+    }
+
+    #[test]
+    fn test_render_root_to_wav() {
+        let mut processor = AudioProcessor::<Stereo<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![0.25, -0.25, 0.5, -0.5], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        let n2 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![0.25, 0.25, 0.25, 0.25], 44100).unwrap(),
+                Some("n2"),
+            ))
+            .unwrap();
+        for node in [n1, n2] {
+            processor
+                .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+
+        let path = temp_path("render_root.wav");
+        processor
+            .render_to_wav(None, &path, BitDepth::Int16 { dither: false })
+            .unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        let samples: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 44100);
+        assert_eq!(samples.len(), 44100 * 5 * 2);
+        assert_eq!(&samples[..4], &[16384, 0, 24576, -8192]);
+
+        let missing = processor.render_to_wav(
+            Some(NodeIndex::new(42)),
+            temp_path("unused.wav"),
+            BitDepth::Float32,
+        );
+        assert!(matches!(missing, Err(AudioError::UnknownNode(_))));
+    }
+
+    #[test]
+    fn test_fill_buffer_pads_end_of_clip() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, 2.0, 3.0], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        let root_len = 44100 * 5;
+        processor.transport().play();
+        let mut out = [[9.0]; 4];
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 4);
+        assert_eq!(out, [[1.0], [2.0], [3.0], [0.0]]);
+
+        processor.set_root_frame_idx(root_len - 2);
+        let mut out = [[9.0]; 4];
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 2);
+        assert_eq!(out, [[0.0]; 4]);
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 0);
+        assert_eq!(processor.transport().get_position(), root_len);
+    }
+
+    #[test]
+    fn test_fill_interleaved() {
+        let mut processor = AudioProcessor::<Stereo<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, -1.0, 2.0, -2.0], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        processor.transport().play();
+        let mut out = [9.0; 5];
+        assert_eq!(processor.fill_interleaved(&mut out).unwrap(), 2);
+        assert_eq!(out, [1.0, -1.0, 2.0, -2.0, 0.0]);
+        assert_eq!(processor.fill_interleaved(&mut out).unwrap(), 2);
+        assert_eq!(out, [0.0; 5]);
+        assert_eq!(processor.transport().get_position(), 4);
+    }
+
+    #[test]
+    fn test_fill_buffer_reports_a_poisoned_graph() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, 2.0], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        let audio_graph = Arc::clone(&processor.audio_graph);
+        let _ = std::thread::spawn(move || {
+            let _guard = audio_graph.lock().unwrap();
+            panic!("poison the graph");
+        })
+        .join();
+
+        processor.transport().play();
+        let mut out = [[9.0]; 4];
+        assert!(matches!(
+            processor.fill_buffer(&mut out),
+            Err(AudioError::PoisonedLock)
+        ));
+        assert_eq!(out, [[0.0]; 4]);
+        let mut out = [9.0; 4];
+        assert!(processor.fill_interleaved(&mut out).is_err());
+        assert_eq!(out, [0.0; 4]);
+    }
+
+    #[test]
+    fn test_transport_pause_and_loop() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, 2.0, 3.0, 4.0], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        // Stopped transport renders silence without moving
+        let transport = processor.transport();
+        let mut out = [[9.0]; 3];
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 0);
+        assert_eq!(out, [[0.0]; 3]);
+        assert_eq!(transport.get_position(), 0);
+
+        // Loop shorter than the block wraps several times within it
+        transport.set_loop_region(1, 3).unwrap();
+        transport.play();
+        let mut out = [[0.0]; 7];
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 7);
+        assert_eq!(out, [[1.0], [2.0], [3.0], [2.0], [3.0], [2.0], [3.0]]);
+        assert_eq!(transport.get_position(), 1);
+
+        transport.pause();
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 0);
+        assert_eq!(transport.get_position(), 1);
+
+        transport.clear_loop_region();
+        transport.seek(2);
+        transport.play();
+        let mut out = [[0.0]; 2];
+        processor.fill_buffer(&mut out).unwrap();
+        assert_eq!(out, [[3.0], [4.0]]);
+    }
+
+    #[test]
+    fn test_transport_seek_from_another_thread() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let transport = processor.transport();
+        transport.play();
+
+        let control = std::thread::spawn(move || {
+            for i in 0..1000 {
+                transport.seek(i * 100);
+                transport.set_loop_region(i, i + 50).unwrap();
+            }
+        });
+        let mut out = [[0.0]; 64];
+        for _ in 0..1000 {
+            assert_eq!(processor.fill_buffer(&mut out).unwrap(), 64);
+        }
+        control.join().unwrap();
+    }
+
+    #[test]
+    fn test_renderer_follows_edits_without_graph_lock() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let mut renderer = processor.create_renderer().unwrap();
+        renderer.transport().play();
+
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, 2.0], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        let edge = processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        // Holding the graph lock must not stall the render side
+        let graph = processor.lock_audio_graph().unwrap();
+        let mut out = [[0.0]; 2];
+        assert_eq!(renderer.fill_buffer(&mut out), 2);
+        assert_eq!(out, [[1.0], [2.0]]);
+        drop(graph);
+
+        processor.disconnect(edge).unwrap();
+        renderer.transport().seek(0);
+        renderer.fill_buffer(&mut out);
+        assert_eq!(out, [[0.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_compound_edits_publish_once() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let hub = add_mono_node(&mut processor, vec![1.0; 4]);
+        for _ in 0..3 {
+            let child = add_mono_node(&mut processor, vec![0.5; 4]);
+            processor
+                .connect(hub, Some(child), AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+            processor
+                .connect(child, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        let _renderer = processor.create_renderer().unwrap();
+        let held = |processor: &AudioProcessor<Mono<f32>>| {
+            processor.publisher.as_ref().unwrap().snapshots_held()
+        };
+        assert_eq!(held(&processor), 1);
+
+        // Three edges go, one new mix is sent
+        processor.remove_node(hub).unwrap();
+        assert_eq!(held(&processor), 2);
+        processor.undo().unwrap();
+        assert_eq!(held(&processor), 3);
+    }
+
+    #[test]
+    fn test_renderer_on_audio_thread_during_edits() {
+        let mut processor = AudioProcessor::<Stereo<f32>>::new();
+        let mut renderer = processor.create_renderer().unwrap();
+        let transport = processor.transport();
+        transport.play();
+        transport.set_loop_region(0, 4096).unwrap();
+
+        let audio_thread = std::thread::spawn(move || {
+            let mut out = [0.0; 512];
+            for _ in 0..2000 {
+                assert_eq!(renderer.fill_interleaved(&mut out), 256);
+            }
+        });
+        for i in 0..20 {
+            let node = processor
+                .add_node(AudioNode::new(
+                    AudioClip::new(vec![0.01 * i as f32; 8192], 44100).unwrap(),
+                    None,
+                ))
+                .unwrap();
+            processor
+                .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        audio_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_edits_and_reads_do_not_deadlock() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        const EDITS: usize = 100;
+
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let bus = add_mono_node(&mut processor, vec![0.0; 64]);
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let audio_graph = Arc::clone(&processor.audio_graph);
+        let (bus_node, root_node) = {
+            let graph = audio_graph.lock().unwrap();
+            let bus_node = Arc::clone(graph.try_get_node(bus).unwrap());
+            let root_node = Arc::clone(graph.try_get_node(graph.root).unwrap());
+            (bus_node, root_node)
+        };
+        let mut renderer = processor.create_renderer().unwrap();
+        processor.transport().play();
+
+        // Readers only hold handles into the graph while the processor itself
+        // moves to the editing thread, so every read races a propagation
+        let editing = Arc::new(AtomicBool::new(true));
+        let mut readers = Vec::new();
+        for node in [bus_node, root_node] {
+            let editing = Arc::clone(&editing);
+            readers.push(std::thread::spawn(move || {
+                while editing.load(Ordering::Acquire) {
+                    let node = node.lock().unwrap();
+                    node.with_output(|clip| clip.get_frames_ref().iter().count());
+                }
+            }));
+        }
+        let graph_editing = Arc::clone(&editing);
+        readers.push(std::thread::spawn(move || {
+            while graph_editing.load(Ordering::Acquire) {
+                let graph = audio_graph.lock().unwrap();
+                let root = graph.try_get_node(graph.root).unwrap().lock().unwrap();
+                root.with_output(|clip| clip.get_frames_ref().iter().count());
+            }
+        }));
+        let render_editing = Arc::clone(&editing);
+        readers.push(std::thread::spawn(move || {
+            let mut out = [[0.0]; 256];
+            while render_editing.load(Ordering::Acquire) {
+                renderer.fill_buffer(&mut out);
+            }
+        }));
+
+        // A deadlock would hang the test, so the work runs behind a watchdog
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for i in 0..EDITS {
+                let node = add_mono_node(&mut processor, vec![1.0; 64]);
+                let edge = processor
+                    .connect(node, Some(bus), mix_edge(MixParams::default()))
+                    .unwrap();
+                processor.trim_node(node, 0, 32).unwrap();
+                if i % 2 == 0 {
+                    processor.disconnect(edge).unwrap();
+                }
+            }
+            editing.store(false, Ordering::Release);
+            for reader in readers {
+                reader.join().unwrap();
+            }
+            done_tx.send(processor).unwrap();
+        });
+        let processor = done_rx
+            .recv_timeout(std::time::Duration::from_secs(60))
+            .expect("concurrent edits deadlocked");
+
+        let remaining = (EDITS / 2) as f32;
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..32], &vec![[remaining]; 32][..]);
+        assert_eq!(&root[32..64], &vec![[0.0]; 32][..]);
+    }
+
+    #[test]
+    fn test_diamond_applies_each_delta_once() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let source = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0]);
+        let bus_a = add_mono_node(&mut processor, vec![0.0; 4]);
+        let bus_b = add_mono_node(&mut processor, vec![0.0; 4]);
+        let sum = add_mono_node(&mut processor, vec![0.0; 4]);
+        for bus in [bus_a, bus_b] {
+            processor
+                .connect(bus, Some(sum), AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        processor
+            .connect(sum, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(
+                source,
+                Some(bus_a),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(
+                source,
+                Some(bus_b),
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(1),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..6], &[[1.0], [3.0], [5.0], [7.0], [4.0], [0.0]]);
+
+        // Both buses change, the sum must take both deltas before passing its own on
+        processor.trim_node(source, 1, 3).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..6], &[[0.0], [2.0], [5.0], [3.0], [0.0], [0.0]]);
+        let sum_frames = processor.get_node_frames_copy(sum);
+        assert_eq!(&sum_frames[..5], &root[..5]);
+    }
+
+    #[test]
+    fn test_shared_sources_reach_root_along_paths_of_different_length() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let source_1 = add_mono_node(&mut processor, vec![1.0; 4]);
+        let source_2 = add_mono_node(&mut processor, vec![10.0; 4]);
+        let bus_1 = add_mono_node(&mut processor, vec![0.0; 4]);
+        let bus_2 = add_mono_node(&mut processor, vec![0.0; 4]);
+        let add_edge = || AudioGraphEdge::new(AddOperation, "AddOp");
+        processor.connect(bus_1, None, add_edge()).unwrap();
+        processor.connect(bus_2, None, add_edge()).unwrap();
+        for source in [source_1, source_2] {
+            processor.connect(source, Some(bus_1), add_edge()).unwrap();
+            processor.connect(source, Some(bus_2), add_edge()).unwrap();
+        }
+        processor.connect(source_1, None, add_edge()).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..4], &[[23.0]; 4]);
+
+        processor.trim_node(source_1, 0, 2).unwrap();
+        processor.trim_node(source_2, 1, 4).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..5], &[[3.0], [23.0], [20.0], [20.0], [0.0]]);
+
+        let graph = processor.lock_audio_graph().unwrap();
+        let order = graph.dependents_in_order(source_1);
+        let position = |node| order.iter().position(|&n| n == node).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(position(bus_1) < position(graph.root));
+        assert!(position(bus_2) < position(graph.root));
+    }
+
+    #[test]
+    fn test_mix_edge_gain_and_pan() {
+        let mut processor = AudioProcessor::<Stereo<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0, 1.0, 0.5, 0.5], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        let edge = processor
+            .connect(n1, None, mix_edge(MixParams::default()))
+            .unwrap();
+
+        // Constant-power center sits at -3 dB per side
+        let center = std::f32::consts::FRAC_1_SQRT_2;
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(
+            &root[..2],
+            &[[center, center], [0.5 * center, 0.5 * center]],
+        );
+
+        processor
+            .update_mix_params(edge, |params| {
+                params.gain_db = 20.0 * 0.5f32.log10();
+                params.pan = -1.0;
+            })
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..3], &[[0.5, 0.0], [0.25, 0.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_mix_edge_mute_recomputes_downstream() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(create_simple_clip(), Some("n1")))
+            .unwrap();
+        let n2 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![10.0, 10.0, 10.0], 44100).unwrap(),
+                Some("n2"),
+            ))
+            .unwrap();
+        let edge = processor
+            .connect(n1, Some(n2), mix_edge(MixParams::default()))
+            .unwrap();
+        processor
+            .connect(n2, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..3], &[[11.0], [12.0], [13.0]]);
+
+        processor
+            .update_mix_params(edge, |params| params.mute = true)
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..3], &[[10.0], [10.0], [10.0]]);
+
+        processor
+            .update_mix_params(edge, |params| params.mute = false)
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..3], &[[11.0], [12.0], [13.0]]);
+    }
+
+    #[test]
+    fn test_mix_edge_solo() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![1.0; 3], 44100).unwrap(),
+                Some("n1"),
+            ))
+            .unwrap();
+        let n2 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![2.0; 5], 44100).unwrap(),
+                Some("n2"),
+            ))
+            .unwrap();
+        let n3 = processor
+            .add_node(AudioNode::new(
+                AudioClip::new(vec![4.0; 2], 44100).unwrap(),
+                Some("n3"),
+            ))
+            .unwrap();
+        let e1 = processor
+            .connect(n1, None, mix_edge(MixParams::default()))
+            .unwrap();
+        let solo = MixParams {
+            solo: true,
+            ..MixParams::default()
+        };
+        let e2 = processor.connect(n2, None, mix_edge(solo)).unwrap();
+
+        // A plain add edge is not part of solo
+        processor
+            .connect(n3, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..6], &[[6.0], [6.0], [2.0], [2.0], [2.0], [0.0]]);
+
+        processor
+            .update_mix_params(e1, |params| params.solo = true)
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..6], &[[7.0], [7.0], [3.0], [2.0], [2.0], [0.0]]);
+
+        processor.set_mix_params(e1, MixParams::default()).unwrap();
+        processor.disconnect(e2).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&root[..6], &[[5.0], [5.0], [1.0], [0.0], [0.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_mix_params_on_add_edge() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node(AudioNode::new(create_simple_clip(), Some("n1")))
+            .unwrap();
+        let edge = processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        assert!(matches!(
+            processor.set_mix_params(edge, MixParams::default()),
+            Err(AudioError::NotAMixEdge(_))
+        ));
+    }
+
+    fn add_mono_node(processor: &mut AudioProcessor<Mono<f32>>, samples: Vec<f32>) -> NodeIndex {
+        processor
+            .add_node(AudioNode::new(
+                AudioClip::new(samples, 44100).unwrap(),
+                None,
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_nonlinear_child_rebuilt_from_all_inputs() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let source = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0]);
+        let offset = add_mono_node(&mut processor, vec![1.0, 1.0, 1.0]);
+        let modulator = add_mono_node(&mut processor, vec![2.0, 2.0, -1.0]);
+
+        processor
+            .connect(source, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let ring_mod = processor
+            .connect(
+                modulator,
+                Some(source),
+                AudioGraphEdge::new(MultiplyOperation, "MulOp"),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[2.0], [4.0], [-3.0]]);
+
+        // A linear input into the same child goes in before the multiply
+        processor
+            .connect(
+                offset,
+                Some(source),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[4.0], [6.0], [-4.0]]);
+
+        // A change upstream of a non-linear edge cannot be applied as a delta
+        processor
+            .set_effect_chain(
+                modulator,
+                Some(AudioEffectChain::new().with_effect(gain(0.5))),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[2.0], [3.0], [-2.0]]);
+
+        processor
+            .set_effect_chain(offset, Some(AudioEffectChain::new().with_effect(invert())))
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[0.0], [1.0], [-1.0]]);
+
+        processor.disconnect(ring_mod).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..4], &[[0.0], [1.0], [2.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_subtract_cancels_identical_takes() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let take_a = add_mono_node(&mut processor, vec![0.5, -0.25, 0.75]);
+        let take_b = add_mono_node(&mut processor, vec![0.5, -0.25, 0.5]);
+        processor
+            .connect(take_a, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let edge = processor
+            .connect(
+                take_b,
+                None,
+                AudioGraphEdge::new(SubtractOperation, "SubOp"),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[0.0], [0.0], [0.25]]);
+
+        processor.disconnect(edge).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[0.5], [-0.25], [0.75]]);
+    }
+
+    #[test]
+    fn test_crossfade_between_clips() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let outgoing = add_mono_node(&mut processor, vec![1.0; 4]);
+        let incoming = processor
+            .add_node({
+                let mut node = AudioNode::new(AudioClip::new(vec![3.0; 4], 44100).unwrap(), None);
+                node.set_clip_start(2);
+                node
+            })
+            .unwrap();
+        let crossfade = CrossfadeOperation {
+            length: 2,
+            curve: CrossfadeCurve::Linear,
+        };
+        processor
+            .connect(
+                incoming,
+                Some(outgoing),
+                AudioGraphEdge::new(crossfade, "XfadeOp"),
+            )
+            .unwrap();
+        processor
+            .connect(outgoing, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(
+            &root[..7],
+            &[[1.0], [1.0], [1.0], [2.0], [3.0], [3.0], [0.0]]
+        );
+    }
+
+    #[test]
+    fn test_one_node_placed_at_several_offsets() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let sample = add_mono_node(&mut processor, vec![1.0, 0.5]);
+        let mut buses = Vec::new();
+        for offset in [0, 3, 4] {
+            let bus = add_mono_node(&mut processor, vec![]);
+            buses.push(bus);
+            processor
+                .connect(
+                    sample,
+                    Some(bus),
+                    AudioGraphEdge::new(AddOperation, "AddOp").with_offset(offset),
+                )
+                .unwrap();
+            processor
+                .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(
+            &root[..7],
+            &[[1.0], [0.5], [0.0], [1.0], [1.5], [0.5], [0.0]]
+        );
+
+        // A second edge to the same child is rejected whatever its offset
+        let duplicate = processor.connect(
+            sample,
+            Some(buses[0]),
+            AudioGraphEdge::new(AddOperation, "AddOp").with_offset(2),
+        );
+        assert!(matches!(duplicate, Err(AudioError::DuplicateEdge { .. })));
+    }
+
+    #[test]
+    fn test_negative_edge_offset() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = processor
+            .add_node({
+                let mut node = AudioNode::new(create_simple_clip(), None);
+                node.set_clip_start(4);
+                node
+            })
+            .unwrap();
+        let too_early = processor.connect(
+            n1,
+            None,
+            AudioGraphEdge::new(AddOperation, "AddOp").with_offset(-5),
+        );
+        assert!(matches!(
+            too_early,
+            Err(AudioError::NegativePlacement { offset: -5, .. })
+        ));
+
+        let edge = processor
+            .connect(
+                n1,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(-3),
+            )
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..5], &[[0.0], [1.0], [2.0], [3.0], [0.0]]);
+
+        // Moving the placement clears the old spot
+        processor.set_edge_offset(edge, 0).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(
+            &root[..8],
+            &[[0.0], [0.0], [0.0], [0.0], [1.0], [2.0], [3.0], [0.0]]
+        );
+    }
+
+    #[test]
+    fn test_edge_offset_seconds_feeds_children_at_different_positions() {
+        let mut processor = AudioProcessor::<Mono<f32>>::with_sample_rate(10);
+        let sample = add_mono_node(&mut processor, vec![1.0; 2]);
+        let bus_a = add_mono_node(&mut processor, vec![0.0; 2]);
+        let bus_b = add_mono_node(&mut processor, vec![0.0; 2]);
+
+        let to_a = processor
+            .connect(
+                sample,
+                Some(bus_a),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(
+                sample,
+                Some(bus_b),
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(5),
+            )
+            .unwrap();
+        processor.set_edge_offset_seconds(to_a, 0.2).unwrap();
+
+        assert_eq!(
+            processor.get_node_frames_copy(bus_a),
+            vec![[0.0], [0.0], [1.0], [1.0]]
+        );
+        assert_eq!(processor.get_node_frames_copy(bus_b)[5..], [[1.0], [1.0]]);
+    }
+
+    #[test]
+    fn test_max_edge_into_root() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let n1 = add_mono_node(&mut processor, vec![0.5, -0.5, 0.25]);
+        let n2 = add_mono_node(&mut processor, vec![0.1, 0.1]);
+        processor
+            .connect(n1, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(n2, None, AudioGraphEdge::new(MaxOperation, "MaxOp"))
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..3], &[[0.5], [0.1], [0.25]]);
+    }
+
+    #[test]
+    fn test_sidechain_ducking_tracks_mix_changes() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let pad = add_mono_node(&mut processor, vec![1.0; 4]);
+        let kick = add_mono_node(&mut processor, vec![0.0, 1.0, 1.0, 0.0]);
+        processor
+            .connect(pad, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let duck = DuckOperation {
+            depth: 1.0,
+            attack: 0,
+            release: 0,
+        };
+        processor
+            .connect(kick, Some(pad), AudioGraphEdge::new(duck, "DuckOp"))
+            .unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..4], &[[1.0], [0.0], [0.0], [1.0]]);
+
+        let pad_edge = processor
+            .lock_audio_graph()
+            .unwrap()
+            .graph
+            .find_edge(pad, processor.root_node_index)
+            .unwrap();
+        processor.disconnect(pad_edge).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..4], &[[0.0]; 4]);
+    }
+
+    #[test]
+    fn test_resample_quality_used_for_new_clips() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        assert_eq!(processor.get_resample_quality(), ResampleQuality::Linear);
+        processor.set_resample_quality(ResampleQuality::Polyphase);
+
+        let samples: Vec<f32> = (0..9600).map(|i| (i as f32 * 0.01).sin()).collect();
+        let clip = AudioClip::<Mono<f32>>::new(samples, 96000).unwrap();
+        let expected = clip.resample_with_quality(44100, ResampleQuality::Polyphase);
+
+        let node = processor
+            .add_node_from_clip(AudioClipEnum::Mono(clip), Some("hires"))
+            .unwrap();
+        assert_eq!(
+            processor.get_node_frames_copy(node),
+            expected.get_frames_ref()
+        );
+    }
+
+    #[test]
+    fn test_effect_chain_applied_before_edge() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+
+        let chain = AudioEffectChain::new().with_effect(gain(2.0));
+        let node1 = processor
+            .add_node(AudioNode::with_effects(
+                create_simple_clip(),
+                chain,
+                Some("node1"),
+            ))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+
+        processor
+            .connect(
+                node1,
+                Some(node2),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        let frames = processor.get_node_frames_copy(node2);
+        assert_eq!(frames, vec![[3.0], [6.0], [9.0]]);
+    }
 
-        processor.print_graph();
+    #[test]
+    fn test_effect_parameter_change_propagates() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
 
-        let expected_samples = [[7.0], [14.0], [21.0]];
+        let node1 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node1"))
+            .unwrap();
+        let node2 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node2"))
+            .unwrap();
+        let node3 = processor
+            .add_node_from_clip(AudioClipEnum::Mono(create_simple_clip()), Some("node3"))
+            .unwrap();
 
-        for expected in &expected_samples {
-            let sample = processor
-                .get_node_or_root_sample(None)
-                .expect("Expected a sample");
-            assert_eq!(sample, *expected);
-        }
+        processor
+            .connect(
+                node1,
+                Some(node2),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(
+                node1,
+                Some(node3),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        processor
+            .connect(node2, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(node3, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
 
-        let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node8, Some(node5), add_edge);
-        let frames = processor.get_node_frames_copy(node5);
-        let expected_frames = vec![[4.0], [8.0], [12.0]]; // previous node5 + node8
-        assert_eq!(frames, expected_frames);
-        let frames = processor.get_node_frames_copy(node4);
-        let expected_frames = vec![[8.0], [16.0], [24.0]];
-        assert_eq!(frames, expected_frames);
+        processor
+            .set_effect_chain(node1, Some(AudioEffectChain::new().with_effect(invert())))
+            .unwrap();
+        assert_eq!(processor.get_node_frames_copy(node2), vec![[0.0]; 3]);
+        assert_eq!(processor.get_node_frames_copy(node3), vec![[0.0]; 3]);
 
-        let expected_samples = [[8.0], [16.0], [24.0]];
-        processor.set_root_frame_idx(0);
-        for expected in &expected_samples {
-            let sample = processor
-                .get_node_or_root_sample(None)
-                .expect("Expected a sample");
-            assert_eq!(sample, *expected);
-        }
+        processor
+            .update_effect_chain(node1, |chain| {
+                chain.set_effect(0, gain(3.0));
+            })
+            .unwrap();
+        assert_eq!(
+            processor.get_node_frames_copy(node2),
+            vec![[4.0], [8.0], [12.0]]
+        );
+
+        let root_frames = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(root_frames[0..3], [[8.0], [16.0], [24.0]]);
+
+        processor.set_effect_chain(node1, None).unwrap();
+        let root_frames = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(root_frames[0..3], [[4.0], [8.0], [12.0]]);
     }
 
     fn create_unit_node(size: usize) -> AudioNode<Mono<f32>> {
@@ -356,7 +3345,7 @@ mod tests {
         for _ in 0..size {
             frames.push(1.0);
         }
-        let clip = AudioClip::<Mono<f32>>::new(frames, 44100);
+        let clip = AudioClip::<Mono<f32>>::new(frames, 44100).unwrap();
         AudioNode::new(clip, None)
     }
     #[test]
@@ -373,19 +3362,23 @@ mod tests {
         node3.set_clip_start(0);
         node4.set_clip_start(7);
 
-        let node_id1 = processor.add_node(node1);
-        let node_id2 = processor.add_node(node2);
-        let node_id3 = processor.add_node(node3);
-        let node_id4 = processor.add_node(node4);
+        let node_id1 = processor.add_node(node1).unwrap();
+        let node_id2 = processor.add_node(node2).unwrap();
+        let node_id3 = processor.add_node(node3).unwrap();
+        let node_id4 = processor.add_node(node4).unwrap();
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node_id1, Some(node_id2), add_edge);
+        processor
+            .connect(node_id1, Some(node_id2), add_edge)
+            .unwrap();
         let frames = processor.get_node_frames_copy(node_id2);
         let expected_frames = vec![[1.0], [1.0], [1.0], [0.0], [1.0], [1.0], [1.0]]; // previous node5 + node8
         assert_eq!(frames, expected_frames);
 
         let add_edge = AudioGraphEdge::new(AddOperation, "AddOp");
-        processor.connect(node_id3, Some(node_id4), add_edge);
+        processor
+            .connect(node_id3, Some(node_id4), add_edge)
+            .unwrap();
 
         processor.print_graph();
         let frames = processor.get_node_frames_copy(node_id4);
@@ -420,15 +3413,15 @@ mod tests {
         node4.set_clip_start(7);
         node5.set_clip_start(2);
 
-        let node_id1 = processor.add_node(node1); // 0-3
-        let node_id2 = processor.add_node(node2); // 4-5
-        let node_id3 = processor.add_node(node3); // 0-3
-        let node_id4 = processor.add_node(node4); // 7-3
-        let node_id5 = processor.add_node(node5); // 2-5
+        let node_id1 = processor.add_node(node1).unwrap(); // 0-3
+        let node_id2 = processor.add_node(node2).unwrap(); // 4-5
+        let node_id3 = processor.add_node(node3).unwrap(); // 0-3
+        let node_id4 = processor.add_node(node4).unwrap(); // 7-3
+        let node_id5 = processor.add_node(node5).unwrap(); // 2-5
 
         // Connect Node5 directly to the Root Node
         let root_edge = AudioGraphEdge::new(AddOperation, "RootOp");
-        processor.connect(node_id5, None, root_edge);
+        processor.connect(node_id5, None, root_edge).unwrap();
 
         let frames_node_root = processor.get_node_frames_copy(processor.root_node_index);
         let expected_frames_root = vec![[0.0], [0.0], [1.0], [1.0], [1.0], [1.0], [1.0], [0.0]]; // node5 + node1 from time frame 2
@@ -436,7 +3429,9 @@ mod tests {
 
         // Connect Node1 and Node2 to Node5
         let add_edge1 = AudioGraphEdge::new(AddOperation, "AddOp1");
-        processor.connect(node_id1, Some(node_id5), add_edge1);
+        processor
+            .connect(node_id1, Some(node_id5), add_edge1)
+            .unwrap();
 
         let frames_node1 = processor.get_node_frames_copy(node_id5);
         let expected_frames_node1 = vec![[1.0], [1.0], [2.0], [1.0], [1.0], [1.0], [1.0]]; // node5 + node1 from time frame 2
@@ -447,7 +3442,9 @@ mod tests {
         assert_eq!(frames_node_root[0..8], expected_frames_root);
 
         let add_edge2 = AudioGraphEdge::new(AddOperation, "AddOp2");
-        processor.connect(node_id2, Some(node_id5), add_edge2);
+        processor
+            .connect(node_id2, Some(node_id5), add_edge2)
+            .unwrap();
 
         let node_5 = processor.get_node_frames_copy(node_id5);
         let expected_node_5 = vec![
@@ -480,7 +3477,9 @@ mod tests {
 
         // Connect Node3 and Node4 to Node2
         let add_edge3 = AudioGraphEdge::new(AddOperation, "AddOp3");
-        processor.connect(node_id3, Some(node_id2), add_edge3);
+        processor
+            .connect(node_id3, Some(node_id2), add_edge3)
+            .unwrap();
 
         let node_2 = processor.get_node_frames_copy(node_id2);
         let expected_node_2 = vec![
@@ -526,7 +3525,9 @@ mod tests {
         assert_eq!(frames_node_root[0..10], expected_frames_root);
 
         let add_edge4 = AudioGraphEdge::new(AddOperation, "AddOp4");
-        processor.connect(node_id4, Some(node_id2), add_edge4);
+        processor
+            .connect(node_id4, Some(node_id2), add_edge4)
+            .unwrap();
 
         let node_2 = processor.get_node_frames_copy(node_id2);
         let expected_node_2 = vec![
@@ -575,4 +3576,851 @@ mod tests {
         assert_eq!(frames_node_root[0..11], expected_frames_root);
         processor.print_graph();
     }
+
+    #[test]
+    fn test_trim_node_is_non_destructive() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0]);
+        processor
+            .connect(
+                clip,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(2),
+            )
+            .unwrap();
+
+        processor.trim_node(clip, 1, 3).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(
+            &root[..7],
+            &[[0.0], [0.0], [0.0], [2.0], [3.0], [0.0], [0.0]]
+        );
+
+        processor.trim_node(clip, 0, 4).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(
+            &root[..7],
+            &[[0.0], [0.0], [1.0], [2.0], [3.0], [4.0], [0.0]]
+        );
+
+        assert!(matches!(
+            processor.trim_node(clip, 2, 5),
+            Err(AudioError::InvalidRegion { start: 2, end: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_slip_node_keeps_bounds() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        processor
+            .connect(clip, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor.trim_node(clip, 1, 3).unwrap();
+
+        processor.slip_node(clip, 2).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..5], &[[0.0], [4.0], [5.0], [0.0], [0.0]]);
+
+        assert!(processor.slip_node(clip, 1).is_err());
+        processor.slip_node(clip, -3).unwrap();
+        let root = processor.get_node_frames_copy(processor.root_node_index);
+        assert_eq!(&root[..5], &[[0.0], [1.0], [2.0], [0.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_split_node_shares_source() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0]);
+        let bus = add_mono_node(&mut processor, vec![0.0; 4]);
+        processor
+            .connect(clip, Some(bus), mix_edge(MixParams::default()))
+            .unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let before = processor.get_node_frames_copy(processor.root_node_index);
+
+        assert!(matches!(
+            processor.split_node(clip, 4),
+            Err(AudioError::InvalidSplit(4))
+        ));
+        let tail = processor.split_node(clip, 2).unwrap();
+        let after = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(&after[..4], &before[..4]);
+
+        let tail_edge = {
+            let graph = processor.lock_audio_graph().unwrap();
+            let head_node = graph.try_get_node(clip).unwrap().lock().unwrap();
+            let tail_node = graph.try_get_node(tail).unwrap().lock().unwrap();
+            assert!(head_node
+                .get_source_clip()
+                .ptr_eq(tail_node.get_source_clip()));
+            assert_eq!(tail_node.get_region_bounds(), (2, 4));
+
+            let edge = graph
+                .graph
+                .edges_directed(tail, Direction::Outgoing)
+                .next()
+                .unwrap();
+            assert_eq!(edge.target(), bus);
+            edge.id()
+        };
+
+        // The halves now move independently
+        processor.set_edge_offset(tail_edge, 3).unwrap();
+        let moved = processor.get_node_frames_copy(processor.root_node_index);
+        assert_frames_close(
+            &moved[..8],
+            &[[1.0], [2.0], [0.0], [0.0], [0.0], [3.0], [4.0], [0.0]],
+        );
+    }
+
+    fn root_prefix(processor: &AudioProcessor<Mono<f32>>, len: usize) -> Vec<[f32; 1]> {
+        let mut frames = processor.get_node_frames_copy(processor.root_node_index);
+        frames.resize(len, [0.0]);
+        frames.truncate(len);
+        frames
+    }
+
+    #[test]
+    fn test_undo_redo_connect_offset_and_trim() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0]);
+        processor.clear_history();
+        let empty = root_prefix(&processor, 8);
+
+        let edge = processor
+            .connect(clip, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let connected = root_prefix(&processor, 8);
+        processor.set_edge_offset(edge, 2).unwrap();
+        let moved = root_prefix(&processor, 8);
+        processor.trim_node(clip, 1, 3).unwrap();
+        let trimmed = root_prefix(&processor, 8);
+
+        assert!(processor.undo().unwrap());
+        assert_frames_close(&root_prefix(&processor, 8), &moved);
+        assert!(processor.undo().unwrap());
+        assert_frames_close(&root_prefix(&processor, 8), &connected);
+        assert!(processor.undo().unwrap());
+        assert_frames_close(&root_prefix(&processor, 8), &empty);
+        assert!(!processor.undo().unwrap());
+
+        // The connect comes back under a new edge index, later steps follow it
+        for _ in 0..3 {
+            assert!(processor.redo().unwrap());
+        }
+        assert!(!processor.can_redo());
+        assert_frames_close(&root_prefix(&processor, 8), &trimmed);
+        assert_frames_close(
+            &trimmed,
+            &[[0.0], [0.0], [0.0], [2.0], [3.0], [0.0], [0.0], [0.0]],
+        );
+    }
+
+    #[test]
+    fn test_undo_mix_params_and_disconnect() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 1.0, 1.0, 1.0]);
+        let edge = processor
+            .connect(clip, None, mix_edge(MixParams::default()))
+            .unwrap();
+        let unity = root_prefix(&processor, 4);
+
+        processor
+            .set_mix_params(
+                edge,
+                MixParams {
+                    gain_db: -6.0,
+                    ..MixParams::default()
+                },
+            )
+            .unwrap();
+        let quieter = root_prefix(&processor, 4);
+        processor.disconnect(edge).unwrap();
+        assert_frames_close(&root_prefix(&processor, 4), &[[0.0]; 4]);
+
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 4), &quieter);
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 4), &unity);
+
+        processor.redo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 4), &quieter);
+
+        // A new edit drops what was left to redo
+        processor.slip_node(clip, 0).unwrap();
+        assert!(!processor.can_redo());
+    }
+
+    #[test]
+    fn test_undo_remove_and_split_restore_root() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0, 4.0]);
+        let bus = add_mono_node(&mut processor, vec![0.0; 4]);
+        processor
+            .connect(clip, Some(bus), mix_edge(MixParams::default()))
+            .unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let before = root_prefix(&processor, 8);
+        let node_count = processor.lock_audio_graph().unwrap().graph.node_count();
+
+        processor.remove_node(bus).unwrap();
+        assert_frames_close(&root_prefix(&processor, 8), &[[0.0]; 8]);
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 8), &before);
+        processor.redo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 8), &[[0.0]; 8]);
+        processor.undo().unwrap();
+
+        // The bus is back, though possibly under a new index
+        let bus = {
+            let graph = processor.lock_audio_graph().unwrap();
+            let edge = graph
+                .graph
+                .edges_directed(graph.root, Direction::Incoming)
+                .next()
+                .unwrap();
+            edge.source()
+        };
+        let tail = processor.split_node(clip, 2).unwrap();
+        let tail_edge = processor
+            .lock_audio_graph()
+            .unwrap()
+            .graph
+            .edges_directed(tail, Direction::Outgoing)
+            .find(|edge| edge.target() == bus)
+            .unwrap()
+            .id();
+        processor.set_edge_offset(tail_edge, 3).unwrap();
+
+        processor.undo().unwrap();
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 8), &before);
+        assert_eq!(
+            processor.lock_audio_graph().unwrap().graph.node_count(),
+            node_count
+        );
+        let graph = processor.lock_audio_graph().unwrap();
+        let head = graph.try_get_node(clip).unwrap().lock().unwrap();
+        assert_eq!(head.get_region_bounds(), (0, 4));
+    }
+
+    #[test]
+    fn test_parent_delta_goes_through_edge_operation() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0]);
+        let bus = add_mono_node(&mut processor, vec![0.0; 3]);
+        processor
+            .connect(
+                clip,
+                Some(bus),
+                AudioGraphEdge::new(SubtractOperation, "SubOp"),
+            )
+            .unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        // The change to the clip reaches the root through the subtraction
+        processor.trim_node(clip, 1, 3).unwrap();
+        assert_frames_close(&root_prefix(&processor, 3), &[[0.0], [-2.0], [-3.0]]);
+    }
+
+    // `clip` feeds the root directly and through a bus, placed at `start`
+    fn diamond_with_clip_at(start: usize) -> (AudioProcessor<Mono<f32>>, NodeIndex, NodeIndex) {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let mut node = AudioNode::new(AudioClip::new(vec![1.0, 2.0, 3.0], 44100).unwrap(), None);
+        node.set_clip_start(start);
+        let clip = processor.add_node(node).unwrap();
+        let bus = add_mono_node(&mut processor, vec![0.5; 4]);
+        processor
+            .connect(
+                clip,
+                Some(bus),
+                mix_edge(MixParams {
+                    gain_db: -6.0,
+                    ..MixParams::default()
+                }),
+            )
+            .unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(
+                clip,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(1),
+            )
+            .unwrap();
+        (processor, clip, bus)
+    }
+
+    #[test]
+    fn test_move_node_remixes_every_child() {
+        let (mut processor, clip, _) = diamond_with_clip_at(0);
+        let (expected, _, _) = diamond_with_clip_at(5);
+        processor.move_node(clip, 5).unwrap();
+        assert_frames_close(&root_prefix(&processor, 12), &root_prefix(&expected, 12));
+
+        // Later edits start from the new position
+        processor.trim_node(clip, 1, 3).unwrap();
+        processor.move_node(clip, 2).unwrap();
+        let (mut expected, _, _) = diamond_with_clip_at(2);
+        expected.trim_node(clip, 1, 3).unwrap();
+        assert_frames_close(&root_prefix(&processor, 12), &root_prefix(&expected, 12));
+    }
+
+    #[test]
+    fn test_move_bus_keeps_inputs_in_place() {
+        let (mut processor, _, bus) = diamond_with_clip_at(0);
+        processor.move_node(bus, 3).unwrap();
+
+        let mut expected = AudioProcessor::<Mono<f32>>::new();
+        let clip = add_mono_node(&mut expected, vec![1.0, 2.0, 3.0]);
+        let mut node = AudioNode::new(AudioClip::new(vec![0.5; 4], 44100).unwrap(), None);
+        node.set_clip_start(3);
+        let moved_bus = expected.add_node(node).unwrap();
+        expected
+            .connect(
+                clip,
+                Some(moved_bus),
+                mix_edge(MixParams {
+                    gain_db: -6.0,
+                    ..MixParams::default()
+                }),
+            )
+            .unwrap();
+        expected
+            .connect(moved_bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        expected
+            .connect(
+                clip,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(1),
+            )
+            .unwrap();
+        assert_frames_close(&root_prefix(&processor, 10), &root_prefix(&expected, 10));
+    }
+
+    #[test]
+    fn test_move_node_checks_placement_and_undoes() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let mut node = AudioNode::new(AudioClip::new(vec![1.0, 2.0], 44100).unwrap(), None);
+        node.set_clip_start(4);
+        let clip = processor.add_node(node).unwrap();
+        processor
+            .connect(
+                clip,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(-2),
+            )
+            .unwrap();
+        let before = root_prefix(&processor, 8);
+
+        assert!(matches!(
+            processor.move_node(clip, 1),
+            Err(AudioError::NegativePlacement { offset: -2, .. })
+        ));
+        assert!(matches!(
+            processor.move_node(processor.root_node_index, 1),
+            Err(AudioError::RootMove)
+        ));
+
+        processor.move_node(clip, 5).unwrap();
+        assert_frames_close(
+            &root_prefix(&processor, 8),
+            &[[0.0], [0.0], [0.0], [1.0], [2.0], [0.0], [0.0], [0.0]],
+        );
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 8), &before);
+    }
+
+    // Linear, non-linear and offset edges plus an effect chain, so every kind
+    // of input has to come out the same in both render modes
+    fn build_mixed_graph(processor: &mut AudioProcessor<Mono<f32>>) {
+        let ramp: Vec<f32> = (0..24).map(|i| i as f32 / 8.0).collect();
+        let lead = add_mono_node(processor, ramp);
+        let bus = add_mono_node(processor, vec![0.25; 8]);
+        let echo = add_mono_node(processor, vec![0.5, -0.5, 1.0, 0.0, 0.75]);
+        let pad = add_mono_node(processor, vec![1.0; 30]);
+        let kick = add_mono_node(processor, vec![0.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        let swell = processor
+            .add_node({
+                let mut node = AudioNode::new(AudioClip::new(vec![2.0; 10], 44100).unwrap(), None);
+                node.set_clip_start(12);
+                node
+            })
+            .unwrap();
+
+        processor
+            .connect(
+                lead,
+                Some(bus),
+                mix_edge(MixParams {
+                    gain_db: -6.0,
+                    ..MixParams::default()
+                }),
+            )
+            .unwrap();
+        processor
+            .connect(
+                echo,
+                Some(bus),
+                AudioGraphEdge::new(SubtractOperation, "SubOp").with_offset(5),
+            )
+            .unwrap();
+        let crossfade = CrossfadeOperation {
+            length: 6,
+            curve: CrossfadeCurve::EqualPower,
+        };
+        processor
+            .connect(swell, Some(bus), AudioGraphEdge::new(crossfade, "XfadeOp"))
+            .unwrap();
+        let effect_chain = AudioEffectChain::new()
+            .with_effect(fade_in(10))
+            .with_effect(gain(0.8));
+        processor.set_effect_chain(bus, Some(effect_chain)).unwrap();
+        processor
+            .connect(bus, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        let duck = DuckOperation {
+            depth: 0.8,
+            attack: 1,
+            release: 3,
+        };
+        processor
+            .connect(
+                kick,
+                Some(pad),
+                AudioGraphEdge::new(duck, "DuckOp").with_offset(4),
+            )
+            .unwrap();
+        processor
+            .connect(
+                pad,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(3),
+            )
+            .unwrap();
+    }
+
+    fn render_in_blocks(
+        processor: &AudioProcessor<Mono<f32>>,
+        len: usize,
+        block_len: usize,
+    ) -> Vec<[f32; 1]> {
+        let mut frames = vec![[0.0]; len];
+        for (i, block) in frames.chunks_mut(block_len).enumerate() {
+            processor.render_block(None, i * block_len, block).unwrap();
+        }
+        frames
+    }
+
+    #[test]
+    fn test_lazy_render_matches_cached_mix() {
+        // A fade out depends on where the bus ends, which inputs decide
+        let bus = NodeIndex::new(2);
+        let mut cached = AudioProcessor::<Mono<f32>>::new();
+        build_mixed_graph(&mut cached);
+        cached
+            .update_effect_chain(bus, |chain| chain.add_effect(fade_out(7)))
+            .unwrap();
+        let expected = root_prefix(&cached, 48);
+
+        let mut lazy = AudioProcessor::<Mono<f32>>::new();
+        lazy.set_render_mode(RenderMode::Lazy).unwrap();
+        build_mixed_graph(&mut lazy);
+        lazy.update_effect_chain(bus, |chain| chain.add_effect(fade_out(7)))
+            .unwrap();
+        // Small blocks cut through the crossfade and the duck's envelope
+        for block_len in [1, 4, 7, 48] {
+            assert_frames_close(&render_in_blocks(&lazy, 48, block_len), &expected);
+        }
+
+        let graph = lazy.lock_audio_graph().unwrap();
+        for node_idx in graph.graph.node_indices() {
+            let node = graph.try_get_node(node_idx).unwrap().lock().unwrap();
+            assert!(!node.is_buffered());
+            assert_eq!(node.with_buffers(|buffers| buffers.clip.get_length()), 0);
+        }
+    }
+
+    #[test]
+    fn test_lazy_render_rejects_filters() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let node = add_mono_node(&mut processor, vec![1.0; 8]);
+        processor
+            .connect(node, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .set_effect_chain(
+                node,
+                Some(AudioEffectChain::new().with_effect(low_pass(1000.0, BUTTERWORTH_Q))),
+            )
+            .unwrap();
+        assert!(matches!(
+            processor.set_render_mode(RenderMode::Lazy),
+            Err(AudioError::UnrenderableEffect(idx)) if idx == node
+        ));
+        assert_eq!(processor.get_render_mode(), RenderMode::Cached);
+
+        processor.set_effect_chain(node, None).unwrap();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let filtered = processor.set_effect_chain(
+            node,
+            Some(AudioEffectChain::new().with_effect(high_pass(1000.0, BUTTERWORTH_Q))),
+        );
+        assert!(matches!(filtered, Err(AudioError::UnrenderableEffect(_))));
+        processor
+            .set_effect_chain(node, Some(AudioEffectChain::new().with_effect(gain(0.5))))
+            .unwrap();
+        assert_eq!(render_in_blocks(&processor, 8, 3), vec![[0.5]; 8]);
+    }
+
+    #[test]
+    fn test_switching_render_modes_keeps_the_mix() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        build_mixed_graph(&mut processor);
+        let expected = root_prefix(&processor, 48);
+
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        assert_frames_close(&render_in_blocks(&processor, 48, 5), &expected);
+
+        // Edits made while lazy are picked up by the rebuild
+        let lead = NodeIndex::new(1);
+        processor.trim_node(lead, 4, 20).unwrap();
+        processor.move_node(lead, 2).unwrap();
+        let edited = render_in_blocks(&processor, 48, 5);
+
+        processor.set_render_mode(RenderMode::Cached).unwrap();
+        assert_frames_close(&root_prefix(&processor, 48), &edited);
+        processor.undo().unwrap();
+        processor.undo().unwrap();
+        assert_frames_close(&root_prefix(&processor, 48), &expected);
+    }
+
+    #[test]
+    fn test_lazy_render_visits_shared_nodes_once() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let mut tip = add_mono_node(&mut processor, vec![1.0; 4]);
+        for _ in 0..24 {
+            let joined = add_mono_node(&mut processor, vec![0.0; 4]);
+            for _ in 0..2 {
+                let side = add_mono_node(&mut processor, vec![0.0; 4]);
+                for (parent, child) in [(tip, side), (side, joined)] {
+                    processor
+                        .connect(
+                            parent,
+                            Some(child),
+                            AudioGraphEdge::new(AddOperation, "AddOp"),
+                        )
+                        .unwrap();
+                }
+            }
+            tip = joined;
+        }
+        processor
+            .connect(tip, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+
+        // One render per path down from the first node would never finish
+        assert_eq!(render_in_blocks(&processor, 4, 4), vec![[16_777_216.0]; 4]);
+    }
+
+    #[test]
+    fn test_lazy_publish_matches_a_full_render() {
+        fn assert_published(processor: &AudioProcessor<Mono<f32>>) {
+            let full = processor
+                .root_output_frames(&processor.lock_audio_graph().unwrap())
+                .unwrap();
+            let published = processor.publisher.as_ref().unwrap().latest();
+            assert_frames_close(&published, &full);
+        }
+
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let _renderer = processor.create_renderer().unwrap();
+        build_mixed_graph(&mut processor);
+        assert_published(&processor);
+
+        let [lead, bus, echo, kick] = [1, 2, 3, 5].map(NodeIndex::new);
+        let find_edge = |processor: &AudioProcessor<Mono<f32>>, parent, child| {
+            let graph = processor.lock_audio_graph().unwrap();
+            graph.graph.find_edge(parent, child).unwrap()
+        };
+        processor.move_node(kick, 9).unwrap();
+        assert_published(&processor);
+        processor.trim_node(lead, 4, 20).unwrap();
+        assert_published(&processor);
+        let echo_edge = find_edge(&processor, echo, bus);
+        processor.set_edge_offset(echo_edge, 11).unwrap();
+        assert_published(&processor);
+        let lead_edge = find_edge(&processor, lead, bus);
+        processor
+            .update_mix_params(lead_edge, |params| params.solo = true)
+            .unwrap();
+        assert_published(&processor);
+        processor
+            .update_effect_chain(bus, |chain| chain.add_effect(fade_out(5)))
+            .unwrap();
+        assert_published(&processor);
+        processor.remove_node(echo).unwrap();
+        assert_published(&processor);
+        while processor.undo().unwrap() {
+            assert_published(&processor);
+        }
+    }
+
+    #[test]
+    fn test_lazy_publish_renders_only_what_changed() {
+        let path = temp_path("stream_partial_publish.wav");
+        write_float_wav(&path, 1, 44100, &vec![0.25; 100_000]);
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let stream = processor.add_node_from_stream(&path, None).unwrap();
+        processor
+            .connect(stream, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        let _renderer = processor.create_renderer().unwrap();
+        truncate_file(&path, 4000);
+
+        // Rendering the whole root again would have to read the stream
+        let late = add_mono_node(&mut processor, vec![0.5; 8]);
+        let edge = AudioGraphEdge::new(AddOperation, "AddOp").with_offset(200_000);
+        processor.connect(late, None, edge).unwrap();
+        let published = processor.publisher.as_ref().unwrap().latest();
+        assert_eq!(published[50_000], [0.25]);
+        assert_eq!(published[150_000], [0.0]);
+        assert_eq!(published[200_007], [0.5]);
+        assert_eq!(published[200_008], [0.0]);
+
+        let mut block = vec![[0.0]; 8];
+        assert!(processor.render_block(None, 50_000, &mut block).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_lazy_playback() {
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let clip = add_mono_node(&mut processor, vec![1.0, 2.0, 3.0]);
+        processor
+            .connect(
+                clip,
+                None,
+                AudioGraphEdge::new(AddOperation, "AddOp").with_offset(1),
+            )
+            .unwrap();
+
+        processor.transport().play();
+        let mut out = [[9.0]; 4];
+        assert_eq!(processor.fill_buffer(&mut out).unwrap(), 4);
+        assert_eq!(out, [[0.0], [1.0], [2.0], [3.0]]);
+
+        // Renderers get the root rendered whole, a block at a time
+        let mut renderer = processor.create_renderer().unwrap();
+        renderer.transport().seek(1);
+        renderer.fill_buffer(&mut out);
+        assert_eq!(out, [[1.0], [2.0], [3.0], [0.0]]);
+    }
+
+    fn is_stream_loaded(processor: &AudioProcessor<Mono<f32>>, node_idx: NodeIndex) -> bool {
+        let graph = processor.lock_audio_graph().unwrap();
+        let node = graph.try_get_node(node_idx).unwrap().lock().unwrap();
+        match node.get_source_clip() {
+            ClipSource::Stream(stream_clip) => stream_clip.is_loaded(),
+            ClipSource::Memory(_) => panic!("node is not streamed"),
+        }
+    }
+
+    #[test]
+    fn test_streamed_node_mixes_like_a_loaded_one() {
+        let samples: Vec<f32> = (0..10_000)
+            .map(|i| ((i % 100) as f32 - 50.0) / 50.0)
+            .collect();
+        let path = temp_path("stream_node.wav");
+        write_float_wav(&path, 1, 44100, &samples);
+
+        for render_mode in [RenderMode::Cached, RenderMode::Lazy] {
+            let mut processor = AudioProcessor::<Mono<f32>>::new();
+            processor.set_render_mode(render_mode).unwrap();
+            let stream = processor
+                .add_node_from_stream(&path, Some("stream"))
+                .unwrap();
+            let gate = add_mono_node(&mut processor, vec![0.5; 6000]);
+            processor
+                .connect(
+                    stream,
+                    Some(gate),
+                    AudioGraphEdge::new(AddOperation, "AddOp").with_offset(1000),
+                )
+                .unwrap();
+            processor
+                .connect(gate, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+            processor.trim_node(stream, 100, 9000).unwrap();
+
+            let mut expected = vec![[0.0]; 11_000];
+            for (t, frame) in expected.iter_mut().enumerate() {
+                if t < 6000 {
+                    frame[0] += 0.5;
+                }
+                if (1100..10_000).contains(&t) {
+                    frame[0] += samples[t - 1000];
+                }
+            }
+            assert_frames_close(&render_in_blocks(&processor, 11_000, 512), &expected);
+            // Only rendering reads the file, nothing needs it whole
+            assert!(!is_stream_loaded(&processor, stream));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_stream_reads_reach_the_caller() {
+        let path = temp_path("stream_truncated_node.wav");
+        write_float_wav(&path, 1, 44100, &[0.25; 10_000]);
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        processor.set_render_mode(RenderMode::Lazy).unwrap();
+        let stream = processor.add_node_from_stream(&path, None).unwrap();
+        processor
+            .connect(stream, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        truncate_file(&path, 4000);
+
+        // Past the material is still plain silence
+        let mut block = vec![[1.0]; 512];
+        processor.render_block(None, 20_000, &mut block).unwrap();
+        assert_eq!(block[0], [0.0]);
+        assert!(processor.render_block(None, 5000, &mut block).is_err());
+        processor.transport().play();
+        block.fill([1.0]);
+        assert!(processor.fill_buffer(&mut block).is_err());
+        assert_eq!(block[0], [0.0]);
+        assert!(processor.set_render_mode(RenderMode::Cached).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn edge_count(processor: &AudioProcessor<Mono<f32>>) -> usize {
+        processor.lock_audio_graph().unwrap().graph.edge_count()
+    }
+
+    #[test]
+    fn test_failed_edits_leave_the_graph_as_it_was() {
+        let path = temp_path("stream_failed_edit.wav");
+        write_float_wav(&path, 1, 44100, &vec![0.25; 100_000]);
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let stream = processor.add_node_from_stream(&path, None).unwrap();
+        let other = add_mono_node(&mut processor, vec![0.5; 8]);
+        let hub = add_mono_node(&mut processor, vec![0.125; 8]);
+        for (parent, child) in [
+            (stream, None),
+            (other, None),
+            (hub, Some(stream)),
+            (hub, Some(other)),
+        ] {
+            processor
+                .connect(parent, child, AudioGraphEdge::new(AddOperation, "AddOp"))
+                .unwrap();
+        }
+        let before = root_prefix(&processor, 8);
+        // Longer than the read-ahead window, so rebuilding the streamed node
+        // decodes it again and fails from here on
+        truncate_file(&path, 4000);
+
+        // Its edge into `other` goes first and is put back when the one into
+        // the streamed node fails
+        assert!(processor.remove_node(hub).is_err());
+        assert_eq!(edge_count(&processor), 4);
+        assert_eq!(root_prefix(&processor, 8), before);
+
+        let mul = AudioGraphEdge::new(MultiplyOperation, "MulOp");
+        assert!(processor.connect(other, Some(stream), mul).is_err());
+        assert_eq!(edge_count(&processor), 4);
+        assert_eq!(root_prefix(&processor, 8), before);
+
+        // Neither failure was recorded, the latest step is still the last connect
+        processor.undo().unwrap();
+        assert_eq!(edge_count(&processor), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_undo_stays_on_the_stack() {
+        let path = temp_path("stream_failed_undo.wav");
+        write_float_wav(&path, 1, 44100, &vec![0.25; 100_000]);
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let stream = processor.add_node_from_stream(&path, None).unwrap();
+        let other = add_mono_node(&mut processor, vec![0.5; 8]);
+        processor
+            .connect(stream, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor
+            .connect(
+                other,
+                Some(stream),
+                AudioGraphEdge::new(AddOperation, "AddOp"),
+            )
+            .unwrap();
+        truncate_file(&path, 4000);
+
+        assert!(processor.undo().is_err());
+        assert_eq!(edge_count(&processor), 2);
+        assert_eq!(root_prefix(&processor, 2), vec![[0.75]; 2]);
+        assert!(!processor.can_redo());
+
+        // Once the file is whole again the same step undoes and redoes
+        write_float_wav(&path, 1, 44100, &vec![0.25; 100_000]);
+        assert!(processor.undo().unwrap());
+        assert_eq!(edge_count(&processor), 1);
+        assert_eq!(root_prefix(&processor, 2), vec![[0.25]; 2]);
+
+        assert!(processor.redo().unwrap());
+        assert_eq!(root_prefix(&processor, 2), vec![[0.75]; 2]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_streamed_node_survives_a_session() {
+        let path = temp_path("stream_session.wav");
+        write_float_wav(&path, 1, 44100, &[0.25; 64]);
+        let session_path = temp_path("stream_session.json");
+
+        let mut processor = AudioProcessor::<Mono<f32>>::new();
+        let stream = processor.add_node_from_stream(&path, None).unwrap();
+        processor
+            .connect(stream, None, AudioGraphEdge::new(AddOperation, "AddOp"))
+            .unwrap();
+        processor.save_session(&session_path).unwrap();
+        assert_eq!(
+            Session::read(&session_path).unwrap().sources[0],
+            SessionSource::File {
+                path: path.clone(),
+                streamed: true,
+            }
+        );
+
+        let loaded = AudioProcessor::<Mono<f32>>::load_session(&session_path).unwrap();
+        assert_frames_close(&root_prefix(&loaded, 64), &[[0.25]; 64]);
+        assert!(!is_stream_loaded(&loaded, stream));
+
+        let mut other_rate = AudioProcessor::<Mono<f32>>::with_sample_rate(48000);
+        assert!(matches!(
+            other_rate.add_node_from_stream(&path, None),
+            Err(AudioError::SampleRateMismatch {
+                expected: 48000,
+                found: 44100
+            })
+        ));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&session_path).unwrap();
+    }
 }
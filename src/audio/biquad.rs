@@ -0,0 +1,389 @@
+use super::audio_clip::{AudioClip, AudioClipTrait};
+use super::audio_effects::{AudioEffect, EffectFn};
+use super::session::SessionEffect;
+use dasp::Frame;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Q giving the flattest passband, -3 dB at the corner frequency
+pub const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+// Filter shapes from Robert Bristow-Johnson's Audio EQ Cookbook. Gains are in
+// dB, positive to boost
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum BiquadKind {
+    LowPass,
+    HighPass,
+    // Peaks at 0 dB on the center frequency, Q sets the bandwidth
+    BandPass,
+    Notch,
+    LowShelf { gain_db: f32 },
+    HighShelf { gain_db: f32 },
+    Peak { gain_db: f32 },
+}
+
+// Normalized so that a0 is 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Coefficients {
+    // Gain at `frequency` in dB, read off the transfer function
+    pub fn magnitude_db(&self, frequency: f64, sample_rate: u32) -> f64 {
+        let w = 2.0 * PI * frequency / sample_rate as f64;
+        // |b0 + b1 z^-1 + b2 z^-2| with z = e^jw
+        let magnitude = |c0: f64, c1: f64, c2: f64| {
+            let re = c0 + c1 * w.cos() + c2 * (2.0 * w).cos();
+            let im = -c1 * w.sin() - c2 * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        20.0 * (magnitude(self.b0, self.b1, self.b2) / magnitude(1.0, self.a1, self.a2)).log10()
+    }
+}
+
+// Input and output history of one channel
+#[derive(Clone, Copy, Debug, Default)]
+struct ChannelState {
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+// Second order IIR filter run over every channel of the clip. Coefficients
+// follow the clip's sample rate, so the same filter sounds the same on any
+// clip. State starts at rest on each pass since effects see the whole clip
+#[derive(Clone)]
+pub struct Biquad {
+    pub kind: BiquadKind,
+    pub frequency: f32,
+    pub q: f32,
+    state: Vec<ChannelState>,
+}
+
+impl Biquad {
+    pub fn new(kind: BiquadKind, frequency: f32, q: f32) -> Self {
+        Self {
+            kind,
+            frequency,
+            q,
+            state: Vec::new(),
+        }
+    }
+
+    pub fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        let rate = sample_rate as f64;
+        // Past Nyquist the formulas fold back, so stop just short of it
+        let frequency = (self.frequency as f64).clamp(1e-3, rate * 0.4999);
+        let w0 = 2.0 * PI * frequency / rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q.max(1e-3) as f64);
+        let amp = |gain_db: f32| 10f64.powf(gain_db as f64 / 40.0);
+
+        let [b0, b1, b2, a0, a1, a2] = match self.kind {
+            BiquadKind::LowPass => [
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ],
+            BiquadKind::HighPass => [
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ],
+            BiquadKind::BandPass => [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::Notch => [1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::LowShelf { gain_db } => {
+                let a = amp(gain_db);
+                let k = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                ]
+            }
+            BiquadKind::HighShelf { gain_db } => {
+                let a = amp(gain_db);
+                let k = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                ]
+            }
+            BiquadKind::Peak { gain_db } => {
+                let a = amp(gain_db);
+                [
+                    1.0 + alpha * a,
+                    -2.0 * cos,
+                    1.0 - alpha * a,
+                    1.0 + alpha / a,
+                    -2.0 * cos,
+                    1.0 - alpha / a,
+                ]
+            }
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+impl<F: Frame<Sample = f32> + Copy> AudioEffect<F> for Biquad {
+    fn clone_box(&self) -> EffectFn<F> {
+        Box::new(self.clone())
+    }
+
+    fn to_session(&self) -> Option<SessionEffect> {
+        Some(SessionEffect::Biquad {
+            kind: self.kind,
+            frequency: self.frequency,
+            q: self.q,
+        })
+    }
+
+    fn apply(&mut self, clip: &mut AudioClip<F>) {
+        let c = self.coefficients(clip.get_sample_rate());
+        self.state.clear();
+        self.state.resize(F::CHANNELS, ChannelState::default());
+
+        let state = &mut self.state;
+        for frame in clip.get_frames_mut() {
+            let input = *frame;
+            *frame = F::from_fn(|channel| {
+                let s = &mut state[channel];
+                let x = *input.channel(channel).unwrap() as f64;
+                let y = c.b0 * x + c.b1 * s.x1 + c.b2 * s.x2 - c.a1 * s.y1 - c.a2 * s.y2;
+                *s = ChannelState {
+                    x1: x,
+                    x2: s.x1,
+                    y1: y,
+                    y2: s.y1,
+                };
+                y as f32
+            });
+        }
+    }
+}
+
+pub fn low_pass<F: Frame<Sample = f32> + Copy>(frequency: f32, q: f32) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::LowPass, frequency, q))
+}
+
+pub fn high_pass<F: Frame<Sample = f32> + Copy>(frequency: f32, q: f32) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::HighPass, frequency, q))
+}
+
+pub fn band_pass<F: Frame<Sample = f32> + Copy>(frequency: f32, q: f32) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::BandPass, frequency, q))
+}
+
+pub fn notch<F: Frame<Sample = f32> + Copy>(frequency: f32, q: f32) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::Notch, frequency, q))
+}
+
+pub fn low_shelf<F: Frame<Sample = f32> + Copy>(
+    frequency: f32,
+    q: f32,
+    gain_db: f32,
+) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::LowShelf { gain_db }, frequency, q))
+}
+
+pub fn high_shelf<F: Frame<Sample = f32> + Copy>(
+    frequency: f32,
+    q: f32,
+    gain_db: f32,
+) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::HighShelf { gain_db }, frequency, q))
+}
+
+pub fn peak<F: Frame<Sample = f32> + Copy>(frequency: f32, q: f32, gain_db: f32) -> EffectFn<F> {
+    Box::new(Biquad::new(BiquadKind::Peak { gain_db }, frequency, q))
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dasp::frame::{Mono, Stereo};
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    const FFT_SIZE: usize = 16384;
+
+    // Magnitude response in dB per FFT bin, measured from the impulse response
+    fn measured_response(biquad: &mut Biquad, sample_rate: u32) -> Vec<f64> {
+        let mut clip = AudioClip::<Mono<f32>>::with_capacity_and_rate(FFT_SIZE, sample_rate);
+        clip.get_frames_mut()[0] = [1.0];
+        biquad.apply(&mut clip);
+
+        let mut spectrum: Vec<Complex<f32>> = clip
+            .get_frames_ref()
+            .iter()
+            .map(|frame| Complex::new(frame[0], 0.0))
+            .collect();
+        FftPlanner::new()
+            .plan_fft_forward(FFT_SIZE)
+            .process(&mut spectrum);
+        spectrum[..FFT_SIZE / 2]
+            .iter()
+            .map(|bin| 20.0 * (bin.norm() as f64).log10())
+            .collect()
+    }
+
+    fn bin_of(frequency: f64, sample_rate: u32) -> usize {
+        (frequency / sample_rate as f64 * FFT_SIZE as f64).round() as usize
+    }
+
+    fn bin_frequency(bin: usize, sample_rate: u32) -> f64 {
+        bin as f64 * sample_rate as f64 / FFT_SIZE as f64
+    }
+
+    // Measured gain at the bin nearest `frequency`
+    fn gain_at(response: &[f64], frequency: f64, sample_rate: u32) -> f64 {
+        response[bin_of(frequency, sample_rate)]
+    }
+
+    fn assert_db(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {} dB, got {} dB",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_every_kind_matches_its_transfer_function() {
+        let kinds = [
+            BiquadKind::LowPass,
+            BiquadKind::HighPass,
+            BiquadKind::BandPass,
+            BiquadKind::Notch,
+            BiquadKind::LowShelf { gain_db: 6.0 },
+            BiquadKind::HighShelf { gain_db: -9.0 },
+            BiquadKind::Peak { gain_db: 12.0 },
+        ];
+        for kind in kinds {
+            let mut biquad = Biquad::new(kind, 2000.0, 2.0);
+            let response = measured_response(&mut biquad, 48000);
+            let coefficients = biquad.coefficients(48000);
+            for bin in (1..FFT_SIZE / 2).step_by(37) {
+                let expected = coefficients.magnitude_db(bin_frequency(bin, 48000), 48000);
+                // Deep in a notch both are just very negative
+                if expected > -60.0 {
+                    assert_db(response[bin], expected, 0.05);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pass_filters() {
+        let mut biquad = Biquad::new(BiquadKind::LowPass, 1000.0, BUTTERWORTH_Q);
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 100.0, 48000), 0.0, 0.1);
+        assert_db(gain_at(&response, 1000.0, 48000), -3.0, 0.1);
+        // Two poles fall off at 12 dB per octave, a little faster toward Nyquist
+        assert!(gain_at(&response, 8000.0, 48000) < -36.0);
+
+        let mut biquad = Biquad::new(BiquadKind::HighPass, 1000.0, BUTTERWORTH_Q);
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 10000.0, 48000), 0.0, 0.1);
+        assert_db(gain_at(&response, 1000.0, 48000), -3.0, 0.1);
+        assert!(gain_at(&response, 125.0, 48000) < -35.0);
+
+        let mut biquad = Biquad::new(BiquadKind::BandPass, 1000.0, 4.0);
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 1000.0, 48000), 0.0, 0.1);
+        assert!(gain_at(&response, 100.0, 48000) < -25.0);
+        assert!(gain_at(&response, 10000.0, 48000) < -25.0);
+
+        let mut biquad = Biquad::new(BiquadKind::Notch, 1000.0, 4.0);
+        let response = measured_response(&mut biquad, 48000);
+        assert!(gain_at(&response, 1000.0, 48000) < -30.0);
+        assert_db(gain_at(&response, 100.0, 48000), 0.0, 0.1);
+        assert_db(gain_at(&response, 10000.0, 48000), 0.0, 0.1);
+    }
+
+    #[test]
+    fn test_shelves_and_peak() {
+        let mut biquad = Biquad::new(BiquadKind::LowShelf { gain_db: 6.0 }, 500.0, BUTTERWORTH_Q);
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 30.0, 48000), 6.0, 0.2);
+        assert_db(gain_at(&response, 500.0, 48000), 3.0, 0.2);
+        assert_db(gain_at(&response, 15000.0, 48000), 0.0, 0.1);
+
+        let mut biquad = Biquad::new(
+            BiquadKind::HighShelf { gain_db: -6.0 },
+            5000.0,
+            BUTTERWORTH_Q,
+        );
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 100.0, 48000), 0.0, 0.1);
+        assert_db(gain_at(&response, 20000.0, 48000), -6.0, 0.3);
+
+        let mut biquad = Biquad::new(BiquadKind::Peak { gain_db: 9.0 }, 3000.0, 1.0);
+        let response = measured_response(&mut biquad, 48000);
+        assert_db(gain_at(&response, 3000.0, 48000), 9.0, 0.1);
+        assert_db(gain_at(&response, 50.0, 48000), 0.0, 0.1);
+    }
+
+    #[test]
+    fn test_coefficients_follow_the_sample_rate() {
+        for sample_rate in [22050, 44100, 96000] {
+            let mut biquad = Biquad::new(BiquadKind::LowPass, 2000.0, BUTTERWORTH_Q);
+            let response = measured_response(&mut biquad, sample_rate);
+            assert_db(gain_at(&response, 2000.0, sample_rate), -3.0, 0.15);
+        }
+    }
+
+    #[test]
+    fn test_channels_are_filtered_separately() {
+        let mut samples = vec![0.0; 2 * 64];
+        samples[0] = 1.0;
+        samples[2 * 10 + 1] = 1.0;
+        let mut clip = AudioClip::<Stereo<f32>>::new(samples, 44100).unwrap();
+        let mut effect = low_pass::<Stereo<f32>>(3000.0, BUTTERWORTH_Q);
+        effect.apply(&mut clip);
+
+        // The right channel is the left's response, ten frames late
+        let frames = clip.get_frames_ref();
+        assert!(frames[..10].iter().all(|frame| frame[1] == 0.0));
+        for i in 10..64 {
+            assert_eq!(frames[i][1], frames[i - 10][0]);
+        }
+
+        // Each pass starts from rest, so re-running the chain is repeatable
+        let mut again = AudioClip::<Stereo<f32>>::new(vec![0.0; 2 * 64], 44100).unwrap();
+        again.get_frames_mut()[0] = [1.0, 0.0];
+        effect.apply(&mut again);
+        for (repeated, first) in again.get_frames_ref().iter().zip(frames) {
+            assert_eq!(repeated[0], first[0]);
+        }
+    }
+}
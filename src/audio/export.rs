@@ -0,0 +1,167 @@
+use super::audio_error::AudioResult;
+use dasp::Frame;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Int16 { dither: bool },
+    Int24 { dither: bool },
+    Float32,
+}
+
+impl BitDepth {
+    pub fn bits_per_sample(&self) -> u16 {
+        match self {
+            BitDepth::Int16 { .. } => 16,
+            BitDepth::Int24 { .. } => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
+// Triangular (TPDF) dither of +-1 LSB, the sum of two uniform values.
+// Uses a fixed-seed xorshift so bounces are reproducible for golden files
+struct TpdfDither {
+    state: u32,
+}
+
+impl TpdfDither {
+    fn new() -> Self {
+        Self { state: 0x9e37_79b9 }
+    }
+
+    fn next_uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn next_lsb(&mut self) -> f32 {
+        self.next_uniform() - self.next_uniform()
+    }
+}
+
+pub fn write_wav<F, P>(
+    path: P,
+    frames: &[F],
+    sample_rate: u32,
+    bit_depth: BitDepth,
+) -> AudioResult<()>
+where
+    F: Frame<Sample = f32>,
+    P: AsRef<Path>,
+{
+    let spec = hound::WavSpec {
+        channels: F::CHANNELS as u16,
+        sample_rate,
+        bits_per_sample: bit_depth.bits_per_sample(),
+        sample_format: match bit_depth {
+            BitDepth::Float32 => hound::SampleFormat::Float,
+            _ => hound::SampleFormat::Int,
+        },
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+
+    match bit_depth {
+        BitDepth::Float32 => {
+            for frame in frames {
+                for sample in frame.channels() {
+                    writer.write_sample(sample)?;
+                }
+            }
+        }
+        BitDepth::Int16 { dither } | BitDepth::Int24 { dither } => {
+            let mut tpdf = dither.then(TpdfDither::new);
+            let bits = bit_depth.bits_per_sample() as i32;
+            for frame in frames {
+                for sample in frame.channels() {
+                    let noise = tpdf.as_mut().map_or(0.0, |tpdf| tpdf.next_lsb());
+                    writer.write_sample(quantize(sample, bits, noise))?;
+                }
+            }
+        }
+    }
+
+    writer.finalize()?;
+    Ok(())
+}
+
+// Scales to the full integer range, adds `noise` (in LSBs) and clips
+fn quantize(sample: f32, bits: i32, noise: f32) -> i32 {
+    let max = (1i64 << (bits - 1)) as f64;
+    let value = (sample as f64 * max + noise as f64).round();
+    value.clamp(-max, max - 1.0) as i32
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::test_util::temp_path;
+    use dasp::frame::{Mono, Stereo};
+
+    fn read_int_samples(path: &Path) -> (hound::WavSpec, Vec<i32>) {
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let samples = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        (reader.spec(), samples)
+    }
+
+    #[test]
+    fn test_quantize_clips_and_rounds() {
+        assert_eq!(quantize(0.5, 16, 0.0), 16384);
+        assert_eq!(quantize(-1.0, 16, 0.0), -32768);
+        assert_eq!(quantize(1.0, 16, 0.0), 32767);
+        assert_eq!(quantize(2.0, 24, 0.0), 8388607);
+        assert_eq!(quantize(0.0, 16, 0.4), 0);
+    }
+
+    #[test]
+    fn test_write_float32_is_lossless() {
+        let path = temp_path("export_float.wav");
+        let frames: Vec<Stereo<f32>> = vec![[0.1, -0.2], [0.3, -0.4], [1.5, -1.5]];
+        write_wav(&path, &frames, 48000, BitDepth::Float32).unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(samples, vec![0.1, -0.2, 0.3, -0.4, 1.5, -1.5]);
+    }
+
+    #[test]
+    fn test_write_int24_without_dither() {
+        let path = temp_path("export_int24.wav");
+        let frames: Vec<Mono<f32>> = vec![[0.0], [0.5], [-0.25]];
+        write_wav(&path, &frames, 44100, BitDepth::Int24 { dither: false }).unwrap();
+
+        let (spec, samples) = read_int_samples(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(spec.bits_per_sample, 24);
+        assert_eq!(samples, vec![0, 4194304, -2097152]);
+    }
+
+    #[test]
+    fn test_tpdf_dither_stays_within_one_lsb() {
+        let path = temp_path("export_dither.wav");
+        let value = 0.3f32;
+        let frames: Vec<Mono<f32>> = vec![[value]; 10000];
+        write_wav(&path, &frames, 44100, BitDepth::Int16 { dither: true }).unwrap();
+
+        let (_, samples) = read_int_samples(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let exact = value as f64 * 32768.0;
+        assert!(samples.iter().all(|&s| (s as f64 - exact).abs() <= 1.5));
+        // Dither decorrelates the error, so it varies but averages out to the signal
+        assert!(samples.iter().any(|&s| s != samples[0]));
+        let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64;
+        assert!((mean - exact).abs() < 0.05, "mean {} vs {}", mean, exact);
+    }
+}
@@ -0,0 +1,289 @@
+use super::audio_edge::{AudioGraphEdge, MixParams};
+use super::audio_effects::AudioEffectChain;
+use super::audio_node::AudioNode;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use std::sync::{Arc, Mutex};
+
+// One reversible graph edit, holding whatever it needs to be performed again.
+// Nodes are kept by handle and edges as a template, so an undone removal can
+// put back exactly what was taken out
+pub(crate) enum EditCommand<F> {
+    AddNode {
+        node_idx: NodeIndex,
+        node: Arc<Mutex<AudioNode<F>>>,
+    },
+    RemoveNode {
+        node_idx: NodeIndex,
+        node: Arc<Mutex<AudioNode<F>>>,
+    },
+    Connect {
+        edge_idx: EdgeIndex,
+        parent: NodeIndex,
+        child: NodeIndex,
+        edge: AudioGraphEdge<F>,
+    },
+    Disconnect {
+        edge_idx: EdgeIndex,
+        parent: NodeIndex,
+        child: NodeIndex,
+        edge: AudioGraphEdge<F>,
+    },
+    SetEdgeOffset {
+        edge_idx: EdgeIndex,
+        old: i64,
+        new: i64,
+    },
+    SetMixParams {
+        edge_idx: EdgeIndex,
+        old: MixParams,
+        new: MixParams,
+    },
+    SetRegion {
+        node_idx: NodeIndex,
+        old: (usize, usize),
+        new: (usize, usize),
+    },
+    Slip {
+        node_idx: NodeIndex,
+        frames: i64,
+    },
+    MoveNode {
+        node_idx: NodeIndex,
+        old: usize,
+        new: usize,
+    },
+    // Holds the chain the node does not have right now. Performing the edit
+    // swaps the two, so it is its own inverse
+    SetEffectChain {
+        node_idx: NodeIndex,
+        chain: Option<AudioEffectChain<F>>,
+    },
+    // Several edits undone and redone as one step
+    Group(Vec<EditCommand<F>>),
+}
+
+// A node or edge that came back under a different index when re-created
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Remap {
+    Node(NodeIndex, NodeIndex),
+    Edge(EdgeIndex, EdgeIndex),
+}
+
+impl<F> EditCommand<F> {
+    pub(crate) fn inverse(self) -> Self {
+        match self {
+            EditCommand::AddNode { node_idx, node } => EditCommand::RemoveNode { node_idx, node },
+            EditCommand::RemoveNode { node_idx, node } => EditCommand::AddNode { node_idx, node },
+            EditCommand::Connect {
+                edge_idx,
+                parent,
+                child,
+                edge,
+            } => EditCommand::Disconnect {
+                edge_idx,
+                parent,
+                child,
+                edge,
+            },
+            EditCommand::Disconnect {
+                edge_idx,
+                parent,
+                child,
+                edge,
+            } => EditCommand::Connect {
+                edge_idx,
+                parent,
+                child,
+                edge,
+            },
+            EditCommand::SetEdgeOffset { edge_idx, old, new } => EditCommand::SetEdgeOffset {
+                edge_idx,
+                old: new,
+                new: old,
+            },
+            EditCommand::SetMixParams { edge_idx, old, new } => EditCommand::SetMixParams {
+                edge_idx,
+                old: new,
+                new: old,
+            },
+            EditCommand::SetRegion { node_idx, old, new } => EditCommand::SetRegion {
+                node_idx,
+                old: new,
+                new: old,
+            },
+            EditCommand::Slip { node_idx, frames } => EditCommand::Slip {
+                node_idx,
+                frames: -frames,
+            },
+            EditCommand::MoveNode { node_idx, old, new } => EditCommand::MoveNode {
+                node_idx,
+                old: new,
+                new: old,
+            },
+            command @ EditCommand::SetEffectChain { .. } => command,
+            EditCommand::Group(commands) => {
+                EditCommand::Group(commands.into_iter().rev().map(Self::inverse).collect())
+            }
+        }
+    }
+
+    pub(crate) fn remap(&mut self, remap: Remap) {
+        let remap_node = |idx: &mut NodeIndex| {
+            if let Remap::Node(old, new) = remap {
+                if *idx == old {
+                    *idx = new;
+                }
+            }
+        };
+        let remap_edge = |idx: &mut EdgeIndex| {
+            if let Remap::Edge(old, new) = remap {
+                if *idx == old {
+                    *idx = new;
+                }
+            }
+        };
+        match self {
+            EditCommand::AddNode { node_idx, .. }
+            | EditCommand::RemoveNode { node_idx, .. }
+            | EditCommand::SetRegion { node_idx, .. }
+            | EditCommand::Slip { node_idx, .. }
+            | EditCommand::MoveNode { node_idx, .. }
+            | EditCommand::SetEffectChain { node_idx, .. } => remap_node(node_idx),
+            EditCommand::Connect {
+                edge_idx,
+                parent,
+                child,
+                ..
+            }
+            | EditCommand::Disconnect {
+                edge_idx,
+                parent,
+                child,
+                ..
+            } => {
+                remap_edge(edge_idx);
+                remap_node(parent);
+                remap_node(child);
+            }
+            EditCommand::SetEdgeOffset { edge_idx, .. }
+            | EditCommand::SetMixParams { edge_idx, .. } => remap_edge(edge_idx),
+            EditCommand::Group(commands) => {
+                for command in commands {
+                    command.remap(remap);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct History<F> {
+    undo: Vec<EditCommand<F>>,
+    redo: Vec<EditCommand<F>>,
+}
+
+impl<F> Default for History<F> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl<F> History<F> {
+    // A new edit makes the undone ones unreachable
+    pub(crate) fn record(&mut self, command: EditCommand<F>) {
+        self.undo.push(command);
+        self.redo.clear();
+    }
+
+    // Records everything `inner` collected as a single step
+    pub(crate) fn record_group(&mut self, inner: History<F>) {
+        if !inner.undo.is_empty() {
+            self.record(EditCommand::Group(inner.undo));
+        }
+    }
+
+    // What `inner` collected as a single step, for putting back an edit that
+    // failed partway
+    pub(crate) fn into_group(self) -> EditCommand<F> {
+        EditCommand::Group(self.undo)
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<EditCommand<F>> {
+        self.undo.pop()
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<EditCommand<F>> {
+        self.redo.pop()
+    }
+
+    pub(crate) fn push_undo(&mut self, command: EditCommand<F>) {
+        self.undo.push(command);
+    }
+
+    pub(crate) fn push_redo(&mut self, command: EditCommand<F>) {
+        self.redo.push(command);
+    }
+
+    pub(crate) fn remap(&mut self, remap: Remap) {
+        for command in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            command.remap(remap);
+        }
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dasp::frame::Mono;
+
+    fn slip(node: usize, frames: i64) -> EditCommand<Mono<f32>> {
+        EditCommand::Slip {
+            node_idx: NodeIndex::new(node),
+            frames,
+        }
+    }
+
+    #[test]
+    fn test_group_inverse_runs_backwards() {
+        let group = EditCommand::Group(vec![slip(1, 2), slip(2, 5)]).inverse();
+        let EditCommand::Group(commands) = group else {
+            panic!("inverse of a group is a group");
+        };
+        assert!(matches!(commands[0], EditCommand::Slip { frames: -5, .. }));
+        assert!(matches!(commands[1], EditCommand::Slip { frames: -2, .. }));
+    }
+
+    #[test]
+    fn test_record_clears_redo_and_remap_reaches_both_stacks() {
+        let mut history = History::default();
+        history.record(slip(1, 1));
+        history.push_redo(slip(1, 2));
+        history.remap(Remap::Node(NodeIndex::new(1), NodeIndex::new(7)));
+        assert!(matches!(
+            history.pop_redo(),
+            Some(EditCommand::Slip { node_idx, .. }) if node_idx.index() == 7
+        ));
+
+        history.push_redo(slip(1, 2));
+        history.record(slip(3, 1));
+        assert!(!history.can_redo());
+        assert!(history.can_undo());
+    }
+}
@@ -1,4 +1,5 @@
-use cpal::traits::{DeviceTrait, HostTrait};
+use super::audio_error::{AudioError, AudioResult};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 use std::{
     fmt::Debug,
@@ -16,42 +17,46 @@ pub struct AudioIO {
 }
 
 impl AudioIO {
-    pub fn new() -> Self {
+    pub fn new() -> AudioResult<Self> {
         let _host = cpal::default_host();
 
         // Output
-        let output_device = _host.default_output_device().unwrap();
+        let output_device = _host
+            .default_output_device()
+            .ok_or_else(|| AudioError::DeviceUnavailable("no default output device".into()))?;
         let mut supported_configs_range = output_device
             .supported_output_configs()
-            .expect("error while querying configs");
+            .map_err(|err| AudioError::DeviceUnavailable(err.to_string()))?;
 
         let supported_output_config = supported_configs_range
             .next()
-            .expect("no supported config?!")
+            .ok_or_else(|| AudioError::DeviceUnavailable("no supported output config".into()))?
             .with_max_sample_rate();
 
         // Input
-        let input_device = _host.default_input_device().unwrap();
+        let input_device = _host
+            .default_input_device()
+            .ok_or_else(|| AudioError::DeviceUnavailable("no default input device".into()))?;
 
         let mut supported_configs_range = input_device
             .supported_input_configs()
-            .expect("error while querying configs");
+            .map_err(|err| AudioError::DeviceUnavailable(err.to_string()))?;
 
         let supported_input_config = supported_configs_range
             .next()
-            .expect("no supported config")
+            .ok_or_else(|| AudioError::DeviceUnavailable("no supported input config".into()))?
             .with_max_sample_rate();
 
-        Self {
+        Ok(Self {
             _host,
             output_device,
             supported_output_config,
             input_device,
             supported_input_config,
-        }
+        })
     }
 
-    pub fn record(&self) -> Option<(Vec<f32>, u32, u32)> {
+    pub fn record(&self) -> AudioResult<(Vec<f32>, u32, u32)> {
         let clip = Vec::new();
         let clip = Arc::new(Mutex::new(Some(clip)));
         let clip_2 = Arc::clone(&clip);
@@ -87,13 +92,22 @@ impl AudioIO {
                 err_fn,
                 None,
             ),
-            _ => unimplemented!(),
-        };
+            sample_format => {
+                return Err(AudioError::DeviceUnavailable(format!(
+                    "unsupported input sample format {}",
+                    sample_format
+                )))
+            }
+        }
+        .map_err(|err| AudioError::DeviceUnavailable(err.to_string()))?;
+        stream
+            .play()
+            .map_err(|err| AudioError::DeviceUnavailable(err.to_string()))?;
 
         std::thread::sleep(std::time::Duration::from_secs(5));
         drop(stream);
-        let clip = clip.lock().unwrap().take().unwrap();
+        let clip = clip.lock()?.take().unwrap_or_default();
         eprintln!("recorded clip: {:?}", clip.len());
-        Some((clip, sample_rate, channels as u32))
+        Ok((clip, sample_rate, channels as u32))
     }
 }
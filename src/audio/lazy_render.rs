@@ -0,0 +1,347 @@
+use super::audio_clip::{AudioClip, AudioClipTrait};
+use super::audio_error::{AudioError, AudioResult};
+use super::audio_graph::AudioGraph;
+use super::audio_node::AudioNode;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{DfsPostOrder, EdgeRef, Reversed, Topo};
+use petgraph::Direction;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    // Every node keeps its full mix and edits push deltas down to the root, so
+    // reading the output is a copy. Costs three full-length clips per node
+    #[default]
+    Cached,
+    // Nodes keep only their placement and the output is computed block by block
+    // from the sources when read. Memory stays flat, every read walks the graph.
+    // Effect chains are limited to gains and fades, filters need the whole clip
+    Lazy,
+}
+
+// Frames rendered at a time when a whole clip is needed, e.g. for export
+pub(crate) const BLOCK_LEN: usize = 4096;
+
+// Pull-based evaluation of a graph. A block of a node is its own material plus
+// every input run through its edge. Every node the block reaches is rendered
+// once, parents first, over all the frames its children read from it, however
+// many paths lead there. Those outputs last until the next block, the extents
+// for the whole render
+pub(crate) struct LazyRender<'a, F> {
+    audio_graph: &'a AudioGraph<F>,
+    extents: HashMap<NodeIndex, (usize, usize)>,
+    // Timeline frame each output starts at and its frames
+    outputs: HashMap<NodeIndex, (usize, Vec<F>)>,
+}
+
+impl<'a, F> LazyRender<'a, F>
+where
+    F: dasp::Frame<Sample = f32> + Default + Copy,
+{
+    pub(crate) fn new(audio_graph: &'a AudioGraph<F>) -> Self {
+        Self {
+            audio_graph,
+            extents: HashMap::new(),
+            outputs: HashMap::new(),
+        }
+    }
+
+    // Timeline frames the node's output covers: its own clip and every input
+    // where its edge places it, as the cached mode would have grown the clip
+    pub(crate) fn extent(&mut self, node_idx: NodeIndex) -> AudioResult<(usize, usize)> {
+        if let Some(extent) = self.extents.get(&node_idx) {
+            return Ok(*extent);
+        }
+        let (mut start, mut end) = {
+            let node = self.audio_graph.try_get_node(node_idx)?.lock()?;
+            (
+                node.get_clip_start(),
+                node.get_clip_start() + node.get_clip_len(),
+            )
+        };
+        for (parent_idx, offset) in self.inputs(node_idx) {
+            let (placed_start, placed_end) = self.placement(parent_idx, offset)?;
+            if placed_start < placed_end {
+                start = start.min(placed_start);
+                end = end.max(placed_end);
+            }
+        }
+        self.extents.insert(node_idx, (start, end));
+        Ok((start, end))
+    }
+
+    // Timeline frames the parent's output covers where an edge with `offset`
+    // places it
+    pub(crate) fn placement(
+        &mut self,
+        parent_idx: NodeIndex,
+        offset: i64,
+    ) -> AudioResult<(usize, usize)> {
+        let (parent_start, parent_end) = self.extent(parent_idx)?;
+        Ok((
+            (parent_start as i64 + offset).max(0) as usize,
+            (parent_end as i64 + offset).max(0) as usize,
+        ))
+    }
+
+    // Writes the node's output for timeline frames `start..start + out.len()`
+    pub(crate) fn render(
+        &mut self,
+        node_idx: NodeIndex,
+        start: usize,
+        out: &mut [F],
+    ) -> AudioResult<()> {
+        self.outputs.clear();
+        for (upstream_idx, (from, to)) in self.plan(node_idx, start, start + out.len())? {
+            let mut frames = vec![F::EQUILIBRIUM; to - from];
+            self.render_node(upstream_idx, from, &mut frames)?;
+            self.outputs.insert(upstream_idx, (from, frames));
+        }
+        let rendered = self.render_node(node_idx, start, out);
+        self.outputs.clear();
+        rendered
+    }
+
+    // Every node above `node_idx` with the timeline frames its children read
+    // from it for frames `start..end`, parents before their children
+    fn plan(
+        &mut self,
+        node_idx: NodeIndex,
+        start: usize,
+        end: usize,
+    ) -> AudioResult<Vec<(NodeIndex, (usize, usize))>> {
+        // Post-order against the edges finishes each node after its parents,
+        // and `node_idx` last
+        let reversed = Reversed(&self.audio_graph.graph);
+        let mut order = Vec::new();
+        let mut dfs = DfsPostOrder::new(reversed, node_idx);
+        while let Some(idx) = dfs.next(reversed) {
+            order.push(idx);
+        }
+
+        let mut needed = HashMap::from([(node_idx, (start, end))]);
+        for &idx in order.iter().rev() {
+            let Some(&(from, to)) = needed.get(&idx) else {
+                continue;
+            };
+            for (_, edge_idx, parent_idx) in self.incoming(idx) {
+                let offset = self.audio_graph.try_get_edge_ref(edge_idx)?.get_offset();
+                let Some((window_from, window_to)) = self.window(edge_idx, parent_idx, from, to)?
+                else {
+                    continue;
+                };
+                let window = (
+                    (window_from as i64 - offset) as usize,
+                    (window_to as i64 - offset) as usize,
+                );
+                needed
+                    .entry(parent_idx)
+                    .and_modify(|(from, to)| {
+                        *from = (*from).min(window.0);
+                        *to = (*to).max(window.1);
+                    })
+                    .or_insert(window);
+            }
+        }
+        order.pop();
+        Ok(order
+            .into_iter()
+            .filter_map(|idx| needed.get(&idx).map(|&range| (idx, range)))
+            .collect())
+    }
+
+    // Child frames around `start..end` where the edge's parent takes part in
+    // the result, context included, `None` if it does not reach them at all
+    fn window(
+        &mut self,
+        edge_idx: EdgeIndex,
+        parent_idx: NodeIndex,
+        start: usize,
+        end: usize,
+    ) -> AudioResult<Option<(usize, usize)>> {
+        let effect = self.audio_graph.try_get_edge_ref(edge_idx)?;
+        let (placed_start, placed_end) = self.placement(parent_idx, effect.get_offset())?;
+        if placed_end <= start || placed_start >= end {
+            return Ok(None);
+        }
+        let context = effect.operation.block_context();
+        Ok(Some((
+            placed_start.max(start.saturating_sub(context)),
+            placed_end.min(end + context),
+        )))
+    }
+
+    // Frames of `node_idx`'s output changed by changes to the given frames of
+    // other nodes' output. A change follows each edge down by its offset,
+    // widened by the frames the operation looks at around each one. A node
+    // with effects changes over its whole extent, as fades depend on where it
+    // starts and ends
+    pub(crate) fn reach(
+        &mut self,
+        node_idx: NodeIndex,
+        changes: &[(NodeIndex, (usize, usize))],
+    ) -> AudioResult<Vec<(usize, usize)>> {
+        let mut changed: HashMap<NodeIndex, Vec<(usize, usize)>> = HashMap::new();
+        for &(idx, range) in changes {
+            changed.entry(idx).or_default().push(range);
+        }
+
+        let graph = &self.audio_graph.graph;
+        let mut topo = Topo::new(graph);
+        while let Some(idx) = topo.next(graph) {
+            let Some(mut ranges) = changed.remove(&idx) else {
+                continue;
+            };
+            let has_effects = self
+                .audio_graph
+                .try_get_node(idx)?
+                .lock()?
+                .get_effect_chain()
+                .is_some();
+            if has_effects {
+                ranges.push(self.extent(idx)?);
+            }
+            let ranges = merge_ranges(ranges);
+            if idx == node_idx {
+                return Ok(ranges);
+            }
+            for edge in graph.edges_directed(idx, Direction::Outgoing) {
+                let offset = edge.weight().get_offset();
+                let context = edge.weight().operation.block_context() as i64;
+                for &(start, end) in &ranges {
+                    let from = (start as i64 + offset - context).max(0) as usize;
+                    let to = (end as i64 + offset + context).max(0) as usize;
+                    if from < to {
+                        changed.entry(edge.target()).or_default().push((from, to));
+                    }
+                }
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn render_node(&mut self, node_idx: NodeIndex, start: usize, out: &mut [F]) -> AudioResult<()> {
+        let audio_node = self.audio_graph.try_get_node(node_idx)?;
+        let blockwise = audio_node
+            .lock()?
+            .get_effect_chain()
+            .map(|effect_chain| effect_chain.is_blockwise());
+        if blockwise == Some(false) {
+            return Err(AudioError::UnrenderableEffect(node_idx));
+        }
+        self.render_dry(node_idx, start, out)?;
+        if blockwise.is_none() {
+            return Ok(());
+        }
+
+        // Effects see the node's output as one clip over its extent, the same
+        // clip the cached mode runs them on
+        let (extent_start, extent_end) = self.extent(node_idx)?;
+        let from = start.max(extent_start);
+        let to = (start + out.len()).min(extent_end);
+        if from < to {
+            if let Some(effect_chain) = audio_node.lock()?.get_effect_chain() {
+                effect_chain.apply_block(
+                    &mut out[from - start..to - start],
+                    from - extent_start,
+                    extent_end - extent_start,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Linear inputs are summed first, then non-linear ones applied in the
+    // order they were connected, the same as a rebuild in the cached mode.
+    // The parents' outputs are already rendered by `plan`
+    fn render_dry(&mut self, node_idx: NodeIndex, start: usize, out: &mut [F]) -> AudioResult<()> {
+        out.fill(F::EQUILIBRIUM);
+        self.audio_graph
+            .try_get_node(node_idx)?
+            .lock()?
+            .read_source(start, out)?;
+
+        let end = start + out.len();
+        for (_, edge_idx, parent_idx) in self.incoming(node_idx) {
+            let Some((from, to)) = self.window(edge_idx, parent_idx, start, end)? else {
+                continue;
+            };
+            let effect = self.audio_graph.try_get_edge_ref(edge_idx)?;
+
+            let mut parent_clip = AudioClip::with_capacity_and_rate(to - from, self.sample_rate());
+            let parent_from = (from as i64 - effect.get_offset()) as usize;
+            let (output_start, output) = &self.outputs[&parent_idx];
+            let output_from = parent_from - output_start;
+            parent_clip
+                .get_frames_mut()
+                .copy_from_slice(&output[output_from..output_from + to - from]);
+            let parent_node = Self::block_node(parent_clip, from);
+
+            // The child outside the block is left silent, operations only
+            // combine it frame by frame so it cannot reach into the block
+            let mut child_clip = AudioClip::with_capacity_and_rate(to - from, self.sample_rate());
+            let (block_from, block_to) = (from.max(start), to.min(end));
+            child_clip.get_frames_mut()[block_from - from..block_to - from]
+                .copy_from_slice(&out[block_from - start..block_to - start]);
+            let mut child_node = Self::block_node(child_clip, from);
+            child_node.set_delta_range(Some((0, to - from)));
+
+            effect.operation.apply(&parent_node, &child_node);
+            child_node.with_buffers(|child_buffers| {
+                out[block_from - start..block_to - start].copy_from_slice(
+                    &child_buffers.clip.get_frames_ref()[block_from - from..block_to - from],
+                )
+            });
+        }
+        Ok(())
+    }
+
+    // Sorted linear inputs first, each with the edge it comes through
+    fn incoming(&self, node_idx: NodeIndex) -> Vec<(bool, EdgeIndex, NodeIndex)> {
+        let mut incoming: Vec<(bool, EdgeIndex, NodeIndex)> = self
+            .audio_graph
+            .graph
+            .edges_directed(node_idx, Direction::Incoming)
+            .map(|edge| {
+                (
+                    !edge.weight().operation.is_linear(),
+                    edge.id(),
+                    edge.source(),
+                )
+            })
+            .collect();
+        incoming.sort();
+        incoming
+    }
+
+    fn inputs(&self, node_idx: NodeIndex) -> Vec<(NodeIndex, i64)> {
+        self.audio_graph
+            .graph
+            .edges_directed(node_idx, Direction::Incoming)
+            .map(|edge| (edge.source(), edge.weight().get_offset()))
+            .collect()
+    }
+
+    fn block_node(clip: AudioClip<F>, start: usize) -> AudioNode<F> {
+        let mut audio_node = AudioNode::new(clip, None);
+        audio_node.set_clip_start(start);
+        audio_node
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.audio_graph.get_sample_rate()
+    }
+}
+
+// Sorted, with overlapping and touching ranges joined
+fn merge_ranges(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
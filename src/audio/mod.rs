@@ -1,9 +1,21 @@
 pub mod audio_clip;
 pub mod audio_edge;
 pub mod audio_effects;
+pub mod audio_error;
 pub mod audio_graph;
 pub mod audio_node;
 pub mod audio_processor;
 pub mod audio_state;
+pub mod biquad;
+pub mod export;
+pub mod history;
 pub mod io;
+pub mod lazy_render;
+pub mod renderer;
+pub mod resample;
+pub mod session;
+pub mod stream_clip;
+#[cfg(test)]
+mod test_util;
+pub mod transport;
 pub mod util;
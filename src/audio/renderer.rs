@@ -0,0 +1,178 @@
+use super::transport::Transport;
+use dasp::Frame;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Every snapshot sent that may still be in use. Both sides hold the list, so
+// the renderer never lets go of the last reference to a snapshot and freeing
+// one is left to the control thread
+type Published<F> = Arc<Mutex<Vec<Arc<Vec<F>>>>>;
+
+pub(crate) fn render_channel<F>(
+    frames: Vec<F>,
+    transport: Arc<Transport>,
+) -> (SnapshotPublisher<F>, AudioRenderer<F>) {
+    let (updates_tx, updates_rx) = mpsc::channel();
+    let snapshot = Arc::new(frames);
+    let published = Arc::new(Mutex::new(vec![Arc::clone(&snapshot)]));
+    let publisher = SnapshotPublisher {
+        updates: updates_tx,
+        published: Arc::clone(&published),
+    };
+    let renderer = AudioRenderer {
+        transport,
+        snapshot,
+        updates: updates_rx,
+        _published: published,
+    };
+    (publisher, renderer)
+}
+
+// Control side: sends a fresh immutable copy of the root mix after every edit
+pub(crate) struct SnapshotPublisher<F> {
+    updates: Sender<Arc<Vec<F>>>,
+    published: Published<F>,
+}
+
+impl<F> SnapshotPublisher<F> {
+    // Frees the snapshots only the list still refers to, which neither the
+    // renderer nor the channel can reach again
+    pub(crate) fn publish(&self, frames: Vec<F>) {
+        let snapshot = Arc::new(frames);
+        {
+            let mut published = self
+                .published
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            published.retain(|old| Arc::strong_count(old) > 1);
+            published.push(Arc::clone(&snapshot));
+        }
+        // A dropped renderer just stops listening
+        let _ = self.updates.send(snapshot);
+    }
+
+    // The snapshot sent last, which a partial update starts from
+    pub(crate) fn latest(&self) -> Arc<Vec<F>> {
+        let published = self
+            .published
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(
+            published
+                .last()
+                .expect("the first snapshot is published on creation"),
+        )
+    }
+
+    #[cfg(test)]
+    pub(crate) fn snapshots_held(&self) -> usize {
+        self.published.lock().unwrap().len()
+    }
+}
+
+// Render side: owns the snapshot it is playing and only picks up a newer one
+// between blocks, so a block is never a mix of two graph states
+pub struct AudioRenderer<F> {
+    transport: Arc<Transport>,
+    snapshot: Arc<Vec<F>>,
+    updates: Receiver<Arc<Vec<F>>>,
+    // Only held, never locked here
+    _published: Published<F>,
+}
+
+impl<F> AudioRenderer<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    pub fn transport(&self) -> Arc<Transport> {
+        Arc::clone(&self.transport)
+    }
+
+    // The old snapshot is still in the published list, so replacing it only
+    // drops a reference
+    fn swap_in_latest(&mut self) {
+        while let Ok(snapshot) = self.updates.try_recv() {
+            self.snapshot = snapshot;
+        }
+    }
+
+    pub fn fill_buffer(&mut self, out: &mut [F]) -> usize {
+        self.swap_in_latest();
+        let written = self
+            .transport
+            .read_block(&self.snapshot, out.len(), |i, frame| out[i] = *frame);
+        out[written..].fill(F::EQUILIBRIUM);
+        written
+    }
+
+    pub fn fill_interleaved(&mut self, out: &mut [f32]) -> usize {
+        self.swap_in_latest();
+        let channels = F::CHANNELS;
+        let written =
+            self.transport
+                .read_block(&self.snapshot, out.len() / channels, |i, frame| {
+                    let samples = &mut out[i * channels..(i + 1) * channels];
+                    for (sample, value) in samples.iter_mut().zip(frame.channels()) {
+                        *sample = value;
+                    }
+                });
+        out[written * channels..].fill(0.0);
+        written
+    }
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dasp::frame::Mono;
+
+    #[test]
+    fn test_renderer_swaps_between_blocks() {
+        let transport = Arc::new(Transport::new(44100));
+        let (publisher, mut renderer) = render_channel(vec![[1.0]; 8], Arc::clone(&transport));
+        transport.play();
+
+        let mut out: [Mono<f32>; 2] = [[0.0]; 2];
+        assert_eq!(renderer.fill_buffer(&mut out), 2);
+        assert_eq!(out, [[1.0]; 2]);
+
+        publisher.publish(vec![[2.0]; 8]);
+        publisher.publish(vec![[3.0]; 8]);
+        renderer.fill_buffer(&mut out);
+        assert_eq!(out, [[3.0]; 2]);
+        assert_eq!(transport.get_position(), 4);
+    }
+
+    #[test]
+    fn test_swapped_snapshots_are_freed_by_the_publisher() {
+        let transport = Arc::new(Transport::new(44100));
+        let (publisher, mut renderer) = render_channel(vec![[1.0]; 8], transport);
+        let first = Arc::downgrade(&renderer.snapshot);
+
+        for value in 2..40 {
+            publisher.publish(vec![[value as f32]; 8]);
+        }
+        let mut out: [Mono<f32>; 2] = [[0.0]; 2];
+        renderer.fill_buffer(&mut out);
+        assert!(first.upgrade().is_some());
+
+        publisher.publish(vec![[0.0]; 8]);
+        assert!(first.upgrade().is_none());
+        // The one being played and the one on its way
+        assert_eq!(publisher.snapshots_held(), 2);
+    }
+
+    #[test]
+    fn test_renderer_survives_dropped_publisher() {
+        let transport = Arc::new(Transport::new(44100));
+        let (publisher, mut renderer) = render_channel(vec![[0.5, -0.5]; 2], transport);
+        drop(publisher);
+        renderer.transport().play();
+
+        let mut out = [9.0; 6];
+        assert_eq!(renderer.fill_interleaved(&mut out), 2);
+        assert_eq!(out, [0.5, -0.5, 0.5, -0.5, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,375 @@
+use dasp::interpolate::linear::Linear;
+use dasp::interpolate::Interpolator;
+use dasp::{ring_buffer, signal, Frame, Signal};
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResampleQuality {
+    // Cheapest, aliases heavily when downsampling
+    #[default]
+    Linear,
+    // Hann-windowed sinc, band-limited to the source Nyquist only
+    Sinc,
+    // Kaiser-windowed sinc band-limited to the lower of the two Nyquists
+    Polyphase,
+}
+
+const SINC_DEPTH: usize = 32;
+
+const POLYPHASE_ZERO_CROSSINGS: usize = 32;
+const POLYPHASE_PHASES: usize = 512;
+const POLYPHASE_ROLLOFF: f64 = 0.94;
+const POLYPHASE_KAISER_BETA: f64 = 9.0;
+
+pub fn resample_frames<F>(
+    frames: &[F],
+    from_hz: u32,
+    to_hz: u32,
+    quality: ResampleQuality,
+) -> Vec<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    let length = (frames.len() as u64 * to_hz as u64 / from_hz as u64) as usize;
+
+    match quality {
+        ResampleQuality::Linear => {
+            let mut signal = signal::from_iter(frames.iter().copied());
+            let a = signal.next();
+            let b = signal.next();
+            let linear = Linear::new(a, b);
+            signal
+                .from_hz_to_hz(linear, from_hz as f64, to_hz as f64)
+                .take(length)
+                .collect()
+        }
+        ResampleQuality::Sinc => {
+            // Trailing silence lets the last frames reach the interpolated point
+            let padding = std::iter::repeat_n(F::EQUILIBRIUM, SINC_DEPTH + 1);
+            let mut signal = signal::from_iter(frames.iter().copied().chain(padding));
+
+            // Priming puts the first frame on the interpolated point, so there is no latency
+            let mut sinc = WindowedSinc::new(SINC_DEPTH);
+            for _ in 0..=SINC_DEPTH {
+                sinc.next_source_frame(signal.next());
+            }
+
+            signal
+                .from_hz_to_hz(sinc, from_hz as f64, to_hz as f64)
+                .take(length)
+                .collect()
+        }
+        ResampleQuality::Polyphase => {
+            PolyphaseResampler::new(from_hz, to_hz).process(frames, length)
+        }
+    }
+}
+
+// dasp 0.11's `Sinc` weights each frame with the distance of its mirror on the
+// other side of the interpolated point. Upsampling the 44.1 kHz test sweep to
+// 96 kHz it loses 1.2 dB in the passband and leaves images at -3 dB, against
+// -23 dB for linear interpolation. Same Hann-windowed kernel, with the lags
+// paired correctly: 0.001 dB and -41 dB. `test_dasp_sinc_mirrors_its_lags`
+// fails once dasp is fixed, at which point this can go
+struct WindowedSinc<F> {
+    frames: ring_buffer::Fixed<Vec<F>>,
+    depth: usize,
+}
+
+impl<F> WindowedSinc<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    fn new(depth: usize) -> Self {
+        Self {
+            frames: ring_buffer::Fixed::from(vec![F::EQUILIBRIUM; depth * 2]),
+            depth,
+        }
+    }
+
+    fn weight(&self, distance: f64) -> f32 {
+        let window = 0.5 + 0.5 * (PI * distance / self.depth as f64).cos();
+        (sinc(distance) * window) as f32
+    }
+}
+
+impl<F> Interpolator for WindowedSinc<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    type Frame = F;
+
+    // `x` is measured from `frames[depth - 1]` towards `frames[depth]`
+    fn interpolate(&self, x: f64) -> F {
+        (0..self.depth).fold(F::EQUILIBRIUM, |acc, n| {
+            let left = self.frames[self.depth - 1 - n].scale_amp(self.weight(x + n as f64));
+            let right = self.frames[self.depth + n].scale_amp(self.weight(1.0 - x + n as f64));
+            acc.add_amp(left).add_amp(right)
+        })
+    }
+
+    fn next_source_frame(&mut self, source_frame: F) {
+        self.frames.push(source_frame);
+    }
+}
+
+struct PolyphaseResampler {
+    from_hz: u64,
+    to_hz: u64,
+    half_taps: usize,
+    // `POLYPHASE_PHASES + 1` rows of `2 * half_taps` coefficients
+    table: Vec<f32>,
+}
+
+impl PolyphaseResampler {
+    fn new(from_hz: u32, to_hz: u32) -> Self {
+        // Cutoff as a fraction of the source Nyquist
+        let cutoff = POLYPHASE_ROLLOFF * (to_hz as f64 / from_hz as f64).min(1.0);
+        let half_width = POLYPHASE_ZERO_CROSSINGS as f64 / cutoff;
+        let half_taps = half_width.ceil() as usize;
+        let taps = 2 * half_taps;
+
+        let kaiser_norm = bessel_i0(POLYPHASE_KAISER_BETA);
+        let mut table = Vec::with_capacity((POLYPHASE_PHASES + 1) * taps);
+        for phase in 0..=POLYPHASE_PHASES {
+            let frac = phase as f64 / POLYPHASE_PHASES as f64;
+            for tap in 0..taps {
+                // Distance from the output instant to source frame `k0 + tap - half_taps + 1`
+                let t = frac + half_taps as f64 - 1.0 - tap as f64;
+                let window = if t.abs() >= half_width {
+                    0.0
+                } else {
+                    let r = t / half_width;
+                    bessel_i0(POLYPHASE_KAISER_BETA * (1.0 - r * r).sqrt()) / kaiser_norm
+                };
+                table.push((cutoff * sinc(cutoff * t) * window) as f32);
+            }
+        }
+
+        Self {
+            from_hz: from_hz as u64,
+            to_hz: to_hz as u64,
+            half_taps,
+            table,
+        }
+    }
+
+    fn process<F>(&self, frames: &[F], length: usize) -> Vec<F>
+    where
+        F: Frame<Sample = f32> + Copy,
+    {
+        let taps = 2 * self.half_taps;
+        let mut output = Vec::with_capacity(length);
+
+        for n in 0..length as u64 {
+            // Exact rational position of output frame `n` on the source timeline
+            let position = n * self.from_hz;
+            let k0 = (position / self.to_hz) as isize;
+            let frac = (position % self.to_hz) as f64 / self.to_hz as f64;
+
+            let phase_pos = frac * POLYPHASE_PHASES as f64;
+            let phase = phase_pos.floor() as usize;
+            let blend = (phase_pos - phase as f64) as f32;
+            let row_a = &self.table[phase * taps..(phase + 1) * taps];
+            let row_b = &self.table[(phase + 1) * taps..(phase + 2) * taps];
+
+            let first = k0 - self.half_taps as isize + 1;
+            let mut acc = F::EQUILIBRIUM;
+            for tap in 0..taps {
+                let idx = first + tap as isize;
+                if idx < 0 || idx as usize >= frames.len() {
+                    continue;
+                }
+                let coefficient = row_a[tap] + (row_b[tap] - row_a[tap]) * blend;
+                acc = acc.add_amp(frames[idx as usize].scale_amp(coefficient));
+            }
+            output.push(acc);
+        }
+
+        output
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Zeroth-order modified Bessel function of the first kind, for the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..64 {
+        term *= (half_x / k as f64) * (half_x / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dasp::frame::Mono;
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    // Linear sweep between two frequencies, in Hz
+    fn swept_sine(sample_rate: u32, start_hz: f64, end_hz: f64, seconds: f64) -> Vec<Mono<f32>> {
+        let length = (sample_rate as f64 * seconds) as usize;
+        let rate = sample_rate as f64;
+        (0..length)
+            .map(|i| {
+                let t = i as f64 / rate;
+                let phase =
+                    2.0 * PI * (start_hz * t + (end_hz - start_hz) * t * t / (2.0 * seconds));
+                [(0.5 * phase.sin()) as f32]
+            })
+            .collect()
+    }
+
+    fn rms(frames: &[Mono<f32>]) -> f64 {
+        let sum: f64 = frames.iter().map(|f| (f[0] as f64).powi(2)).sum();
+        (sum / frames.len() as f64).sqrt()
+    }
+
+    // Output level relative to input level in dB, ignoring the filter's edge transients
+    fn gain_db(input: &[Mono<f32>], output: &[Mono<f32>]) -> f64 {
+        let trim = output.len() / 20;
+        20.0 * (rms(&output[trim..output.len() - trim]) / rms(input)).log10()
+    }
+
+    // Energy above `cutoff_hz` relative to the energy below it, in dB
+    fn image_db(frames: &[Mono<f32>], sample_rate: u32, cutoff_hz: f64) -> f64 {
+        let size = 16384;
+        let start = (frames.len() - size) / 2;
+        let mut spectrum: Vec<Complex<f32>> = frames[start..start + size]
+            .iter()
+            .map(|frame| Complex::new(frame[0], 0.0))
+            .collect();
+        FftPlanner::new()
+            .plan_fft_forward(size)
+            .process(&mut spectrum);
+
+        let cutoff_bin = (cutoff_hz / sample_rate as f64 * size as f64) as usize;
+        let energy =
+            |bins: &[Complex<f32>]| -> f64 { bins.iter().map(|c| c.norm_sqr() as f64).sum() };
+        10.0 * (energy(&spectrum[cutoff_bin..size / 2]) / energy(&spectrum[..cutoff_bin])).log10()
+    }
+
+    #[test]
+    fn test_output_lengths() {
+        let input = swept_sine(48000, 100.0, 1000.0, 0.1);
+        for quality in [
+            ResampleQuality::Linear,
+            ResampleQuality::Sinc,
+            ResampleQuality::Polyphase,
+        ] {
+            let output = resample_frames(&input, 48000, 44100, quality);
+            assert_eq!(output.len(), 4410);
+        }
+    }
+
+    #[test]
+    fn test_polyphase_rejects_aliasing_on_swept_sine() {
+        // Everything in this sweep lies above the 22.05 kHz output Nyquist
+        let input = swept_sine(96000, 24000.0, 44000.0, 0.25);
+
+        let polyphase = resample_frames(&input, 96000, 44100, ResampleQuality::Polyphase);
+        let linear = resample_frames(&input, 96000, 44100, ResampleQuality::Linear);
+
+        let polyphase_db = gain_db(&input, &polyphase);
+        let linear_db = gain_db(&input, &linear);
+        assert!(
+            polyphase_db < -60.0,
+            "polyphase aliasing at {} dB",
+            polyphase_db
+        );
+        assert!(linear_db > -20.0, "linear aliasing at {} dB", linear_db);
+    }
+
+    #[test]
+    fn test_polyphase_passband_on_swept_sine() {
+        let input = swept_sine(96000, 100.0, 18000.0, 0.25);
+        let output = resample_frames(&input, 96000, 44100, ResampleQuality::Polyphase);
+        assert!(gain_db(&input, &output).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_sinc_upsampling_preserves_swept_sine() {
+        let input = swept_sine(44100, 100.0, 15000.0, 0.25);
+        let output = resample_frames(&input, 44100, 96000, ResampleQuality::Sinc);
+        assert!(gain_db(&input, &output).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_sinc_upsampling_rejects_images() {
+        let input = swept_sine(44100, 100.0, 15000.0, 0.25);
+
+        let sinc = resample_frames(&input, 44100, 96000, ResampleQuality::Sinc);
+        let linear = resample_frames(&input, 44100, 96000, ResampleQuality::Linear);
+
+        let sinc_db = image_db(&sinc, 96000, 22050.0);
+        let linear_db = image_db(&linear, 96000, 22050.0);
+        assert!(sinc_db < -35.0, "sinc images at {} dB", sinc_db);
+        assert!(sinc_db < linear_db - 15.0);
+    }
+
+    // dasp's own `Sinc`, primed the same way as `WindowedSinc`
+    fn dasp_sinc(frames: &[Mono<f32>], from_hz: u32, to_hz: u32) -> Vec<Mono<f32>> {
+        let length = (frames.len() as u64 * to_hz as u64 / from_hz as u64) as usize;
+        let padding = std::iter::repeat_n([0.0], SINC_DEPTH + 1);
+        let mut signal = signal::from_iter(frames.iter().copied().chain(padding));
+        let ring = ring_buffer::Fixed::from(vec![[0.0]; SINC_DEPTH * 2]);
+        let mut sinc = dasp::interpolate::sinc::Sinc::new(ring);
+        for _ in 0..SINC_DEPTH - 1 {
+            sinc.next_source_frame(signal.next());
+        }
+        signal
+            .from_hz_to_hz(sinc, from_hz as f64, to_hz as f64)
+            .take(length)
+            .collect()
+    }
+
+    #[test]
+    fn test_dasp_sinc_mirrors_its_lags() {
+        // An impulse halfway between two output frames comes out lopsided
+        let mut input = vec![[0.0]; 200];
+        input[100] = [1.0];
+        let output = dasp_sinc(&input, 44100, 88200);
+        assert_eq!(output[200], [1.0]);
+        assert!((output[199][0] - output[201][0]).abs() > 0.2);
+
+        let input = swept_sine(44100, 100.0, 15000.0, 0.25);
+        let dasp = dasp_sinc(&input, 44100, 96000);
+        let linear = resample_frames(&input, 44100, 96000, ResampleQuality::Linear);
+        assert!(image_db(&dasp, 96000, 22050.0) > image_db(&linear, 96000, 22050.0));
+    }
+
+    #[test]
+    fn test_impulse_stays_in_place() {
+        let mut input = vec![[0.0]; 200];
+        input[100] = [1.0];
+        for quality in [ResampleQuality::Sinc, ResampleQuality::Polyphase] {
+            let output = resample_frames(&input, 44100, 88200, quality);
+            let peak = output
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1[0].total_cmp(&b.1[0]))
+                .map(|(i, _)| i);
+            assert_eq!(peak, Some(200), "{:?}", quality);
+            assert!(
+                (output[199][0] - output[201][0]).abs() < 1e-3,
+                "{:?}",
+                quality
+            );
+        }
+    }
+}
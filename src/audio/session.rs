@@ -0,0 +1,284 @@
+// Session files store a graph as JSON so it can be rebuilt later:
+//
+// {
+//   "version": 1,
+//   "sample_rate": 44100,
+//   "sources": [
+//     { "kind": "file", "path": "drums.wav" },
+//     { "kind": "file", "path": "interview.wav", "streamed": true },
+//     { "kind": "embedded", "channels": 1, "sample_rate": 44100, "samples": [0.0, 0.5] }
+//   ],
+//   "nodes": [
+//     { "id": 1, "name": "drums", "source": 0, "region": [0, 88200], "position": 0,
+//       "effects": [{ "type": "gain", "factor": 0.5 },
+//                   { "type": "biquad", "kind": { "shape": "low_pass" }, "frequency": 800.0, "q": 0.7 }] }
+//   ],
+//   "edges": [
+//     { "parent": 1, "child": null, "offset": 0,
+//       "operation": { "type": "mix", "params": { "gain_db": -3.0, "pan": 0.0, "mute": false, "solo": false } } }
+//   ]
+// }
+//
+// `sources` is the material nodes play from, nodes split from the same material
+// share one entry. Clips loaded from disk are referenced by the path they were
+// loaded from, anything else is embedded as interleaved samples. Streamed files
+// are read from disk again on load instead of being decoded whole. A node plays
+// source frames `region` starting at timeline frame `position` and runs
+// `effects` over it in order, the key is left out for nodes without a chain.
+// Edges refer to nodes by `id`, a `null` child is the root, and are reconnected
+// in file order. Custom effects and edge operations cannot be stored, saving a
+// graph that uses them fails
+
+use super::audio_edge::{
+    AddOperation, AudioGraphEdge, CrossfadeCurve, CrossfadeOperation, DuckOperation, MaxOperation,
+    MinOperation, MixOperation, MixParams, MultiplyOperation, SubtractOperation,
+};
+use super::audio_effects::{AudioEffectChain, EffectFn, FadeIn, FadeOut, Gain, Invert};
+use super::audio_error::{AudioError, AudioResult};
+use super::biquad::{Biquad, BiquadKind};
+use dasp::Frame;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub sample_rate: u32,
+    pub sources: Vec<SessionSource>,
+    pub nodes: Vec<SessionNode>,
+    pub edges: Vec<SessionEdge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionSource {
+    File {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        streamed: bool,
+    },
+    Embedded {
+        channels: u32,
+        sample_rate: u32,
+        samples: Vec<f32>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionNode {
+    pub id: usize,
+    pub name: Option<String>,
+    pub source: usize,
+    pub region: (usize, usize),
+    pub position: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<SessionEffect>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionEdge {
+    pub parent: usize,
+    pub child: Option<usize>,
+    pub offset: i64,
+    pub operation: SessionOperation,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionOperation {
+    Add,
+    Subtract,
+    Mix {
+        params: MixParams,
+    },
+    Multiply,
+    Min,
+    Max,
+    Duck {
+        depth: f32,
+        attack: usize,
+        release: usize,
+    },
+    Crossfade {
+        length: usize,
+        curve: CrossfadeCurve,
+    },
+}
+
+impl SessionOperation {
+    pub fn into_edge<F>(self) -> AudioGraphEdge<F>
+    where
+        F: Frame<Sample = f32> + Default + Copy,
+    {
+        match self {
+            SessionOperation::Add => AudioGraphEdge::new(AddOperation, "AddOp"),
+            SessionOperation::Subtract => AudioGraphEdge::new(SubtractOperation, "SubOp"),
+            SessionOperation::Mix { params } => {
+                AudioGraphEdge::new(MixOperation::new(params), "MixOp")
+            }
+            SessionOperation::Multiply => AudioGraphEdge::new(MultiplyOperation, "MulOp"),
+            SessionOperation::Min => AudioGraphEdge::new(MinOperation, "MinOp"),
+            SessionOperation::Max => AudioGraphEdge::new(MaxOperation, "MaxOp"),
+            SessionOperation::Duck {
+                depth,
+                attack,
+                release,
+            } => AudioGraphEdge::new(
+                DuckOperation {
+                    depth,
+                    attack,
+                    release,
+                },
+                "DuckOp",
+            ),
+            SessionOperation::Crossfade { length, curve } => {
+                AudioGraphEdge::new(CrossfadeOperation { length, curve }, "XfadeOp")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEffect {
+    Gain {
+        factor: f32,
+    },
+    Invert,
+    FadeIn {
+        duration: usize,
+    },
+    FadeOut {
+        duration: usize,
+    },
+    Biquad {
+        kind: BiquadKind,
+        frequency: f32,
+        q: f32,
+    },
+}
+
+impl SessionEffect {
+    pub fn into_effect<F>(self) -> EffectFn<F>
+    where
+        F: Frame<Sample = f32> + Copy,
+    {
+        match self {
+            SessionEffect::Gain { factor } => Box::new(Gain { factor }),
+            SessionEffect::Invert => Box::new(Invert),
+            SessionEffect::FadeIn { duration } => Box::new(FadeIn { duration }),
+            SessionEffect::FadeOut { duration } => Box::new(FadeOut { duration }),
+            SessionEffect::Biquad { kind, frequency, q } => {
+                Box::new(Biquad::new(kind, frequency, q))
+            }
+        }
+    }
+
+    pub fn into_chain<F>(effects: Vec<SessionEffect>) -> AudioEffectChain<F>
+    where
+        F: Frame<Sample = f32> + Copy,
+    {
+        effects
+            .into_iter()
+            .fold(AudioEffectChain::new(), |chain, effect| {
+                chain.with_effect(effect.into_effect())
+            })
+    }
+}
+
+impl Session {
+    pub fn read<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        let session: Session = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if session.version != SESSION_VERSION {
+            return Err(AudioError::Session(format!(
+                "unsupported session version {}",
+                session.version
+            )));
+        }
+        Ok(session)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> AudioResult<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::test_util::temp_path;
+
+    #[test]
+    fn test_session_json_layout() {
+        let session = Session {
+            version: SESSION_VERSION,
+            sample_rate: 48000,
+            sources: vec![SessionSource::File {
+                path: PathBuf::from("drums.wav"),
+                streamed: false,
+            }],
+            nodes: vec![SessionNode {
+                id: 1,
+                name: Some("drums".to_string()),
+                source: 0,
+                region: (0, 100),
+                position: 10,
+                effects: vec![
+                    SessionEffect::FadeIn { duration: 8 },
+                    SessionEffect::Biquad {
+                        kind: BiquadKind::LowShelf { gain_db: -6.0 },
+                        frequency: 200.0,
+                        q: 0.7,
+                    },
+                ],
+            }],
+            edges: vec![SessionEdge {
+                parent: 1,
+                child: None,
+                offset: -5,
+                operation: SessionOperation::Crossfade {
+                    length: 4,
+                    curve: CrossfadeCurve::EqualPower,
+                },
+            }],
+        };
+
+        let json = serde_json::to_value(&session).unwrap();
+        assert_eq!(json["sources"][0]["kind"], "file");
+        assert!(json["sources"][0].get("streamed").is_none());
+        assert_eq!(json["nodes"][0]["region"], serde_json::json!([0, 100]));
+        assert_eq!(json["edges"][0]["child"], serde_json::Value::Null);
+        assert_eq!(json["edges"][0]["operation"]["type"], "crossfade");
+        assert_eq!(json["nodes"][0]["effects"][0]["type"], "fade_in");
+        assert_eq!(
+            json["nodes"][0]["effects"][1]["kind"],
+            serde_json::json!({ "shape": "low_shelf", "gain_db": -6.0 })
+        );
+
+        let path = temp_path("session_layout.json");
+        session.write(&path).unwrap();
+        let read_back = Session::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_back, session);
+    }
+
+    #[test]
+    fn test_read_rejects_other_versions() {
+        let path = temp_path("session_version.json");
+        std::fs::write(
+            &path,
+            r#"{"version": 99, "sample_rate": 44100, "sources": [], "nodes": [], "edges": []}"#,
+        )
+        .unwrap();
+        let result = Session::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(AudioError::Session(_))));
+    }
+}
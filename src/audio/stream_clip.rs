@@ -0,0 +1,464 @@
+use super::audio_clip::{AudioClipTrait, DEFAULT_SAMPLE_RATE};
+use super::audio_error::{AudioError, AudioResult};
+use super::resample::{resample_frames, ResampleQuality};
+use dasp::frame::Frame;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+// Frames decoded past the end of a read, so playback moving forward finds the
+// next blocks already in memory
+pub const DEFAULT_READ_AHEAD: usize = 1 << 16;
+
+type Decoder = hound::WavReader<BufReader<File>>;
+
+// Decoded frames `start..start + frames.len()` of the file. The decoder is
+// dropped when a read fails and the file opened again for the next one
+struct ReadCache<F> {
+    decoder: Option<Decoder>,
+    start: usize,
+    frames: Vec<F>,
+}
+
+// WAV file decoded from disk as it is read. Only the last read and the
+// read-ahead after it are held in memory, so hours of material can back a node.
+// Anything that needs every frame as one slice, an edit or `get_frames_ref`,
+// loads the whole file once and the clip is in memory from then on
+pub struct StreamClip<F> {
+    path: Option<PathBuf>,
+    sample_rate: u32,
+    length: usize,
+    read_ahead: usize,
+    cache: Option<Mutex<ReadCache<F>>>,
+    loaded: OnceLock<Vec<F>>,
+}
+
+impl<F> StreamClip<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    // Reads the header only, the file's channel count has to match `F`
+    pub fn open<P: AsRef<Path>>(path: P) -> AudioResult<Self> {
+        let decoder = Decoder::open(&path).map_err(decode_error)?;
+        let spec = decoder.spec();
+        if spec.channels as usize != F::CHANNELS {
+            return Err(AudioError::UnsupportedChannelCount(spec.channels as u32));
+        }
+        if spec.sample_format == hound::SampleFormat::Float && spec.bits_per_sample != 32 {
+            return Err(AudioError::Decode(format!(
+                "unsupported float sample size: {} bits",
+                spec.bits_per_sample
+            )));
+        }
+        Ok(Self {
+            path: Some(path.as_ref().to_path_buf()),
+            sample_rate: spec.sample_rate,
+            length: decoder.duration() as usize,
+            read_ahead: DEFAULT_READ_AHEAD,
+            cache: Some(Mutex::new(ReadCache {
+                decoder: Some(decoder),
+                start: 0,
+                frames: Vec::new(),
+            })),
+            loaded: OnceLock::new(),
+        })
+    }
+
+    pub fn with_read_ahead(mut self, frames: usize) -> Self {
+        self.read_ahead = frames;
+        self
+    }
+
+    // File the frames are read from, None once the clip has been edited
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Whether every frame has been decoded into memory
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.get().is_some()
+    }
+
+    fn from_frames(frames: Vec<F>, sample_rate: u32) -> Self {
+        Self {
+            path: None,
+            sample_rate,
+            length: frames.len(),
+            read_ahead: DEFAULT_READ_AHEAD,
+            cache: None,
+            loaded: OnceLock::from(frames),
+        }
+    }
+
+    // Decodes every frame into memory. Nothing is kept from a failed decode,
+    // the next load reads the file again
+    pub fn load(&self) -> AudioResult<&[F]> {
+        if let Some(frames) = self.loaded.get() {
+            return Ok(frames);
+        }
+        let mut frames = vec![F::EQUILIBRIUM; self.length];
+        self.read_uncached(0, &mut frames)?;
+        Ok(self.loaded.get_or_init(|| frames))
+    }
+
+    fn load_mut(&mut self) -> AudioResult<&mut Vec<F>> {
+        self.load()?;
+        // Edited frames no longer match the file
+        self.path = None;
+        Ok(self.loaded.get_mut().unwrap())
+    }
+
+    // Trait methods that cannot fail leave the clip as it is when the file
+    // cannot be read
+    fn edit(&mut self, f: impl FnOnce(&mut Vec<F>)) {
+        match self.load_mut() {
+            Ok(frames) => f(frames),
+            Err(err) => log::warn!("failed to load {:?}: {}", self.path, err),
+        }
+    }
+
+    // Decodes straight into `out`, leaving the cache as it is
+    fn read_uncached(&self, start: usize, out: &mut [F]) -> AudioResult<usize> {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+        let mut cache = cache.lock()?;
+        let count = self.length.saturating_sub(start).min(out.len());
+        self.decode_with(&mut cache.decoder, start, &mut out[..count])?;
+        Ok(count)
+    }
+
+    // A failed decode can leave the file somewhere other than where the decoder
+    // counts itself, so later seeks would land on the wrong frames
+    fn decode_with(
+        &self,
+        decoder: &mut Option<Decoder>,
+        start: usize,
+        out: &mut [F],
+    ) -> AudioResult<()> {
+        let active = match decoder {
+            Some(active) => active,
+            None => {
+                let path = self.path.as_ref().ok_or_else(|| {
+                    AudioError::Decode("clip no longer reads from a file".to_string())
+                })?;
+                decoder.insert(Decoder::open(path).map_err(decode_error)?)
+            }
+        };
+        let result = decode(active, start, out);
+        if result.is_err() {
+            *decoder = None;
+        }
+        result
+    }
+}
+
+impl<F> AudioClipTrait for StreamClip<F>
+where
+    F: Frame<Sample = f32> + Copy,
+{
+    type S = F;
+
+    // A clip with nothing on disk lives in memory from the start
+    fn default() -> Self {
+        Self::from_frames(Vec::new(), DEFAULT_SAMPLE_RATE)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_rate(capacity, DEFAULT_SAMPLE_RATE)
+    }
+
+    fn with_capacity_and_rate(capacity: usize, sample_rate: u32) -> Self {
+        Self::from_frames(vec![F::EQUILIBRIUM; capacity], sample_rate)
+    }
+
+    // No frames at all when the file cannot be read, see `load`
+    fn get_frames_ref(&self) -> &[Self::S] {
+        self.load().unwrap_or_else(|err| {
+            log::warn!("failed to load {:?}: {}", self.path, err);
+            &[]
+        })
+    }
+
+    fn get_frames_mut(&mut self) -> &mut [Self::S] {
+        if let Err(err) = self.load_mut() {
+            log::warn!("failed to load {:?}: {}", self.path, err);
+            return &mut [];
+        }
+        self.loaded.get_mut().unwrap()
+    }
+
+    fn get_frame(&self, idx: usize) -> Option<Self::S> {
+        let mut frame = [F::EQUILIBRIUM];
+        match self.read_frames(idx, &mut frame) {
+            Ok(count) => (count == 1).then_some(frame[0]),
+            Err(err) => {
+                log::warn!("failed to read {:?}: {}", self.path, err);
+                None
+            }
+        }
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn get_length(&self) -> usize {
+        match self.loaded.get() {
+            Some(frames) => frames.len(),
+            None => self.length,
+        }
+    }
+
+    fn set_frame(&mut self, idx: usize, val: Self::S) -> AudioResult<()> {
+        let frames = self.load_mut()?;
+        let len = frames.len();
+        let frame = frames
+            .get_mut(idx)
+            .ok_or(AudioError::FrameOutOfBounds { idx, len })?;
+        *frame = val;
+        Ok(())
+    }
+
+    fn resample_with_quality(&self, sample_rate: u32, quality: ResampleQuality) -> Self {
+        let frames = resample_frames(
+            self.get_frames_ref(),
+            self.sample_rate,
+            sample_rate,
+            quality,
+        );
+        Self::from_frames(frames, sample_rate)
+    }
+
+    fn resize_frames(&mut self, new_size: usize, value: Self::S) {
+        self.edit(|frames| frames.resize(new_size, value));
+    }
+
+    fn add_padding_left(&mut self, padding_frames: usize) {
+        self.edit(|frames| {
+            frames.splice(0..0, std::iter::repeat_n(F::EQUILIBRIUM, padding_frames));
+        });
+    }
+
+    // Silence needs nothing from the file
+    fn reset_clip(&mut self) {
+        let frames = vec![F::EQUILIBRIUM; self.get_length()];
+        self.loaded = OnceLock::from(frames);
+        self.path = None;
+    }
+
+    // Served from the read-ahead window, which a miss decodes again from `start`
+    // on in place. A failed decode leaves the window empty
+    fn read_frames(&self, start: usize, out: &mut [Self::S]) -> AudioResult<usize> {
+        if let Some(frames) = self.loaded.get() {
+            return Ok(copy_frames(frames, start, out));
+        }
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+        let count = self.length.saturating_sub(start).min(out.len());
+        if count == 0 {
+            return Ok(0);
+        }
+        let mut cache = cache.lock()?;
+        let cached = start >= cache.start && start + count <= cache.start + cache.frames.len();
+        if !cached {
+            let window = (count + self.read_ahead).min(self.length - start);
+            let ReadCache {
+                decoder, frames, ..
+            } = &mut *cache;
+            frames.resize(window, F::EQUILIBRIUM);
+            if let Err(err) = self.decode_with(decoder, start, frames) {
+                frames.clear();
+                return Err(err);
+            }
+            cache.start = start;
+        }
+        let offset = start - cache.start;
+        out[..count].copy_from_slice(&cache.frames[offset..offset + count]);
+        Ok(count)
+    }
+}
+
+fn copy_frames<F: Copy>(frames: &[F], start: usize, out: &mut [F]) -> usize {
+    if start >= frames.len() {
+        return 0;
+    }
+    let count = (frames.len() - start).min(out.len());
+    out[..count].copy_from_slice(&frames[start..start + count]);
+    count
+}
+
+// Fills `out` with frames from `start` on, scaling integer samples to -1..1
+fn decode<F>(decoder: &mut Decoder, start: usize, out: &mut [F]) -> AudioResult<()>
+where
+    F: Frame<Sample = f32>,
+{
+    decoder.seek(start as u32)?;
+    let spec = decoder.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => fill_frames(decoder.samples::<f32>(), out),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            let samples = decoder
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale));
+            fill_frames(samples, out)
+        }
+    }
+}
+
+// Builds each frame of `out` straight from the decoder's samples
+fn fill_frames<F, I>(samples: I, out: &mut [F]) -> AudioResult<()>
+where
+    F: Frame<Sample = f32>,
+    I: Iterator<Item = Result<f32, hound::Error>>,
+{
+    let mut failure = None;
+    let filled = {
+        let mut samples =
+            samples.map_while(|sample| sample.map_err(|err| failure = Some(err)).ok());
+        out.iter_mut()
+            .all(|frame| match F::from_samples(&mut samples) {
+                Some(decoded) => {
+                    *frame = decoded;
+                    true
+                }
+                None => false,
+            })
+    };
+    match failure {
+        Some(err) => Err(decode_error(err)),
+        None if !filled => Err(AudioError::Decode("file ended early".to_string())),
+        None => Ok(()),
+    }
+}
+
+fn decode_error(err: hound::Error) -> AudioError {
+    match err {
+        hound::Error::IoError(err) => AudioError::Io(err),
+        err => AudioError::Decode(err.to_string()),
+    }
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::test_util::{temp_path, truncate_file, write_int_wav};
+    use dasp::frame::{Mono, Stereo};
+
+    #[test]
+    fn test_reads_match_the_loaded_file() {
+        let path = temp_path("stream_reads.wav");
+        let samples: Vec<i32> = (0..1000).map(|i| (i * 31 % 2000) - 1000).collect();
+        write_int_wav(&path, 1, 16, &samples);
+
+        let clip = StreamClip::<Mono<f32>>::open(&path)
+            .unwrap()
+            .with_read_ahead(64);
+        assert_eq!(clip.get_length(), 1000);
+        assert_eq!(clip.get_sample_rate(), 44100);
+
+        // Forward, backward and past the end, each a hit or a refill
+        let mut out = [[0.0]; 100];
+        for start in [0, 50, 120, 900, 10, 950] {
+            let count = clip.read_frames(start, &mut out).unwrap();
+            assert_eq!(count, 100.min(1000 - start));
+            for (i, frame) in out[..count].iter().enumerate() {
+                assert_eq!(frame[0], samples[start + i] as f32 / 32768.0);
+            }
+        }
+        assert_eq!(clip.read_frames(1000, &mut out).unwrap(), 0);
+        assert!(!clip.is_loaded());
+
+        assert_eq!(clip.get_frame(999), Some([samples[999] as f32 / 32768.0]));
+        assert_eq!(clip.get_frame(1000), None);
+
+        let frames = clip.get_frames_ref();
+        assert_eq!(frames.len(), 1000);
+        assert_eq!(frames[321][0], samples[321] as f32 / 32768.0);
+        assert!(clip.is_loaded());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_edits_detach_from_the_file() {
+        let path = temp_path("stream_edits.wav");
+        write_int_wav(&path, 2, 24, &[1 << 22, -(1 << 22), 0, 1 << 21]);
+
+        let mut clip = StreamClip::<Stereo<f32>>::open(&path).unwrap();
+        assert_eq!(clip.get_frame(0), Some([0.5, -0.5]));
+        assert_eq!(clip.get_path(), Some(path.as_path()));
+
+        clip.add_padding_left(2);
+        assert_eq!(clip.get_path(), None);
+        assert_eq!(clip.get_length(), 4);
+        assert_eq!(clip.get_frame(3), Some([0.0, 0.25]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_decodes_are_not_the_end() {
+        let path = temp_path("stream_truncated.wav");
+        let samples: Vec<i32> = (0..1000).collect();
+        write_int_wav(&path, 1, 16, &samples);
+
+        let clip = StreamClip::<Mono<f32>>::open(&path)
+            .unwrap()
+            .with_read_ahead(0);
+        // The header still claims 1000 frames
+        truncate_file(&path, 1000);
+
+        let mut out = [[0.0]; 100];
+        assert_eq!(clip.read_frames(0, &mut out).unwrap(), 100);
+        assert!(clip.read_frames(800, &mut out).is_err());
+        assert_eq!(clip.read_frames(1000, &mut out).unwrap(), 0);
+        assert_eq!(clip.get_frame(900), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_loads_are_not_kept() {
+        let path = temp_path("stream_reload.wav");
+        let samples: Vec<i32> = (0..1000).collect();
+        write_int_wav(&path, 1, 16, &samples);
+        let contents = std::fs::read(&path).unwrap();
+
+        let clip = StreamClip::<Mono<f32>>::open(&path).unwrap();
+        truncate_file(&path, 1000);
+        assert!(clip.load().is_err());
+        assert!(clip.get_frames_ref().is_empty());
+        assert!(!clip.is_loaded());
+
+        // Once the file reads again, so does the clip
+        std::fs::write(&path, &contents).unwrap();
+        let frames = clip.load().unwrap();
+        assert_eq!(frames.len(), 1000);
+        assert_eq!(frames[999][0], 999.0 / 32768.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_checks_the_file() {
+        let path = temp_path("stream_stereo.wav");
+        write_int_wav(&path, 2, 16, &[0, 0]);
+        assert!(matches!(
+            StreamClip::<Mono<f32>>::open(&path),
+            Err(AudioError::UnsupportedChannelCount(2))
+        ));
+        std::fs::write(&path, b"not a wav file").unwrap();
+        assert!(matches!(
+            StreamClip::<Mono<f32>>::open(&path),
+            Err(AudioError::Decode(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            StreamClip::<Mono<f32>>::open(temp_path("stream_missing.wav")),
+            Err(AudioError::Io(_))
+        ));
+    }
+}
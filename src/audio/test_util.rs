@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+// File in the temp directory that no other test run shares
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("audio_general_{}_{}", std::process::id(), name))
+}
+
+// Interleaved 32 bit float samples
+pub fn write_float_wav(path: &Path, channels: u16, sample_rate: u32, samples: &[f32]) {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    write_samples(path, spec, samples);
+}
+
+// Interleaved integer samples of `bits` bits at 44.1kHz
+pub fn write_int_wav(path: &Path, channels: u16, bits: u16, samples: &[i32]) {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate: 44100,
+        bits_per_sample: bits,
+        sample_format: hound::SampleFormat::Int,
+    };
+    write_samples(path, spec, samples);
+}
+
+// Cuts the file short behind the back of anything that already opened it
+pub fn truncate_file(path: &Path, len: u64) {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_len(len)
+        .unwrap();
+}
+
+fn write_samples<S: hound::Sample + Copy>(path: &Path, spec: hound::WavSpec, samples: &[S]) {
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for &sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+}
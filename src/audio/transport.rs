@@ -0,0 +1,227 @@
+use super::audio_error::{AudioError, AudioResult};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+impl TransportState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TransportState::Playing,
+            2 => TransportState::Paused,
+            _ => TransportState::Stopped,
+        }
+    }
+}
+
+const NO_LOOP: u64 = 0;
+
+// Play state and play head shared between control threads and the render side.
+// Everything is atomic so a UI thread never waits on the audio callback
+pub struct Transport {
+    state: AtomicU8,
+    position: AtomicUsize,
+    // Loop start and end packed as two u32 halves so both change in one store
+    loop_region: AtomicU64,
+    sample_rate: u32,
+}
+
+impl Transport {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            state: AtomicU8::new(TransportState::Stopped as u8),
+            position: AtomicUsize::new(0),
+            loop_region: AtomicU64::new(NO_LOOP),
+            sample_rate,
+        }
+    }
+
+    pub fn get_state(&self) -> TransportState {
+        TransportState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.get_state() == TransportState::Playing
+    }
+
+    pub fn play(&self) {
+        self.state
+            .store(TransportState::Playing as u8, Ordering::Release);
+    }
+
+    pub fn pause(&self) {
+        self.state
+            .store(TransportState::Paused as u8, Ordering::Release);
+    }
+
+    // Stops and rewinds to the start of the timeline
+    pub fn stop(&self) {
+        self.state
+            .store(TransportState::Stopped as u8, Ordering::Release);
+        self.position.store(0, Ordering::Release);
+    }
+
+    pub fn get_position(&self) -> usize {
+        self.position.load(Ordering::Acquire)
+    }
+
+    pub fn get_position_seconds(&self) -> f64 {
+        self.get_position() as f64 / self.sample_rate as f64
+    }
+
+    pub fn seek(&self, frame: usize) {
+        self.position.store(frame, Ordering::Release);
+    }
+
+    pub fn seek_seconds(&self, seconds: f64) {
+        let frame = (seconds.max(0.0) * self.sample_rate as f64).round() as usize;
+        self.seek(frame);
+    }
+
+    pub fn get_loop_region(&self) -> Option<(usize, usize)> {
+        match self.loop_region.load(Ordering::Acquire) {
+            NO_LOOP => None,
+            packed => Some(((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)),
+        }
+    }
+
+    // Frames in `start..end` repeat until the loop is cleared
+    pub fn set_loop_region(&self, start: usize, end: usize) -> AudioResult<()> {
+        if start >= end || end > u32::MAX as usize {
+            return Err(AudioError::InvalidLoopRegion { start, end });
+        }
+        let packed = ((start as u64) << 32) | end as u64;
+        self.loop_region.store(packed, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn set_loop_region_seconds(&self, start: f64, end: f64) -> AudioResult<()> {
+        let to_frames = |seconds: f64| (seconds.max(0.0) * self.sample_rate as f64).round();
+        self.set_loop_region(to_frames(start) as usize, to_frames(end) as usize)
+    }
+
+    pub fn clear_loop_region(&self) {
+        self.loop_region.store(NO_LOOP, Ordering::Release);
+    }
+
+    // Next contiguous run of at most `max` frames from the play head, cut at the
+    // loop end or at `clip_len`, as (start, count)
+    pub(crate) fn next_segment(&self, max: usize, clip_len: usize) -> (usize, usize) {
+        let position = self.get_position();
+        let end = match self.get_loop_region() {
+            Some((_, loop_end)) if position < loop_end => loop_end.min(clip_len),
+            _ => clip_len,
+        };
+        (position, end.saturating_sub(position).min(max))
+    }
+
+    // Moves the play head past a segment from `next_segment`, jumping back to the
+    // loop start at the loop end. A seek made meanwhile wins over the advance
+    pub(crate) fn finish_segment(&self, start: usize, count: usize, clip_len: usize) {
+        let mut next = start + count;
+        if let Some((loop_start, loop_end)) = self.get_loop_region() {
+            if start < loop_end && next >= loop_end.min(clip_len) {
+                next = loop_start;
+            }
+        }
+        let _ = self
+            .position
+            .compare_exchange(start, next, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    // Hands `write` up to `len` frames from the play head while playing and
+    // returns how many were real. Loop regions can split a block into several runs
+    pub(crate) fn read_block<F>(
+        &self,
+        frames: &[F],
+        len: usize,
+        mut write: impl FnMut(usize, &F),
+    ) -> usize {
+        if !self.is_playing() {
+            return 0;
+        }
+        let mut written = 0;
+        while written < len {
+            let (start, count) = self.next_segment(len - written, frames.len());
+            if count == 0 {
+                break;
+            }
+            for (i, frame) in frames[start..start + count].iter().enumerate() {
+                write(written + i, frame);
+            }
+            self.finish_segment(start, count, frames.len());
+            written += count;
+        }
+        written
+    }
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_pause_stop() {
+        let transport = Transport::new(44100);
+        assert_eq!(transport.get_state(), TransportState::Stopped);
+
+        transport.play();
+        transport.seek(100);
+        assert!(transport.is_playing());
+
+        transport.pause();
+        assert_eq!(transport.get_state(), TransportState::Paused);
+        assert_eq!(transport.get_position(), 100);
+
+        transport.stop();
+        assert_eq!(transport.get_state(), TransportState::Stopped);
+        assert_eq!(transport.get_position(), 0);
+    }
+
+    #[test]
+    fn test_seek_seconds() {
+        let transport = Transport::new(48000);
+        transport.seek_seconds(1.5);
+        assert_eq!(transport.get_position(), 72000);
+        assert_eq!(transport.get_position_seconds(), 1.5);
+
+        transport.seek_seconds(-1.0);
+        assert_eq!(transport.get_position(), 0);
+    }
+
+    #[test]
+    fn test_loop_region() {
+        let transport = Transport::new(44100);
+        assert!(matches!(
+            transport.set_loop_region(10, 10),
+            Err(AudioError::InvalidLoopRegion { .. })
+        ));
+
+        transport.set_loop_region(2, 6).unwrap();
+        assert_eq!(transport.get_loop_region(), Some((2, 6)));
+
+        transport.seek(4);
+        let (start, count) = transport.next_segment(10, 100);
+        assert_eq!((start, count), (4, 2));
+        transport.finish_segment(start, count, 100);
+        assert_eq!(transport.get_position(), 2);
+
+        transport.clear_loop_region();
+        assert_eq!(transport.next_segment(10, 100), (2, 10));
+    }
+
+    #[test]
+    fn test_seek_wins_over_concurrent_advance() {
+        let transport = Transport::new(44100);
+        let (start, count) = transport.next_segment(64, 1000);
+        transport.seek(500);
+        transport.finish_segment(start, count, 1000);
+        assert_eq!(transport.get_position(), 500);
+    }
+}
@@ -1,6 +1,5 @@
 use rustfft::{num_complex::Complex, FftPlanner};
 
-
 pub fn get_max_amplitude_freq(samples: &Vec<f32>, reduced_slice_size: usize) -> f32 {
     let fft = FftPlanner::new().plan_fft_forward(reduced_slice_size);
     let num_slices = samples.len() / reduced_slice_size;
@@ -29,23 +28,69 @@ pub fn get_max_amplitude_freq(samples: &Vec<f32>, reduced_slice_size: usize) ->
     max_amplitude
 }
 
-use std::env;
-use std::fs::File;
-use std::path::PathBuf;
-
-
-pub fn from_file() -> Option<(Vec<f32>, u32, u32)> {
-    let mut path = PathBuf::from(env::current_dir().unwrap());
-    path.push("src/audio/");
-    path.push("test.flac");
+use super::audio_clip::AudioClipEnum;
+use super::audio_error::AudioResult;
+use std::path::Path;
 
-    let file = File::open(&path).unwrap();
-    let mut reader = audrey::Reader::new(file).unwrap();
+// Reads interleaved samples from any format audrey supports (FLAC, WAV, OGG Vorbis, ALAC)
+// The format is detected from the file header rather than the extension
+pub fn from_file<P: AsRef<Path>>(path: P) -> AudioResult<(Vec<f32>, u32, u32)> {
+    let mut reader = audrey::open(path)?;
     let desc = reader.description();
-    let sample_rate = desc.sample_rate() as u32;
+    let sample_rate = desc.sample_rate();
     let channels = desc.channel_count();
 
-    let samples: Vec<f32> = reader.samples::<f32>().filter_map(Result::ok).collect();
+    let samples = reader.samples::<f32>().collect::<Result<Vec<f32>, _>>()?;
+
+    Ok((samples, sample_rate, channels))
+}
+
+pub fn load_clip<P: AsRef<Path>>(path: P) -> AudioResult<AudioClipEnum> {
+    let (samples, sample_rate, channels) = from_file(path)?;
+    AudioClipEnum::from_samples(samples, sample_rate, channels)
+}
+
+// ! ---------  Tests ---------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_clip::AudioClipTrait;
+    use crate::audio::audio_error::AudioError;
+    use crate::audio::test_util::{temp_path, write_float_wav};
 
-    Some((samples, sample_rate, channels))
+    #[test]
+    fn test_load_clip_keeps_channel_layout() {
+        let path = temp_path("quad.wav");
+        let samples: Vec<f32> = (0..16).map(|i| i as f32 / 16.0).collect();
+        write_float_wav(&path, 4, 48000, &samples);
+
+        let clip = load_clip(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(clip.get_channel_count(), 4);
+        match clip {
+            AudioClipEnum::Quad(clip) => {
+                assert_eq!(clip.get_sample_rate(), 48000);
+                assert_eq!(clip.get_length(), 4);
+                assert_eq!(clip.get_frames_ref()[1], [0.25, 0.3125, 0.375, 0.4375]);
+            }
+            _ => panic!("expected a quad clip"),
+        }
+    }
+
+    #[test]
+    fn test_load_clip_missing_file() {
+        let result = load_clip(temp_path("missing.flac"));
+        assert!(matches!(result, Err(AudioError::Io(_))));
+    }
+
+    #[test]
+    fn test_load_clip_unsupported_format() {
+        let path = temp_path("garbage.ogg");
+        std::fs::write(&path, vec![0x5a; 1024]).unwrap();
+        let result = load_clip(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(AudioError::Decode(_))));
+    }
 }